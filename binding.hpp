@@ -1,3 +1,5 @@
+#include <rcutils/error_handling.h>
+#include <rcutils/logging.h>
 #include <rcutils/strdup.h>
 #include <rmw/event.h>
 #include <rmw/rmw.h>
@@ -12,4 +14,12 @@
 #include <rmw/validate_node_name.h>
 #include <rmw/validate_namespace.h>
 #include <rosidl_runtime_c/message_type_support_struct.h>
+#include <rosidl_runtime_c/primitives_sequence_functions.h>
+#include <rosidl_runtime_c/service_type_support_struct.h>
+#include <rosidl_runtime_c/string_functions.h>
+#include <rosidl_runtime_c/u16string_functions.h>
+#include <rosidl_typesupport_introspection_c/field_types.h>
+#include <rosidl_typesupport_introspection_c/message_introspection.h>
+#include <rosidl_typesupport_introspection_c/service_introspection.h>
+#include "cpp/cpp_members.h"
 #include "cpp/type_support.h"
@@ -14,8 +14,12 @@ fn main() {
         .clang_arg(format!("-I{include_path}/rcutils/"))
         .clang_arg(format!("-I{include_path}/rosidl_runtime_c/"))
         .clang_arg(format!("-I{include_path}/rosidl_typesupport_interface/"))
+        .clang_arg(format!("-I{include_path}/rosidl_typesupport_introspection_c/"))
         .allowlist_function("rs_.*")
         .allowlist_function("rcutils_.*")
+        .allowlist_function("rosidl_runtime_c__.*")
+        .allowlist_function("get_message_typesupport_handle")
+        .allowlist_function("get_service_typesupport_handle")
         .allowlist_function("rmw_topic_endpoint_info_.*")
         .allowlist_function("rmw_get_zero_initialized_.*")
         .allowlist_function("rmw_names_and_types_init")
@@ -39,7 +43,11 @@ fn main() {
         .expect("Couldn't write bindings!");
 
     // Compile cpp
-    cc::Build::new()
+    // With `rust-cdr`, messages are serialized in Rust and only the accessors of the
+    // C++ standard containers are needed, so fastcdr and FastRTPS are not linked
+    let rust_cdr = std::env::var("CARGO_FEATURE_RUST_CDR").is_ok();
+    let mut build = cc::Build::new();
+    build
         .cpp(true)
         .warnings(true)
         .flag("-std=c++17")
@@ -47,19 +55,32 @@ fn main() {
         .include(format!("{include_path}/rcutils/"))
         .include(format!("{include_path}/fastcdr"))
         .include(format!("{include_path}/rosidl_runtime_c"))
+        .include(format!("{include_path}/rosidl_runtime_cpp"))
         .include(format!("{include_path}/rosidl_typesupport_fastrtps_c"))
         .include(format!("{include_path}/rosidl_typesupport_fastrtps_cpp"))
         .include(format!("{include_path}/rosidl_typesupport_interface"))
-        .file("cpp/type_support.cpp")
-        .compile("type_support");
+        .include(format!("{include_path}/rosidl_typesupport_introspection_c"))
+        .include(format!("{include_path}/rosidl_typesupport_introspection_cpp"));
+    if rust_cdr {
+        build.file("cpp/cpp_members.cpp");
+    } else {
+        build
+            .file("cpp/type_support.cpp")
+            .file("cpp/introspection.cpp");
+    }
+    build.compile("type_support");
 
     // Link libraries
     println!("cargo:rustc-link-search=native=/usr/local/lib");
     println!("cargo:rustc-link-search=native={prefix_path}/lib/");
     println!("cargo:rustc-link-lib=dylib=rmw");
     println!("cargo:rustc-link-lib=dylib=rcutils");
-    println!("cargo:rustc-link-lib=dylib=fastcdr");
     println!("cargo:rustc-link-lib=dylib=rosidl_runtime_c");
-    println!("cargo:rustc-link-lib=dylib=rosidl_typesupport_fastrtps_c");
-    println!("cargo:rustc-link-lib=dylib=rosidl_typesupport_fastrtps_cpp");
+    if !rust_cdr {
+        println!("cargo:rustc-link-lib=dylib=fastcdr");
+        println!("cargo:rustc-link-lib=dylib=rosidl_typesupport_fastrtps_c");
+        println!("cargo:rustc-link-lib=dylib=rosidl_typesupport_fastrtps_cpp");
+        println!("cargo:rustc-link-lib=dylib=rosidl_typesupport_introspection_c");
+        println!("cargo:rustc-link-lib=dylib=rosidl_typesupport_introspection_cpp");
+    }
 }
@@ -0,0 +1,83 @@
+#include <cstddef>
+#include <cstdint>
+#include <new>
+#include <string>
+#include <vector>
+#include "cpp_members.h"
+
+// Retrieve the characters and length of a std::string
+const char *rs_cpp_string_data(const void *string, size_t *size)
+{
+    auto value = static_cast<const std::string *>(string);
+    *size = value->size();
+    return value->data();
+}
+
+// Assign characters to a std::string
+bool rs_cpp_string_assign(void *string, const char *data, size_t size)
+{
+    try
+    {
+        static_cast<std::string *>(string)->assign(data, size);
+    }
+    catch (const std::bad_alloc &)
+    {
+        return false;
+    }
+    return true;
+}
+
+// Retrieve the characters and length of a std::u16string
+const uint16_t *rs_cpp_u16string_data(const void *string, size_t *size)
+{
+    auto value = static_cast<const std::u16string *>(string);
+    *size = value->size();
+    return reinterpret_cast<const uint16_t *>(value->data());
+}
+
+// Assign characters to a std::u16string
+bool rs_cpp_u16string_assign(void *string, const uint16_t *data, size_t size)
+{
+    try
+    {
+        static_cast<std::u16string *>(string)->assign(
+            reinterpret_cast<const char16_t *>(data), size);
+    }
+    catch (const std::bad_alloc &)
+    {
+        return false;
+    }
+    return true;
+}
+
+// Retrieve the length of a std::vector<bool>
+size_t rs_cpp_bool_vector_size(const void *vector)
+{
+    return static_cast<const std::vector<bool> *>(vector)->size();
+}
+
+// Retrieve an element of a std::vector<bool>
+bool rs_cpp_bool_vector_get(const void *vector, size_t index)
+{
+    return (*static_cast<const std::vector<bool> *>(vector))[index];
+}
+
+// Resize a std::vector<bool>
+bool rs_cpp_bool_vector_resize(void *vector, size_t size)
+{
+    try
+    {
+        static_cast<std::vector<bool> *>(vector)->resize(size);
+    }
+    catch (const std::bad_alloc &)
+    {
+        return false;
+    }
+    return true;
+}
+
+// Set an element of a std::vector<bool>
+void rs_cpp_bool_vector_set(void *vector, size_t index, bool value)
+{
+    (*static_cast<std::vector<bool> *>(vector))[index] = value;
+}
@@ -0,0 +1,25 @@
+#ifdef __cplusplus
+extern "C"
+{
+#endif
+
+    // Retrieve the characters and length of a std::string
+    const char *rs_cpp_string_data(const void *string, size_t *size);
+    // Assign characters to a std::string
+    bool rs_cpp_string_assign(void *string, const char *data, size_t size);
+    // Retrieve the characters and length of a std::u16string
+    const uint16_t *rs_cpp_u16string_data(const void *string, size_t *size);
+    // Assign characters to a std::u16string
+    bool rs_cpp_u16string_assign(void *string, const uint16_t *data, size_t size);
+    // Retrieve the length of a std::vector<bool>
+    size_t rs_cpp_bool_vector_size(const void *vector);
+    // Retrieve an element of a std::vector<bool>
+    bool rs_cpp_bool_vector_get(const void *vector, size_t index);
+    // Resize a std::vector<bool>
+    bool rs_cpp_bool_vector_resize(void *vector, size_t size);
+    // Set an element of a std::vector<bool>
+    void rs_cpp_bool_vector_set(void *vector, size_t index, bool value);
+
+#ifdef __cplusplus
+}
+#endif
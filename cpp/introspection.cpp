@@ -0,0 +1,856 @@
+#include <fastcdr/Cdr.h>
+#include <rosidl_runtime_c/primitives_sequence.h>
+#include <rosidl_runtime_c/primitives_sequence_functions.h>
+#include <rosidl_runtime_c/string.h>
+#include <rosidl_runtime_c/string_functions.h>
+#include <rosidl_runtime_c/u16string.h>
+#include <rosidl_runtime_c/u16string_functions.h>
+#include <rosidl_typesupport_introspection_c/field_types.h>
+#include <rosidl_typesupport_introspection_c/message_introspection.h>
+#include <rosidl_typesupport_introspection_cpp/message_introspection.hpp>
+#include <cstdint>
+#include <string>
+#include <vector>
+#include "introspection.hpp"
+
+#define ROS_TYPE(NAME) rosidl_typesupport_introspection_c__ROS_TYPE_##NAME
+
+using eprosima::fastcdr::Cdr;
+
+namespace
+{
+    // Layout shared by every rosidl_runtime_c sequence
+    struct CSequence
+    {
+        void *data;
+        size_t size;
+        size_t capacity;
+    };
+
+    // Returns true if the member is a bounded or unbounded sequence, false for fixed arrays
+    template <typename MemberT>
+    bool is_sequence(const MemberT *member)
+    {
+        return member->array_size_ == 0 || member->is_upper_bound_;
+    }
+
+    // Returns true if a sequence is longer than its bound
+    template <typename MemberT>
+    bool exceeds_bound(const MemberT *member, size_t size)
+    {
+        return member->is_upper_bound_ && size > member->array_size_;
+    }
+
+    // Returns true if a string is longer than its bound
+    template <typename MemberT>
+    bool exceeds_string_bound(const MemberT *member, size_t size)
+    {
+        return member->string_upper_bound_ != 0 && size > member->string_upper_bound_;
+    }
+
+    // Serialize a UTF-16 string as rosidl_typesupport_fastrtps does: its length, then 4-byte characters
+    template <typename CharT>
+    void write_wstring(Cdr &ser, const CharT *data, size_t size)
+    {
+        ser << static_cast<uint32_t>(size);
+        for (size_t i = 0; i < size; i++)
+        {
+            ser << static_cast<wchar_t>(data[i]);
+        }
+    }
+
+    // Deserialize a UTF-16 string written by write_wstring
+    void read_wstring(Cdr &deser, std::u16string &value)
+    {
+        uint32_t size;
+        deser >> size;
+        value.clear();
+        for (uint32_t i = 0; i < size; i++)
+        {
+            wchar_t c;
+            deser >> c;
+            value.push_back(static_cast<char16_t>(c));
+        }
+    }
+
+    // CTraits struct: Access to the fields of messages generated for C
+    struct CTraits
+    {
+        using Members = rosidl_typesupport_introspection_c__MessageMembers;
+        using Member = rosidl_typesupport_introspection_c__MessageMember;
+
+        static const Members *nested(const Member *member)
+        {
+            return static_cast<const Members *>(member->members_->data);
+        }
+
+        static bool serialize_string(Cdr &ser, const Member *member, const void *field)
+        {
+            auto string = static_cast<const rosidl_runtime_c__String *>(field);
+            if (exceeds_string_bound(member, string->size))
+            {
+                return false;
+            }
+            ser << (string->data ? string->data : "");
+            return true;
+        }
+
+        static bool deserialize_string(Cdr &deser, const Member *member, void *field)
+        {
+            std::string value;
+            deser >> value;
+            return !exceeds_string_bound(member, value.size()) &&
+                   rosidl_runtime_c__String__assignn(
+                       static_cast<rosidl_runtime_c__String *>(field), value.c_str(), value.size());
+        }
+
+        static bool serialize_wstring(Cdr &ser, const Member *member, const void *field)
+        {
+            auto string = static_cast<const rosidl_runtime_c__U16String *>(field);
+            if (exceeds_string_bound(member, string->size))
+            {
+                return false;
+            }
+            write_wstring(ser, string->data, string->size);
+            return true;
+        }
+
+        static bool deserialize_wstring(Cdr &deser, const Member *member, void *field)
+        {
+            std::u16string value;
+            read_wstring(deser, value);
+            return !exceeds_string_bound(member, value.size()) &&
+                   rosidl_runtime_c__U16String__assignn(
+                       static_cast<rosidl_runtime_c__U16String *>(field),
+                       reinterpret_cast<const uint16_t *>(value.data()), value.size());
+        }
+
+        // C arrays are accessed through their layout, as older generators only
+        // provide accessor functions for arrays of messages
+        static bool has_array_functions(const Member *)
+        {
+            return true;
+        }
+
+        static size_t element_size(const Member *member)
+        {
+            switch (member->type_id_)
+            {
+            case ROS_TYPE(FLOAT):
+                return sizeof(float);
+            case ROS_TYPE(DOUBLE):
+                return sizeof(double);
+            case ROS_TYPE(LONG_DOUBLE):
+                return sizeof(long double);
+            case ROS_TYPE(CHAR):
+            case ROS_TYPE(OCTET):
+            case ROS_TYPE(UINT8):
+            case ROS_TYPE(INT8):
+                return sizeof(uint8_t);
+            case ROS_TYPE(BOOLEAN):
+                return sizeof(bool);
+            case ROS_TYPE(WCHAR):
+            case ROS_TYPE(UINT16):
+            case ROS_TYPE(INT16):
+                return sizeof(uint16_t);
+            case ROS_TYPE(UINT32):
+            case ROS_TYPE(INT32):
+                return sizeof(uint32_t);
+            case ROS_TYPE(UINT64):
+            case ROS_TYPE(INT64):
+                return sizeof(uint64_t);
+            case ROS_TYPE(STRING):
+                return sizeof(rosidl_runtime_c__String);
+            case ROS_TYPE(WSTRING):
+                return sizeof(rosidl_runtime_c__U16String);
+            case ROS_TYPE(MESSAGE):
+                return nested(member)->size_of_;
+            default:
+                return 0;
+            }
+        }
+
+        static size_t array_size(const Member *member, const void *field)
+        {
+            if (is_sequence(member))
+            {
+                return static_cast<const CSequence *>(field)->size;
+            }
+            return member->array_size_;
+        }
+
+        static const void *array_element(const Member *member, const void *field, size_t index)
+        {
+            const void *data =
+                is_sequence(member) ? static_cast<const CSequence *>(field)->data : field;
+            return static_cast<const uint8_t *>(data) + index * element_size(member);
+        }
+
+        static void *array_element(const Member *member, void *field, size_t index)
+        {
+            return const_cast<void *>(
+                array_element(member, static_cast<const void *>(field), index));
+        }
+
+        static bool resize_sequence(const Member *member, void *field, size_t size)
+        {
+            auto sequence = static_cast<CSequence *>(field);
+            // Elements are initialized up to the capacity, so shrinking needs no reallocation
+            if (size <= sequence->capacity)
+            {
+                sequence->size = size;
+                return true;
+            }
+#define RESIZE_SEQUENCE(TYPE_ID, NAME)                                        \
+    case ROS_TYPE(TYPE_ID):                                                   \
+    {                                                                         \
+        auto typed = static_cast<rosidl_runtime_c__##NAME##__Sequence *>(field); \
+        rosidl_runtime_c__##NAME##__Sequence__fini(typed);                    \
+        return rosidl_runtime_c__##NAME##__Sequence__init(typed, size);       \
+    }
+            switch (member->type_id_)
+            {
+                RESIZE_SEQUENCE(FLOAT, float)
+                RESIZE_SEQUENCE(DOUBLE, double)
+                RESIZE_SEQUENCE(LONG_DOUBLE, long_double)
+                RESIZE_SEQUENCE(CHAR, char)
+                RESIZE_SEQUENCE(WCHAR, wchar)
+                RESIZE_SEQUENCE(BOOLEAN, boolean)
+                RESIZE_SEQUENCE(OCTET, octet)
+                RESIZE_SEQUENCE(UINT8, uint8)
+                RESIZE_SEQUENCE(INT8, int8)
+                RESIZE_SEQUENCE(UINT16, uint16)
+                RESIZE_SEQUENCE(INT16, int16)
+                RESIZE_SEQUENCE(UINT32, uint32)
+                RESIZE_SEQUENCE(INT32, int32)
+                RESIZE_SEQUENCE(UINT64, uint64)
+                RESIZE_SEQUENCE(INT64, int64)
+                RESIZE_SEQUENCE(STRING, String)
+                RESIZE_SEQUENCE(WSTRING, U16String)
+            case ROS_TYPE(MESSAGE):
+                return member->resize_function && member->resize_function(field, size);
+            default:
+                return false;
+            }
+#undef RESIZE_SEQUENCE
+        }
+
+        // bool sequences have the same layout as the other C sequences
+        static bool is_bool_vector(const Member *)
+        {
+            return false;
+        }
+
+        static bool serialize_bool_vector(Cdr &, const Member *, const void *)
+        {
+            return false;
+        }
+
+        static bool deserialize_bool_vector(Cdr &, const Member *, void *)
+        {
+            return false;
+        }
+    };
+
+    // CppTraits struct: Access to the fields of messages generated for C++
+    struct CppTraits
+    {
+        using Members = rosidl_typesupport_introspection_cpp::MessageMembers;
+        using Member = rosidl_typesupport_introspection_cpp::MessageMember;
+
+        static const Members *nested(const Member *member)
+        {
+            return static_cast<const Members *>(member->members_->data);
+        }
+
+        static bool serialize_string(Cdr &ser, const Member *member, const void *field)
+        {
+            auto &string = *static_cast<const std::string *>(field);
+            if (exceeds_string_bound(member, string.size()))
+            {
+                return false;
+            }
+            ser << string;
+            return true;
+        }
+
+        static bool deserialize_string(Cdr &deser, const Member *member, void *field)
+        {
+            auto &string = *static_cast<std::string *>(field);
+            deser >> string;
+            return !exceeds_string_bound(member, string.size());
+        }
+
+        static bool serialize_wstring(Cdr &ser, const Member *member, const void *field)
+        {
+            auto &string = *static_cast<const std::u16string *>(field);
+            if (exceeds_string_bound(member, string.size()))
+            {
+                return false;
+            }
+            write_wstring(ser, string.data(), string.size());
+            return true;
+        }
+
+        static bool deserialize_wstring(Cdr &deser, const Member *member, void *field)
+        {
+            auto &string = *static_cast<std::u16string *>(field);
+            read_wstring(deser, string);
+            return !exceeds_string_bound(member, string.size());
+        }
+
+        static bool has_array_functions(const Member *member)
+        {
+            return member->size_function && member->get_const_function && member->get_function;
+        }
+
+        static size_t array_size(const Member *member, const void *field)
+        {
+            return member->size_function(field);
+        }
+
+        static const void *array_element(const Member *member, const void *field, size_t index)
+        {
+            return member->get_const_function(field, index);
+        }
+
+        static void *array_element(const Member *member, void *field, size_t index)
+        {
+            return member->get_function(field, index);
+        }
+
+        static bool resize_sequence(const Member *member, void *field, size_t size)
+        {
+            if (!member->resize_function)
+            {
+                return false;
+            }
+            member->resize_function(field, size);
+            return true;
+        }
+
+        // std::vector<bool> has no addressable elements, so it is accessed directly
+        static bool is_bool_vector(const Member *member)
+        {
+            return member->type_id_ == ROS_TYPE(BOOLEAN) && is_sequence(member);
+        }
+
+        static bool serialize_bool_vector(Cdr &ser, const Member *member, const void *field)
+        {
+            auto &vector = *static_cast<const std::vector<bool> *>(field);
+            if (exceeds_bound(member, vector.size()))
+            {
+                return false;
+            }
+            ser << static_cast<uint32_t>(vector.size());
+            for (bool value : vector)
+            {
+                ser << value;
+            }
+            return true;
+        }
+
+        static bool deserialize_bool_vector(Cdr &deser, const Member *member, void *field)
+        {
+            auto &vector = *static_cast<std::vector<bool> *>(field);
+            uint32_t size;
+            deser >> size;
+            if (exceeds_bound(member, size))
+            {
+                return false;
+            }
+            vector.resize(size);
+            for (uint32_t i = 0; i < size; i++)
+            {
+                bool value;
+                deser >> value;
+                vector[i] = value;
+            }
+            return true;
+        }
+    };
+
+    template <typename Traits>
+    bool serialize_message(Cdr &ser, const typename Traits::Members *members, const void *ros_message);
+
+    template <typename Traits>
+    bool deserialize_message(Cdr &deser, const typename Traits::Members *members, void *ros_message);
+
+    // Serialize a single value of a member
+    template <typename Traits>
+    bool serialize_value(Cdr &ser, const typename Traits::Member *member, const void *value)
+    {
+        switch (member->type_id_)
+        {
+        case ROS_TYPE(FLOAT):
+            ser << *static_cast<const float *>(value);
+            return true;
+        case ROS_TYPE(DOUBLE):
+            ser << *static_cast<const double *>(value);
+            return true;
+        case ROS_TYPE(LONG_DOUBLE):
+            ser << *static_cast<const long double *>(value);
+            return true;
+        case ROS_TYPE(CHAR):
+        case ROS_TYPE(OCTET):
+        case ROS_TYPE(UINT8):
+            ser << *static_cast<const uint8_t *>(value);
+            return true;
+        case ROS_TYPE(INT8):
+            ser << *static_cast<const int8_t *>(value);
+            return true;
+        case ROS_TYPE(BOOLEAN):
+            ser << *static_cast<const bool *>(value);
+            return true;
+        case ROS_TYPE(WCHAR):
+            ser << static_cast<wchar_t>(*static_cast<const uint16_t *>(value));
+            return true;
+        case ROS_TYPE(UINT16):
+            ser << *static_cast<const uint16_t *>(value);
+            return true;
+        case ROS_TYPE(INT16):
+            ser << *static_cast<const int16_t *>(value);
+            return true;
+        case ROS_TYPE(UINT32):
+            ser << *static_cast<const uint32_t *>(value);
+            return true;
+        case ROS_TYPE(INT32):
+            ser << *static_cast<const int32_t *>(value);
+            return true;
+        case ROS_TYPE(UINT64):
+            ser << *static_cast<const uint64_t *>(value);
+            return true;
+        case ROS_TYPE(INT64):
+            ser << *static_cast<const int64_t *>(value);
+            return true;
+        case ROS_TYPE(STRING):
+            return Traits::serialize_string(ser, member, value);
+        case ROS_TYPE(WSTRING):
+            return Traits::serialize_wstring(ser, member, value);
+        case ROS_TYPE(MESSAGE):
+            return serialize_message<Traits>(ser, Traits::nested(member), value);
+        default:
+            return false;
+        }
+    }
+
+    // Deserialize a single value of a member
+    template <typename Traits>
+    bool deserialize_value(Cdr &deser, const typename Traits::Member *member, void *value)
+    {
+        switch (member->type_id_)
+        {
+        case ROS_TYPE(FLOAT):
+            deser >> *static_cast<float *>(value);
+            return true;
+        case ROS_TYPE(DOUBLE):
+            deser >> *static_cast<double *>(value);
+            return true;
+        case ROS_TYPE(LONG_DOUBLE):
+            deser >> *static_cast<long double *>(value);
+            return true;
+        case ROS_TYPE(CHAR):
+        case ROS_TYPE(OCTET):
+        case ROS_TYPE(UINT8):
+            deser >> *static_cast<uint8_t *>(value);
+            return true;
+        case ROS_TYPE(INT8):
+            deser >> *static_cast<int8_t *>(value);
+            return true;
+        case ROS_TYPE(BOOLEAN):
+            deser >> *static_cast<bool *>(value);
+            return true;
+        case ROS_TYPE(WCHAR):
+        {
+            wchar_t c;
+            deser >> c;
+            *static_cast<uint16_t *>(value) = static_cast<uint16_t>(c);
+            return true;
+        }
+        case ROS_TYPE(UINT16):
+            deser >> *static_cast<uint16_t *>(value);
+            return true;
+        case ROS_TYPE(INT16):
+            deser >> *static_cast<int16_t *>(value);
+            return true;
+        case ROS_TYPE(UINT32):
+            deser >> *static_cast<uint32_t *>(value);
+            return true;
+        case ROS_TYPE(INT32):
+            deser >> *static_cast<int32_t *>(value);
+            return true;
+        case ROS_TYPE(UINT64):
+            deser >> *static_cast<uint64_t *>(value);
+            return true;
+        case ROS_TYPE(INT64):
+            deser >> *static_cast<int64_t *>(value);
+            return true;
+        case ROS_TYPE(STRING):
+            return Traits::deserialize_string(deser, member, value);
+        case ROS_TYPE(WSTRING):
+            return Traits::deserialize_wstring(deser, member, value);
+        case ROS_TYPE(MESSAGE):
+            return deserialize_message<Traits>(deser, Traits::nested(member), value);
+        default:
+            return false;
+        }
+    }
+
+    // Serialize a member: a single value, a fixed array or a sequence prefixed by its length
+    template <typename Traits>
+    bool serialize_member(Cdr &ser, const typename Traits::Member *member, const void *field)
+    {
+        if (!member->is_array_)
+        {
+            return serialize_value<Traits>(ser, member, field);
+        }
+        if (Traits::is_bool_vector(member))
+        {
+            return Traits::serialize_bool_vector(ser, member, field);
+        }
+        if (!Traits::has_array_functions(member))
+        {
+            return false;
+        }
+        size_t size = Traits::array_size(member, field);
+        if (is_sequence(member))
+        {
+            if (exceeds_bound(member, size))
+            {
+                return false;
+            }
+            ser << static_cast<uint32_t>(size);
+        }
+        for (size_t i = 0; i < size; i++)
+        {
+            if (!serialize_value<Traits>(ser, member, Traits::array_element(member, field, i)))
+            {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    // Deserialize a member, resizing sequences to the received length
+    template <typename Traits>
+    bool deserialize_member(Cdr &deser, const typename Traits::Member *member, void *field)
+    {
+        if (!member->is_array_)
+        {
+            return deserialize_value<Traits>(deser, member, field);
+        }
+        if (Traits::is_bool_vector(member))
+        {
+            return Traits::deserialize_bool_vector(deser, member, field);
+        }
+        if (!Traits::has_array_functions(member))
+        {
+            return false;
+        }
+        size_t size = member->array_size_;
+        if (is_sequence(member))
+        {
+            uint32_t length;
+            deser >> length;
+            size = length;
+            if (exceeds_bound(member, size) || !Traits::resize_sequence(member, field, size))
+            {
+                return false;
+            }
+        }
+        for (size_t i = 0; i < size; i++)
+        {
+            if (!deserialize_value<Traits>(deser, member, Traits::array_element(member, field, i)))
+            {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    template <typename Traits>
+    bool serialize_message(Cdr &ser, const typename Traits::Members *members, const void *ros_message)
+    {
+        auto message = static_cast<const uint8_t *>(ros_message);
+        for (uint32_t i = 0; i < members->member_count_; i++)
+        {
+            const auto *member = &members->members_[i];
+            if (!serialize_member<Traits>(ser, member, message + member->offset_))
+            {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    template <typename Traits>
+    bool deserialize_message(Cdr &deser, const typename Traits::Members *members, void *ros_message)
+    {
+        auto message = static_cast<uint8_t *>(ros_message);
+        for (uint32_t i = 0; i < members->member_count_; i++)
+        {
+            const auto *member = &members->members_[i];
+            if (!deserialize_member<Traits>(deser, member, message + member->offset_))
+            {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    // Size and alignment of a primitive member in CDR, 0 for strings and messages
+    template <typename MemberT>
+    size_t primitive_size(const MemberT *member, size_t &alignment)
+    {
+        switch (member->type_id_)
+        {
+        case ROS_TYPE(CHAR):
+        case ROS_TYPE(OCTET):
+        case ROS_TYPE(UINT8):
+        case ROS_TYPE(INT8):
+        case ROS_TYPE(BOOLEAN):
+            alignment = 1;
+            return 1;
+        case ROS_TYPE(UINT16):
+        case ROS_TYPE(INT16):
+            alignment = 2;
+            return 2;
+        case ROS_TYPE(FLOAT):
+        case ROS_TYPE(WCHAR):
+        case ROS_TYPE(UINT32):
+        case ROS_TYPE(INT32):
+            alignment = 4;
+            return 4;
+        case ROS_TYPE(DOUBLE):
+        case ROS_TYPE(UINT64):
+        case ROS_TYPE(INT64):
+            alignment = 8;
+            return 8;
+        case ROS_TYPE(LONG_DOUBLE):
+            alignment = 8;
+            return 16;
+        default:
+            alignment = 0;
+            return 0;
+        }
+    }
+
+    // Pad an offset from the encapsulation to the alignment, saturating at SIZE_MAX
+    size_t align_offset(size_t offset, size_t alignment)
+    {
+        if (offset > SIZE_MAX - (alignment - 1))
+        {
+            return SIZE_MAX;
+        }
+        return (offset + alignment - 1) / alignment * alignment;
+    }
+
+    // Add `count` values of `size` bytes to an offset. A size that does not fit in size_t
+    // saturates the offset and clears `full_bounded`, as the message cannot be preallocated.
+    void add_size(size_t &offset, size_t count, size_t size, bool &full_bounded)
+    {
+        if (size != 0 && count > (SIZE_MAX - offset) / size)
+        {
+            offset = SIZE_MAX;
+            full_bounded = false;
+            return;
+        }
+        offset += count * size;
+    }
+
+    template <typename Traits>
+    void add_max_serialized_size(
+        const typename Traits::Members *members,
+        size_t unbounded_length,
+        size_t &offset,
+        bool &full_bounded);
+
+    // Add the largest serialized size of a single non-primitive value of a member.
+    // Unbounded strings count `unbounded_length` characters, or else count as empty
+    // and clear `full_bounded`.
+    template <typename Traits>
+    void add_max_value_size(
+        const typename Traits::Member *member,
+        size_t unbounded_length,
+        size_t &offset,
+        bool &full_bounded)
+    {
+        size_t bound = member->string_upper_bound_;
+        if (bound == 0)
+        {
+            bound = unbounded_length;
+        }
+        switch (member->type_id_)
+        {
+        case ROS_TYPE(STRING):
+            full_bounded = full_bounded && bound != 0;
+            offset = align_offset(offset, 4);
+            add_size(offset, 1, 4, full_bounded);
+            add_size(offset, bound, 1, full_bounded);
+            add_size(offset, 1, 1, full_bounded);
+            break;
+        case ROS_TYPE(WSTRING):
+            full_bounded = full_bounded && bound != 0;
+            offset = align_offset(offset, 4);
+            add_size(offset, 1, 4, full_bounded);
+            add_size(offset, bound, 4, full_bounded);
+            break;
+        case ROS_TYPE(MESSAGE):
+            add_max_serialized_size<Traits>(
+                Traits::nested(member), unbounded_length, offset, full_bounded);
+            break;
+        default:
+            full_bounded = false;
+            break;
+        }
+    }
+
+    // Add the largest serialized size of a member, as rosidl_typesupport_fastrtps computes it.
+    // Unbounded sequences count `unbounded_length` elements, or else count as empty
+    // and clear `full_bounded`.
+    template <typename Traits>
+    void add_max_member_size(
+        const typename Traits::Member *member,
+        size_t unbounded_length,
+        size_t &offset,
+        bool &full_bounded)
+    {
+        size_t count = 1;
+        if (member->is_array_)
+        {
+            count = member->array_size_;
+            if (is_sequence(member))
+            {
+                offset = align_offset(offset, 4);
+                add_size(offset, 1, 4, full_bounded);
+                if (!member->is_upper_bound_)
+                {
+                    full_bounded = full_bounded && unbounded_length != 0;
+                    count = unbounded_length;
+                }
+            }
+        }
+        size_t alignment;
+        size_t size = primitive_size(member, alignment);
+        if (size != 0)
+        {
+            // The size of every primitive is a multiple of its alignment
+            if (count != 0)
+            {
+                offset = align_offset(offset, alignment);
+                add_size(offset, count, size, full_bounded);
+            }
+            return;
+        }
+        // Stop counting once the size overflowed
+        for (size_t i = 0; i < count; i++)
+        {
+            if (offset == SIZE_MAX)
+            {
+                full_bounded = false;
+                break;
+            }
+            add_max_value_size<Traits>(member, unbounded_length, offset, full_bounded);
+        }
+    }
+
+    template <typename Traits>
+    void add_max_serialized_size(
+        const typename Traits::Members *members,
+        size_t unbounded_length,
+        size_t &offset,
+        bool &full_bounded)
+    {
+        for (uint32_t i = 0; i < members->member_count_; i++)
+        {
+            add_max_member_size<Traits>(
+                &members->members_[i], unbounded_length, offset, full_bounded);
+        }
+    }
+}
+
+// Serialize a ROS message described by C or C++ introspection members, without encapsulation
+bool introspection_serialize(
+    Cdr &ser,
+    const void *members,
+    bool cpp,
+    const void *ros_message)
+{
+    if (cpp)
+    {
+        return serialize_message<CppTraits>(
+            ser, static_cast<const CppTraits::Members *>(members), ros_message);
+    }
+    return serialize_message<CTraits>(
+        ser, static_cast<const CTraits::Members *>(members), ros_message);
+}
+
+// Deserialize a ROS message described by C or C++ introspection members, without encapsulation
+bool introspection_deserialize(
+    Cdr &deser,
+    const void *members,
+    bool cpp,
+    void *ros_message)
+{
+    if (cpp)
+    {
+        return deserialize_message<CppTraits>(
+            deser, static_cast<const CppTraits::Members *>(members), ros_message);
+    }
+    return deserialize_message<CTraits>(
+        deser, static_cast<const CTraits::Members *>(members), ros_message);
+}
+
+// Estimate the serialized size of a ROS message from the size of its structure.
+// The contents of strings and sequences are not counted, the caller grows the buffer as needed.
+size_t introspection_get_size_hint(const void *members, bool cpp)
+{
+    if (cpp)
+    {
+        return static_cast<const CppTraits::Members *>(members)->size_of_;
+    }
+    return static_cast<const CTraits::Members *>(members)->size_of_;
+}
+
+// Calculate the largest serialized size of a ROS message described by introspection members,
+// without encapsulation. Unbounded strings and sequences count `unbounded_length` elements;
+// `full_bounded` is cleared if the type has some and `unbounded_length` is 0.
+size_t introspection_get_max_serialized_size(
+    const void *members,
+    bool cpp,
+    size_t unbounded_length,
+    bool &full_bounded)
+{
+    size_t offset = 0;
+    if (cpp)
+    {
+        add_max_serialized_size<CppTraits>(
+            static_cast<const CppTraits::Members *>(members), unbounded_length, offset, full_bounded);
+    }
+    else
+    {
+        add_max_serialized_size<CTraits>(
+            static_cast<const CTraits::Members *>(members), unbounded_length, offset, full_bounded);
+    }
+    return offset;
+}
+
+// Retrieve the message name from introspection members
+const char *introspection_get_message_name(const void *members, bool cpp)
+{
+    if (cpp)
+    {
+        return static_cast<const CppTraits::Members *>(members)->message_name_;
+    }
+    return static_cast<const CTraits::Members *>(members)->message_name_;
+}
+
+// Retrieve the message namespace from introspection members
+const char *introspection_get_message_namespace(const void *members, bool cpp)
+{
+    if (cpp)
+    {
+        return static_cast<const CppTraits::Members *>(members)->message_namespace_;
+    }
+    return static_cast<const CTraits::Members *>(members)->message_namespace_;
+}
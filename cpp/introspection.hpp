@@ -0,0 +1,40 @@
+#pragma once
+
+#include <fastcdr/Cdr.h>
+
+// Serialize a ROS message described by C or C++ introspection members, without encapsulation
+bool introspection_serialize(
+    eprosima::fastcdr::Cdr &ser,
+    const void *members,
+    bool cpp,
+    const void *ros_message);
+
+// Deserialize a ROS message described by C or C++ introspection members, without encapsulation
+bool introspection_deserialize(
+    eprosima::fastcdr::Cdr &deser,
+    const void *members,
+    bool cpp,
+    void *ros_message);
+
+// Estimate the serialized size of a ROS message described by introspection members
+size_t introspection_get_size_hint(const void *members, bool cpp);
+
+// Calculate the largest serialized size of a ROS message described by introspection members
+size_t introspection_get_max_serialized_size(
+    const void *members,
+    bool cpp,
+    size_t unbounded_length,
+    bool &full_bounded);
+
+// Retrieve the message name from introspection members
+const char *introspection_get_message_name(const void *members, bool cpp);
+
+// Calculate the largest serialized size of a ROS message described by introspection members
+size_t introspection_get_max_serialized_size(
+    const void *members,
+    bool cpp,
+    size_t unbounded_length,
+    bool &full_bounded);
+
+// Retrieve the message namespace from introspection members
+const char *introspection_get_message_namespace(const void *members, bool cpp);
@@ -1,24 +1,78 @@
 #include <fastcdr/Cdr.h>
 #include <fastcdr/config.h>
 #include <fastcdr/FastBuffer.h>
+#include <fastcdr/exceptions/Exception.h>
+#include <fastcdr/exceptions/NotEnoughMemoryException.h>
+#include <rcutils/error_handling.h>
 #include <rosidl_typesupport_fastrtps_c/identifier.h>
 #include <rosidl_typesupport_fastrtps_cpp/identifier.hpp>
 #include <rosidl_typesupport_fastrtps_cpp/message_type_support.h>
 #include <rosidl_typesupport_fastrtps_cpp/service_type_support.h>
+#include <rosidl_typesupport_introspection_c/identifier.h>
+#include <rosidl_typesupport_introspection_c/service_introspection.h>
+#include <rosidl_typesupport_introspection_cpp/identifier.hpp>
+#include <rosidl_typesupport_introspection_cpp/service_introspection.hpp>
 #include <rmw/serialized_message.h>
+#include <cstdint>
+#include <utility>
+#include "introspection.hpp"
 #include "type_support.h"
 
-// Retrieve message type support callbacks for FastRTPS
+// Find the handle of the first type support able to (de)serialize the type.
+// FastRTPS type supports are preferred, introspection ones are the fallback.
+template <typename TypeSupportT, typename GetHandle>
+static const TypeSupportT *find_type_support(
+    const TypeSupportT *type_support,
+    GetHandle get_handle,
+    rs_type_support_kind_t *kind,
+    bool introspection_only = false)
+{
+    const std::pair<const char *, rs_type_support_kind_t> candidates[] = {
+        {rosidl_typesupport_fastrtps_c__identifier, RS_TYPE_SUPPORT_FASTRTPS},
+        {rosidl_typesupport_fastrtps_cpp::typesupport_identifier, RS_TYPE_SUPPORT_FASTRTPS},
+        {rosidl_typesupport_introspection_c__identifier, RS_TYPE_SUPPORT_INTROSPECTION_C},
+        {rosidl_typesupport_introspection_cpp::typesupport_identifier,
+         RS_TYPE_SUPPORT_INTROSPECTION_CPP},
+    };
+    for (const auto &[identifier, candidate_kind] : candidates)
+    {
+        if (introspection_only && candidate_kind == RS_TYPE_SUPPORT_FASTRTPS)
+        {
+            continue;
+        }
+        const TypeSupportT *handle = get_handle(type_support, identifier);
+        if (handle)
+        {
+            *kind = candidate_kind;
+            return handle;
+        }
+        // A missing type support sets the error state, but another one may be found
+        rcutils_reset_error();
+    }
+    return NULL;
+}
+
+// Retrieve message type support data: FastRTPS callbacks or introspection members
 const void *rs_get_message_type_support_callbacks(
-    const rosidl_message_type_support_t *type_support_)
+    const rosidl_message_type_support_t *type_support_,
+    rs_type_support_kind_t *kind)
 {
     const rosidl_message_type_support_t *type_support =
-        get_message_typesupport_handle(type_support_, rosidl_typesupport_fastrtps_c__identifier);
+        find_type_support(type_support_, get_message_typesupport_handle, kind);
     if (!type_support)
     {
-        type_support = get_message_typesupport_handle(
-            type_support_, rosidl_typesupport_fastrtps_cpp::typesupport_identifier);
+        return NULL;
     }
+    return type_support->data;
+}
+
+// Retrieve the introspection members of a message, which describe every field
+const void *rs_get_message_introspection_callbacks(
+    const rosidl_message_type_support_t *type_support_,
+    rs_type_support_kind_t *kind)
+{
+    const rosidl_message_type_support_t *type_support =
+        find_type_support(type_support_, get_message_typesupport_handle, kind, true);
     if (!type_support)
     {
         return NULL;
@@ -26,132 +80,294 @@ const void *rs_get_message_type_support_callbacks(
     return type_support->data;
 }
 
-// Retrieve service type support callbacks for FastRTPS
-const service_type_support_callbacks_t *rs_get_service_type_support_callbacks(
-    const rosidl_service_type_support_t *type_support_)
+// Retrieve the type support data of the request or response of a service
+static const void *get_service_member_callbacks(
+    const rosidl_service_type_support_t *type_support_,
+    rs_type_support_kind_t *kind,
+    bool request)
 {
     const rosidl_service_type_support_t *type_support =
-        get_service_typesupport_handle(type_support_, rosidl_typesupport_fastrtps_c__identifier);
+        find_type_support(type_support_, get_service_typesupport_handle, kind);
     if (!type_support)
     {
-        type_support = get_service_typesupport_handle(
-            type_support_, rosidl_typesupport_fastrtps_cpp::typesupport_identifier);
+        return NULL;
     }
-    if (!type_support)
+    switch (*kind)
     {
-        return NULL;
+    case RS_TYPE_SUPPORT_FASTRTPS:
+    {
+        auto callbacks = static_cast<const service_type_support_callbacks_t *>(type_support->data);
+        return request ? callbacks->request_members_->data : callbacks->response_members_->data;
+    }
+    case RS_TYPE_SUPPORT_INTROSPECTION_C:
+    {
+        auto members = static_cast<const rosidl_typesupport_introspection_c__ServiceMembers *>(
+            type_support->data);
+        return request ? members->request_members_ : members->response_members_;
+    }
+    case RS_TYPE_SUPPORT_INTROSPECTION_CPP:
+    {
+        auto members = static_cast<const rosidl_typesupport_introspection_cpp::ServiceMembers *>(
+            type_support->data);
+        return request ? members->request_members_ : members->response_members_;
+    }
     }
-    return static_cast<const service_type_support_callbacks_t *>(type_support->data);
+    return NULL;
 }
 
-// Retrieve request type support callbacks from service type support
+// Retrieve request type support data from service type support
 const void *rs_get_request_type_support_callbacks(
-    const rosidl_service_type_support_t *type_support)
+    const rosidl_service_type_support_t *type_support,
+    rs_type_support_kind_t *kind)
 {
-    const service_type_support_callbacks_t *service_members =
-        rs_get_service_type_support_callbacks(type_support);
-    if (service_members)
+    return get_service_member_callbacks(type_support, kind, true);
+}
+
+// Retrieve response type support data from service type support
+const void *rs_get_response_type_support_callbacks(
+    const rosidl_service_type_support_t *type_support,
+    rs_type_support_kind_t *kind)
+{
+    return get_service_member_callbacks(type_support, kind, false);
+}
+
+// Retrieve the message name from type support data
+const char *rs_get_message_name(
+    rs_type_support_kind_t kind,
+    const void *callbacks_)
+{
+    if (!callbacks_)
     {
-        return service_members->request_members_->data;
+        return NULL;
     }
-    else
+    if (kind != RS_TYPE_SUPPORT_FASTRTPS)
     {
-        return NULL;
+        return introspection_get_message_name(callbacks_, kind == RS_TYPE_SUPPORT_INTROSPECTION_CPP);
     }
+    return static_cast<const message_type_support_callbacks_t *>(callbacks_)->message_name_;
 }
 
-// Retrieve response type support callbacks from service type support
-const void *rs_get_response_type_support_callbacks(
-    const rosidl_service_type_support_t *type_support)
+// Retrieve the message namespace from type support data
+const char *rs_get_message_namespace(
+    rs_type_support_kind_t kind,
+    const void *callbacks_)
 {
-    const service_type_support_callbacks_t *service_members =
-        rs_get_service_type_support_callbacks(type_support);
-    if (service_members)
+    if (!callbacks_)
     {
-        return service_members->response_members_->data;
+        return NULL;
     }
-    else
+    if (kind != RS_TYPE_SUPPORT_FASTRTPS)
     {
-        return NULL;
+        return introspection_get_message_namespace(
+            callbacks_, kind == RS_TYPE_SUPPORT_INTROSPECTION_CPP);
     }
+    return static_cast<const message_type_support_callbacks_t *>(callbacks_)->message_namespace_;
 }
 
-// Retrieve the message name from type support callbacks
-const char *rs_get_message_name(
-    const void *callbacks_)
+// Calculate the largest serialized size of a message, including encapsulation.
+// Introspection type supports count `unbounded_length` elements for unbounded strings
+// and sequences. `is_bounded` is set to false if the type has some that were not counted.
+size_t rs_get_max_serialized_size(
+    rs_type_support_kind_t kind,
+    const void *callbacks,
+    size_t unbounded_length,
+    bool *is_bounded)
 {
-    auto callbacks = static_cast<const message_type_support_callbacks_t *>(callbacks_);
-    if (callbacks)
+    bool full_bounded = true;
+    size_t size;
+    if (kind != RS_TYPE_SUPPORT_FASTRTPS)
     {
-        return callbacks->message_name_;
+        size = introspection_get_max_serialized_size(
+            callbacks, kind == RS_TYPE_SUPPORT_INTROSPECTION_CPP, unbounded_length, full_bounded);
     }
     else
     {
-        return NULL;
+        size = static_cast<const message_type_support_callbacks_t *>(callbacks)->max_serialized_size(
+            full_bounded);
     }
+    *is_bounded = full_bounded;
+    return size > SIZE_MAX - 4 ? SIZE_MAX : 4 + size;
 }
 
-// Retrieve the message namespace from type support callbacks
-const char *rs_get_message_namespace(
-    const void *callbacks_)
+// Calculate the serialized size of a message, without encapsulation.
+// It is exact for FastRTPS and an estimate for introspection type supports.
+static size_t get_serialized_size(
+    rs_type_support_kind_t kind,
+    const void *callbacks,
+    const void *ros_message)
 {
-    auto callbacks = static_cast<const message_type_support_callbacks_t *>(callbacks_);
-    if (callbacks)
+    if (kind != RS_TYPE_SUPPORT_FASTRTPS)
     {
-        return callbacks->message_namespace_;
+        return introspection_get_size_hint(callbacks, kind == RS_TYPE_SUPPORT_INTROSPECTION_CPP);
     }
-    else
+    return static_cast<const message_type_support_callbacks_t *>(callbacks)->get_serialized_size(
+        ros_message);
+}
+
+// Serialize the members of a ROS message, without encapsulation
+static bool serialize_members(
+    eprosima::fastcdr::Cdr &ser,
+    rs_type_support_kind_t kind,
+    const void *callbacks,
+    const void *ros_message)
+{
+    if (kind != RS_TYPE_SUPPORT_FASTRTPS)
     {
-        return NULL;
+        return introspection_serialize(
+            ser, callbacks, kind == RS_TYPE_SUPPORT_INTROSPECTION_CPP, ros_message);
     }
+    return static_cast<const message_type_support_callbacks_t *>(callbacks)->cdr_serialize(
+        ros_message, ser);
 }
 
-// Calculate the serialized size of a message, including encapsulation
-size_t rs_get_serialized_size(
-    const message_type_support_callbacks_t *callbacks,
-    const void *ros_message)
+// Deserialize the members of a ROS message, without encapsulation
+static bool deserialize_members(
+    eprosima::fastcdr::Cdr &deser,
+    rs_type_support_kind_t kind,
+    const void *callbacks,
+    void *ros_message)
 {
-    return 4 + callbacks->get_serialized_size(ros_message);
+    if (kind != RS_TYPE_SUPPORT_FASTRTPS)
+    {
+        return introspection_deserialize(
+            deser, callbacks, kind == RS_TYPE_SUPPORT_INTROSPECTION_CPP, ros_message);
+    }
+    return static_cast<const message_type_support_callbacks_t *>(callbacks)->cdr_deserialize(
+        deser, ros_message);
+}
+
+// Serialize the encapsulation and `serialize_body` into a serialized message buffer.
+// The buffer is first sized for `data_length`; when it is only an estimate and turns
+// out too small, the buffer is grown and the serialization retried.
+template <typename SerializeBody>
+static bool serialize_into(
+    rmw_serialized_message_t *serialized_message,
+    size_t data_length,
+    SerializeBody serialize_body)
+{
+    const int max_attempts = 32;
+    for (int attempt = 0; attempt < max_attempts; attempt++, data_length *= 2)
+    {
+        if (serialized_message->buffer_capacity < data_length)
+        {
+            if (rmw_serialized_message_resize(serialized_message, data_length) != RMW_RET_OK)
+            {
+                return false;
+            }
+        }
+        data_length = serialized_message->buffer_capacity;
+        eprosima::fastcdr::FastBuffer buffer(
+            reinterpret_cast<char *>(serialized_message->buffer), data_length);
+        eprosima::fastcdr::Cdr ser(
+            buffer, eprosima::fastcdr::Cdr::DEFAULT_ENDIAN, eprosima::fastcdr::Cdr::DDS_CDR);
+        try
+        {
+            ser.serialize_encapsulation();
+            if (!serialize_body(ser))
+            {
+                return false;
+            }
+        }
+        catch (const eprosima::fastcdr::exception::NotEnoughMemoryException &)
+        {
+            continue;
+        }
+        serialized_message->buffer_length = ser.getSerializedDataLength();
+        return true;
+    }
+    return false;
 }
 
 // Serialize a ROS message into a serialized message buffer
 bool rs_serialize_message(
+    rs_type_support_kind_t kind,
     const void *callbacks,
     const void *ros_message,
     rmw_serialized_message_t *serialized_message)
 {
-    const message_type_support_callbacks_t *cb =
-        static_cast<const message_type_support_callbacks_t *>(callbacks);
-    size_t data_length = rs_get_serialized_size(cb, ros_message);
-    if (serialized_message->buffer_capacity < data_length)
-    {
-        if (rmw_serialized_message_resize(serialized_message, data_length) != RMW_RET_OK)
+    size_t data_length = 4 + get_serialized_size(kind, callbacks, ros_message);
+    return serialize_into(
+        serialized_message, data_length, [&](eprosima::fastcdr::Cdr &ser)
         {
-            return false;
-        }
-    }
-    serialized_message->buffer_length = data_length;
-
-    eprosima::fastcdr::FastBuffer buffer(
-        reinterpret_cast<char *>(serialized_message->buffer), data_length);
-    eprosima::fastcdr::Cdr ser(
-        buffer, eprosima::fastcdr::Cdr::DEFAULT_ENDIAN, eprosima::fastcdr::Cdr::DDS_CDR);
-    ser.serialize_encapsulation();
-    return cb->cdr_serialize(ros_message, ser);
+            return serialize_members(ser, kind, callbacks, ros_message);
+        });
 }
 
 // Deserialize a serialized message buffer into a ROS message
 bool rs_deserialize_message(
+    rs_type_support_kind_t kind,
     const void *callbacks,
     const rmw_serialized_message_t *serialized_message,
     void *ros_message)
 {
-    const message_type_support_callbacks_t *cb =
-        static_cast<const message_type_support_callbacks_t *>(callbacks);
     eprosima::fastcdr::FastBuffer buffer(
         reinterpret_cast<char *>(serialized_message->buffer), serialized_message->buffer_length);
     eprosima::fastcdr::Cdr deser(buffer, eprosima::fastcdr::Cdr::DEFAULT_ENDIAN,
                                  eprosima::fastcdr::Cdr::DDS_CDR);
-    deser.read_encapsulation();
-    return cb->cdr_deserialize(deser, ros_message);
+    try
+    {
+        deser.read_encapsulation();
+        return deserialize_members(deser, kind, callbacks, ros_message);
+    }
+    catch (const eprosima::fastcdr::exception::Exception &)
+    {
+        // Truncated or malformed payload
+        return false;
+    }
+}
+
+// Serialize a service introspection event: service_msgs/msg/ServiceEventInfo
+// followed by the bounded request and response sequences (<=1 element each)
+bool rs_serialize_service_event(
+    rs_type_support_kind_t request_kind,
+    const void *request_callbacks,
+    const void *ros_request,
+    rs_type_support_kind_t response_kind,
+    const void *response_callbacks,
+    const void *ros_response,
+    uint8_t event_type,
+    int64_t timestamp,
+    const uint8_t *client_gid,
+    int64_t sequence_number,
+    rmw_serialized_message_t *serialized_message)
+{
+    if ((ros_request && !request_callbacks) || (ros_response && !response_callbacks))
+    {
+        return false;
+    }
+
+    // Encapsulation, ServiceEventInfo and the two sequence lengths
+    size_t data_length = 4 + 48 + 8;
+    if (ros_request)
+    {
+        data_length += 8 + get_serialized_size(request_kind, request_callbacks, ros_request);
+    }
+    if (ros_response)
+    {
+        data_length += 8 + get_serialized_size(response_kind, response_callbacks, ros_response);
+    }
+
+    // The nested messages are not aligned the same way as when they are serialized
+    // on their own, so the size is only an estimate
+    return serialize_into(
+        serialized_message, data_length, [&](eprosima::fastcdr::Cdr &ser)
+        {
+            ser << event_type;
+            ser << static_cast<int32_t>(timestamp / 1000000000);
+            ser << static_cast<uint32_t>(timestamp % 1000000000);
+            ser.serializeArray(client_gid, 16);
+            ser << sequence_number;
+            ser << static_cast<uint32_t>(ros_request ? 1 : 0);
+            if (ros_request && !serialize_members(ser, request_kind, request_callbacks, ros_request))
+            {
+                return false;
+            }
+            ser << static_cast<uint32_t>(ros_response ? 1 : 0);
+            if (ros_response &&
+                !serialize_members(ser, response_kind, response_callbacks, ros_response))
+            {
+                return false;
+            }
+            return true;
+        });
 }
@@ -3,29 +3,68 @@ extern "C"
 {
 #endif
 
-    // Retrieve message type support callbacks for FastRTPS
+    // Type support used to (de)serialize a type
+    typedef enum rs_type_support_kind_e
+    {
+        // FastRTPS callbacks (message_type_support_callbacks_t)
+        RS_TYPE_SUPPORT_FASTRTPS,
+        // rosidl_typesupport_introspection_c members, for types without FastRTPS type support
+        RS_TYPE_SUPPORT_INTROSPECTION_C,
+        // rosidl_typesupport_introspection_cpp members, for types without FastRTPS type support
+        RS_TYPE_SUPPORT_INTROSPECTION_CPP,
+    } rs_type_support_kind_t;
+
+    // Retrieve message type support data: FastRTPS callbacks or introspection members
     const void *rs_get_message_type_support_callbacks(
-        const rosidl_message_type_support_t *type_support_);
-    // Retrieve request type support callbacks from service type support
+        const rosidl_message_type_support_t *type_support_,
+        rs_type_support_kind_t *kind);
+    // Retrieve the introspection members of a message, skipping its FastRTPS type support
+    const void *rs_get_message_introspection_callbacks(
+        const rosidl_message_type_support_t *type_support_,
+        rs_type_support_kind_t *kind);
+    // Retrieve request type support data from service type support
     const void *rs_get_request_type_support_callbacks(
-        const rosidl_service_type_support_t *type_support);
-    // Retrieve response type support callbacks from service type support
+        const rosidl_service_type_support_t *type_support,
+        rs_type_support_kind_t *kind);
+    // Retrieve response type support data from service type support
     const void *rs_get_response_type_support_callbacks(
-        const rosidl_service_type_support_t *type_support);
-    // Retrieve the message name from type support callbacks
-    const char *rs_get_message_name(const void *callbacks);
-    // Retrieve the message namespace from type support callbacks
-    const char *rs_get_message_namespace(const void *callbacks);
+        const rosidl_service_type_support_t *type_support,
+        rs_type_support_kind_t *kind);
+    // Retrieve the message name from type support data
+    const char *rs_get_message_name(rs_type_support_kind_t kind, const void *callbacks);
+    // Retrieve the message namespace from type support data
+    const char *rs_get_message_namespace(rs_type_support_kind_t kind, const void *callbacks);
+    // Calculate the largest serialized size of a message, including encapsulation
+    size_t rs_get_max_serialized_size(
+        rs_type_support_kind_t kind,
+        const void *callbacks,
+        size_t unbounded_length,
+        bool *is_bounded);
     // Serialize a ROS message into a serialized message buffer
     bool rs_serialize_message(
+        rs_type_support_kind_t kind,
         const void *callbacks,
         const void *ros_message,
         rmw_serialized_message_t *serialized_message);
     // Deserialize a serialized message buffer into a ROS message
     bool rs_deserialize_message(
+        rs_type_support_kind_t kind,
         const void *callbacks,
         const rmw_serialized_message_t *serialized_message,
         void *ros_message);
+    // Serialize a service introspection event into a serialized message buffer
+    bool rs_serialize_service_event(
+        rs_type_support_kind_t request_kind,
+        const void *request_callbacks,
+        const void *ros_request,
+        rs_type_support_kind_t response_kind,
+        const void *response_callbacks,
+        const void *ros_response,
+        uint8_t event_type,
+        int64_t timestamp,
+        const uint8_t *client_gid,
+        int64_t sequence_number,
+        rmw_serialized_message_t *serialized_message);
 
 #ifdef __cplusplus
 }
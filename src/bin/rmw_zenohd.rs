@@ -0,0 +1,51 @@
+use zenoh::Wait;
+
+use rmw_zenoh_rs::context::Context;
+use rmw_zenoh_rs::context::{DEFAULT_ROUTER_CONFIG, ROUTER_CONFIG_ENV};
+use rmw_zenoh_rs::transport_rules::TransportRules;
+
+// Starts a Zenoh router for rmw_zenoh_rs and keeps it running until the process is terminated
+fn main() {
+    // Locate the router configuration the same way as the session configuration
+    let config_path = match Context::get_config_path(ROUTER_CONFIG_ENV, DEFAULT_ROUTER_CONFIG) {
+        Ok(config_path) => config_path,
+        Err(e) => {
+            eprintln!("Failed to locate the router configuration: {e}");
+            std::process::exit(1);
+        }
+    };
+    // Load the router configuration with the discovery settings and overrides from the environment
+    let localhost_only = std::env::var("ROS_LOCALHOST_ONLY").is_ok_and(|value| value == "1");
+    let mut config =
+        match Context::load_config(ROUTER_CONFIG_ENV, DEFAULT_ROUTER_CONFIG, localhost_only) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Failed to load {}: {e}", config_path.display());
+                std::process::exit(1);
+            }
+        };
+    // Downsample the topics of the transport rules opting in to router downsampling
+    if let Err(e) =
+        TransportRules::load(&config).and_then(|rules| rules.apply_downsampling(&mut config))
+    {
+        eprintln!("Failed to load the transport rules: {e}");
+        std::process::exit(1);
+    }
+    // Open the router session
+    let session = match zenoh::open(config).wait() {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("Failed to start the router: {e}");
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "Started rmw_zenohd with ZID {} using {}",
+        session.zid(),
+        config_path.display()
+    );
+    // The router runs in the background threads of the session
+    loop {
+        std::thread::park();
+    }
+}
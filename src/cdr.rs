@@ -0,0 +1,378 @@
+use crate::rmw::rmw_serialized_message_t;
+use crate::Error;
+
+// Encapsulation header of little-endian plain CDR (XCDR1), as written by fastcdr
+const ENCAPSULATION_CDR_LE: [u8; 4] = [0x00, 0x01, 0x00, 0x00];
+// Length of the encapsulation header; alignment is relative to its end
+const ENCAPSULATION_SIZE: usize = 4;
+// fastcdr aligns long double to 8 bytes and copies the 16 bytes of the C long double
+const LONG_DOUBLE_ALIGNMENT: usize = 8;
+const LONG_DOUBLE_SIZE: usize = 16;
+// Bytes holding the value of a C long double. On x86_64 it is the 80-bit x87 extended
+// format followed by padding, which is written as zeros so that the output is deterministic.
+#[cfg(target_arch = "x86_64")]
+const LONG_DOUBLE_VALUE_SIZE: usize = 10;
+#[cfg(not(target_arch = "x86_64"))]
+const LONG_DOUBLE_VALUE_SIZE: usize = LONG_DOUBLE_SIZE;
+
+// Primitive trait: Fixed-size values aligned to their size
+pub trait Primitive: Copy {
+    const SIZE: usize;
+    // Writes the little-endian representation into `bytes` of length SIZE
+    fn encode(self, bytes: &mut [u8]);
+    // Reads the value from `bytes` of length SIZE, None if they are not a valid value
+    fn decode(bytes: &[u8], little_endian: bool) -> Option<Self>;
+}
+
+macro_rules! impl_primitive {
+    ($($t:ty),*) => {
+        $(
+            impl Primitive for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+                fn encode(self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_le_bytes());
+                }
+                fn decode(bytes: &[u8], little_endian: bool) -> Option<Self> {
+                    let bytes = bytes.try_into().ok()?;
+                    if little_endian {
+                        Some(<$t>::from_le_bytes(bytes))
+                    } else {
+                        Some(<$t>::from_be_bytes(bytes))
+                    }
+                }
+            }
+        )*
+    };
+}
+impl_primitive!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+impl Primitive for bool {
+    const SIZE: usize = 1;
+    fn encode(self, bytes: &mut [u8]) {
+        bytes[0] = self as u8;
+    }
+    // fastcdr rejects any byte other than 0 and 1
+    fn decode(bytes: &[u8], _little_endian: bool) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
+
+// CdrWriter struct: Little-endian XCDR1 encoder writing into a serialized message buffer
+pub struct CdrWriter<'a> {
+    message: &'a mut rmw_serialized_message_t,
+    position: usize,
+}
+
+impl<'a> CdrWriter<'a> {
+    // Starts a message with its encapsulation header
+    pub fn new(message: &'a mut rmw_serialized_message_t) -> Result<Self, Error> {
+        let mut writer = CdrWriter {
+            message,
+            position: 0,
+        };
+        writer.write_bytes(&ENCAPSULATION_CDR_LE)?;
+        Ok(writer)
+    }
+    // Sets the length of the serialized message to the written bytes
+    pub fn finish(self) {
+        self.message.buffer_length = self.position;
+    }
+    // Writes raw bytes without alignment
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let data = self.allocate(bytes.len())?;
+        data.copy_from_slice(bytes);
+        Ok(())
+    }
+    // Writes a primitive aligned to its size
+    pub fn write<T: Primitive>(&mut self, value: T) -> Result<(), Error> {
+        self.align(T::SIZE)?;
+        value.encode(self.allocate(T::SIZE)?);
+        Ok(())
+    }
+    // Writes the length of a sequence
+    pub fn write_length(&mut self, length: usize) -> Result<(), Error> {
+        let length = u32::try_from(length)
+            .map_err(|_| Error::Serialization(format!("a sequence of {length} elements")))?;
+        self.write(length)
+    }
+    // Writes a C long double read from memory
+    pub fn write_long_double(&mut self, value: &[u8; LONG_DOUBLE_SIZE]) -> Result<(), Error> {
+        self.align(LONG_DOUBLE_ALIGNMENT)?;
+        let data = self.allocate(LONG_DOUBLE_SIZE)?;
+        data.fill(0);
+        data[..LONG_DOUBLE_VALUE_SIZE].copy_from_slice(&value[..LONG_DOUBLE_VALUE_SIZE]);
+        Ok(())
+    }
+    // Writes a string: its length including the terminating NUL, its bytes and the NUL
+    pub fn write_string(&mut self, string: &[u8]) -> Result<(), Error> {
+        self.write_length(string.len() + 1)?;
+        self.write_bytes(string)?;
+        self.write_bytes(&[0])
+    }
+    // Writes a UTF-16 string as fastcdr writes wide strings: its length, then 4-byte characters
+    pub fn write_wstring(&mut self, string: &[u16]) -> Result<(), Error> {
+        self.write_length(string.len())?;
+        for &c in string {
+            self.write(c as u32)?;
+        }
+        Ok(())
+    }
+    // Writes zero padding up to the alignment
+    fn align(&mut self, alignment: usize) -> Result<(), Error> {
+        let offset = self.position - ENCAPSULATION_SIZE;
+        let padding = (alignment - offset % alignment) % alignment;
+        self.allocate(padding)?.fill(0);
+        Ok(())
+    }
+    // Returns the next `size` bytes of the buffer, growing it if needed
+    fn allocate(&mut self, size: usize) -> Result<&mut [u8], Error> {
+        if size == 0 {
+            return Ok(&mut []);
+        }
+        let end = self.position + size;
+        if end > self.message.buffer_capacity {
+            // Grow geometrically so that large messages do not reallocate on every field
+            self.message
+                .try_reserve(end.max(self.message.buffer_capacity * 2))?;
+        }
+        let data =
+            unsafe { std::slice::from_raw_parts_mut(self.message.buffer.add(self.position), size) };
+        self.position = end;
+        Ok(data)
+    }
+}
+
+// CdrReader struct: XCDR1 decoder over a received payload, in either endianness
+pub struct CdrReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    little_endian: bool,
+}
+
+impl<'a> CdrReader<'a> {
+    // Reads the encapsulation header of a payload
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let little_endian = match data {
+            [0x00, 0x01, _, _, ..] => true,
+            [0x00, 0x00, _, _, ..] => false,
+            _ => {
+                return Err(Error::Serialization(
+                    "a payload without a plain CDR encapsulation".to_string(),
+                ))
+            }
+        };
+        Ok(CdrReader {
+            data,
+            position: ENCAPSULATION_SIZE,
+            little_endian,
+        })
+    }
+    // Reads a primitive aligned to its size
+    pub fn read<T: Primitive>(&mut self) -> Result<T, Error> {
+        self.align(T::SIZE)?;
+        let bytes = self.read_bytes(T::SIZE)?;
+        T::decode(bytes, self.little_endian).ok_or_else(|| {
+            Error::Serialization(format!("an invalid {} value", std::any::type_name::<T>()))
+        })
+    }
+    // Reads the length of a sequence
+    pub fn read_length(&mut self) -> Result<usize, Error> {
+        Ok(self.read::<u32>()? as usize)
+    }
+    // Reads a long double into the layout of a C long double
+    pub fn read_long_double(&mut self) -> Result<[u8; LONG_DOUBLE_SIZE], Error> {
+        self.align(LONG_DOUBLE_ALIGNMENT)?;
+        let mut bytes: [u8; LONG_DOUBLE_SIZE] = self
+            .read_bytes(LONG_DOUBLE_SIZE)?
+            .try_into()
+            .unwrap_or_default();
+        if !self.little_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+    // Reads a string, without its terminating NUL
+    pub fn read_string(&mut self) -> Result<&'a [u8], Error> {
+        let length = self.read_length()?;
+        let bytes = self.read_bytes(length)?;
+        Ok(bytes.strip_suffix(&[0]).unwrap_or(bytes))
+    }
+    // Reads a UTF-16 string written by `CdrWriter::write_wstring`
+    pub fn read_wstring(&mut self, string: &mut Vec<u16>) -> Result<(), Error> {
+        let length = self.read_length()?;
+        string.clear();
+        for _ in 0..length {
+            string.push(self.read::<u32>()? as u16);
+        }
+        Ok(())
+    }
+    // Reads raw bytes without alignment
+    pub fn read_bytes(&mut self, size: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .position
+            .checked_add(size)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| Error::Serialization("a truncated CDR payload".to_string()))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+    // Skips the padding up to the alignment
+    fn align(&mut self, alignment: usize) -> Result<(), Error> {
+        let offset = self.position - ENCAPSULATION_SIZE;
+        let padding = (alignment - offset % alignment) % alignment;
+        self.read_bytes(padding).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rmw::rcutils_get_default_allocator;
+
+    // Returns the bytes written by `write` after the encapsulation header
+    fn write(write: impl FnOnce(&mut CdrWriter) -> Result<(), Error>) -> Vec<u8> {
+        let allocator = unsafe { rcutils_get_default_allocator() };
+        let mut message = rmw_serialized_message_t::new(8, allocator).unwrap();
+        let mut writer = CdrWriter::new(&mut message).unwrap();
+        write(&mut writer).unwrap();
+        writer.finish();
+        let bytes =
+            unsafe { std::slice::from_raw_parts(message.buffer, message.buffer_length) }.to_vec();
+        message.fini();
+        assert_eq!(bytes[..ENCAPSULATION_SIZE], ENCAPSULATION_CDR_LE);
+        bytes[ENCAPSULATION_SIZE..].to_vec()
+    }
+
+    #[test]
+    fn primitives_are_aligned_after_the_encapsulation() {
+        let bytes = write(|writer| {
+            writer.write(0x01u8)?;
+            writer.write(0x0203u16)?;
+            writer.write(-1i8)?;
+            writer.write(0x04050607u32)?;
+            writer.write(0x08u8)?;
+            // Aligned to 8 from the end of the header, not from the start of the buffer
+            writer.write(0x090a0b0c0d0e0f10u64)?;
+            writer.write(0.5f32)?;
+            writer.write(-2.0f64)
+        });
+        #[rustfmt::skip]
+        assert_eq!(bytes, [
+            0x01, 0x00, 0x03, 0x02, 0xff, 0x00, 0x00, 0x00,
+            0x07, 0x06, 0x05, 0x04, 0x08, 0x00, 0x00, 0x00,
+            0x10, 0x0f, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a, 0x09,
+            0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc0,
+        ]);
+    }
+
+    #[test]
+    fn booleans_are_single_bytes() {
+        let bytes = write(|writer| {
+            writer.write(true)?;
+            writer.write(false)
+        });
+        assert_eq!(bytes, [0x01, 0x00]);
+
+        let mut reader = CdrReader::new(&[0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x02]).unwrap();
+        assert!(reader.read::<bool>().unwrap());
+        assert!(!reader.read::<bool>().unwrap());
+        assert!(matches!(
+            reader.read::<bool>(),
+            Err(Error::Serialization(_))
+        ));
+    }
+
+    #[test]
+    fn strings_include_their_terminating_nul() {
+        let bytes = write(|writer| {
+            writer.write_string(b"ros")?;
+            writer.write_string(b"")?;
+            writer.write(0x01u8)?;
+            writer.write_wstring(&[0x00e9, 0x20ac])
+        });
+        #[rustfmt::skip]
+        assert_eq!(bytes, [
+            0x04, 0x00, 0x00, 0x00, b'r', b'o', b's', 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x02, 0x00, 0x00, 0x00, 0xe9, 0x00, 0x00, 0x00,
+            0xac, 0x20, 0x00, 0x00,
+        ]);
+
+        let mut payload = ENCAPSULATION_CDR_LE.to_vec();
+        payload.extend_from_slice(&bytes);
+        let mut reader = CdrReader::new(&payload).unwrap();
+        assert_eq!(reader.read_string().unwrap(), b"ros");
+        assert_eq!(reader.read_string().unwrap(), b"");
+        assert_eq!(reader.read::<u8>().unwrap(), 0x01);
+        let mut wstring = Vec::new();
+        reader.read_wstring(&mut wstring).unwrap();
+        assert_eq!(wstring, [0x00e9, 0x20ac]);
+    }
+
+    // fastcdr copies the x87 extended representation of a long double on x86_64
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn long_doubles_are_copied_in_their_native_format() {
+        let mut one = [0; LONG_DOUBLE_SIZE];
+        one[7] = 0x80;
+        one[8] = 0xff;
+        one[9] = 0x3f;
+        // The padding of the C long double is not written
+        one[10..].fill(0xaa);
+        let bytes = write(|writer| {
+            writer.write(0x07u8)?;
+            writer.write_long_double(&one)
+        });
+        #[rustfmt::skip]
+        assert_eq!(bytes, [
+            0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+            0xff, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+
+        let mut payload = ENCAPSULATION_CDR_LE.to_vec();
+        payload.extend_from_slice(&bytes);
+        let mut reader = CdrReader::new(&payload).unwrap();
+        assert_eq!(reader.read::<u8>().unwrap(), 0x07);
+        assert_eq!(reader.read_long_double().unwrap()[..10], one[..10]);
+    }
+
+    #[test]
+    fn big_endian_payloads_are_decoded() {
+        #[rustfmt::skip]
+        let payload = [
+            0x00, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+            0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10,
+            0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x03, b'r', b'o', 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xe9,
+        ];
+        let mut reader = CdrReader::new(&payload).unwrap();
+        assert_eq!(reader.read::<u8>().unwrap(), 0x01);
+        assert_eq!(reader.read::<u16>().unwrap(), 0x0203);
+        assert_eq!(reader.read::<i32>().unwrap(), 0x04050607);
+        assert_eq!(reader.read::<u64>().unwrap(), 0x090a0b0c0d0e0f10);
+        assert_eq!(reader.read::<f64>().unwrap(), -2.0);
+        assert_eq!(reader.read_string().unwrap(), b"ro");
+        let mut wstring = Vec::new();
+        reader.read_wstring(&mut wstring).unwrap();
+        assert_eq!(wstring, [0x00e9]);
+    }
+
+    #[test]
+    fn malformed_payloads_are_rejected() {
+        assert!(CdrReader::new(&[0x00, 0x07, 0x00, 0x00]).is_err());
+        assert!(CdrReader::new(&[0x00, 0x01]).is_err());
+        let mut reader = CdrReader::new(&[0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00]).unwrap();
+        assert!(reader.read::<u32>().is_err());
+        let mut reader = CdrReader::new(&[0x00, 0x01, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00]).unwrap();
+        assert!(reader.read_string().is_err());
+    }
+}
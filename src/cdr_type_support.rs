@@ -0,0 +1,1390 @@
+// Rust counterparts of the functions of cpp/type_support.cpp, used with the `rust-cdr` feature.
+// Types are (de)serialized by walking their introspection type support with the CDR engine
+// of `cdr.rs`, so neither fastcdr nor the FastRTPS type supports are needed.
+use std::ffi::c_void;
+use std::os::raw::c_char;
+use std::ptr::null;
+
+use crate::cdr::{CdrReader, CdrWriter};
+use crate::rmw::*;
+use crate::rsutils::str_from_ptr;
+use crate::Error;
+
+// Identifiers of the introspection type supports; the rosidl lookup compares them by value
+const INTROSPECTION_C_IDENTIFIER: *const c_char =
+    "rosidl_typesupport_introspection_c\0".as_ptr() as *const c_char;
+const INTROSPECTION_CPP_IDENTIFIER: *const c_char =
+    "rosidl_typesupport_introspection_cpp\0".as_ptr() as *const c_char;
+
+const KIND_INTROSPECTION_C: rs_type_support_kind_t =
+    rs_type_support_kind_e_RS_TYPE_SUPPORT_INTROSPECTION_C;
+const KIND_INTROSPECTION_CPP: rs_type_support_kind_t =
+    rs_type_support_kind_e_RS_TYPE_SUPPORT_INTROSPECTION_CPP;
+
+// Field types of the introspection type supports, shared by C and C++
+const ROS_TYPE_FLOAT: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_FLOAT as u8;
+const ROS_TYPE_DOUBLE: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_DOUBLE as u8;
+const ROS_TYPE_LONG_DOUBLE: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_LONG_DOUBLE as u8;
+const ROS_TYPE_CHAR: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_CHAR
+        as u8;
+const ROS_TYPE_WCHAR: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_WCHAR as u8;
+const ROS_TYPE_BOOLEAN: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_BOOLEAN as u8;
+const ROS_TYPE_OCTET: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_OCTET as u8;
+const ROS_TYPE_UINT8: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_UINT8 as u8;
+const ROS_TYPE_INT8: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_INT8
+        as u8;
+const ROS_TYPE_UINT16: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_UINT16 as u8;
+const ROS_TYPE_INT16: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_INT16 as u8;
+const ROS_TYPE_UINT32: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_UINT32 as u8;
+const ROS_TYPE_INT32: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_INT32 as u8;
+const ROS_TYPE_UINT64: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_UINT64 as u8;
+const ROS_TYPE_INT64: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_INT64 as u8;
+const ROS_TYPE_STRING: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_STRING as u8;
+const ROS_TYPE_WSTRING: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_WSTRING as u8;
+const ROS_TYPE_MESSAGE: u8 =
+    rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_MESSAGE as u8;
+
+// The C and C++ introspection members have the same layout, except for the return type
+// of resize_function, so the C bindings describe both
+type Members = rosidl_typesupport_introspection_c__MessageMembers;
+type Member = rosidl_typesupport_introspection_c__MessageMember;
+type CppResizeFunction = unsafe extern "C" fn(*mut c_void, usize);
+
+// Layout shared by every rosidl_runtime_c sequence
+#[repr(C)]
+struct Sequence {
+    data: *mut c_void,
+    size: usize,
+    capacity: usize,
+}
+
+// Retrieve message introspection members
+pub unsafe fn rs_get_message_type_support_callbacks(
+    type_support: *const rosidl_message_type_support_t,
+    kind: *mut rs_type_support_kind_t,
+) -> *const c_void {
+    let handle = find_type_support(type_support, get_message_typesupport_handle, kind);
+    if handle.is_null() {
+        return null();
+    }
+    (*handle).data
+}
+
+// Retrieve message introspection members; every type support found here is one
+pub unsafe fn rs_get_message_introspection_callbacks(
+    type_support: *const rosidl_message_type_support_t,
+    kind: *mut rs_type_support_kind_t,
+) -> *const c_void {
+    rs_get_message_type_support_callbacks(type_support, kind)
+}
+
+// Retrieve request introspection members from service type support
+pub unsafe fn rs_get_request_type_support_callbacks(
+    type_support: *const rosidl_service_type_support_t,
+    kind: *mut rs_type_support_kind_t,
+) -> *const c_void {
+    get_service_members(type_support, kind, true)
+}
+
+// Retrieve response introspection members from service type support
+pub unsafe fn rs_get_response_type_support_callbacks(
+    type_support: *const rosidl_service_type_support_t,
+    kind: *mut rs_type_support_kind_t,
+) -> *const c_void {
+    get_service_members(type_support, kind, false)
+}
+
+// Retrieve the message name from introspection members
+pub unsafe fn rs_get_message_name(
+    _kind: rs_type_support_kind_t,
+    callbacks: *const c_void,
+) -> *const c_char {
+    if callbacks.is_null() {
+        return null();
+    }
+    (*(callbacks as *const Members)).message_name_
+}
+
+// Retrieve the message namespace from introspection members
+pub unsafe fn rs_get_message_namespace(
+    _kind: rs_type_support_kind_t,
+    callbacks: *const c_void,
+) -> *const c_char {
+    if callbacks.is_null() {
+        return null();
+    }
+    (*(callbacks as *const Members)).message_namespace_
+}
+
+// Serialize a ROS message into a serialized message buffer
+pub unsafe fn rs_serialize_message(
+    kind: rs_type_support_kind_t,
+    callbacks: *const c_void,
+    ros_message: *const c_void,
+    serialized_message: *mut rmw_serialized_message_t,
+) -> bool {
+    let mut writer = match CdrWriter::new(&mut *serialized_message) {
+        Ok(writer) => writer,
+        Err(e) => return succeeded(Err(e)),
+    };
+    let result = serialize_message(
+        &mut writer,
+        &*(callbacks as *const Members),
+        kind == KIND_INTROSPECTION_CPP,
+        ros_message as *const u8,
+    );
+    writer.finish();
+    succeeded(result)
+}
+
+// Deserialize a serialized message buffer into a ROS message
+pub unsafe fn rs_deserialize_message(
+    kind: rs_type_support_kind_t,
+    callbacks: *const c_void,
+    serialized_message: *const rmw_serialized_message_t,
+    ros_message: *mut c_void,
+) -> bool {
+    let serialized_message = &*serialized_message;
+    if serialized_message.buffer.is_null() {
+        return false;
+    }
+    let data =
+        std::slice::from_raw_parts(serialized_message.buffer, serialized_message.buffer_length);
+    let result = CdrReader::new(data).and_then(|mut reader| {
+        deserialize_message(
+            &mut reader,
+            &*(callbacks as *const Members),
+            kind == KIND_INTROSPECTION_CPP,
+            ros_message as *mut u8,
+        )
+    });
+    succeeded(result)
+}
+
+// Calculate the largest serialized size of a message, including encapsulation.
+// Unbounded strings and sequences count `unbounded_length` elements.
+// `is_bounded` is set to false if the type has some that were not counted.
+pub unsafe fn rs_get_max_serialized_size(
+    _kind: rs_type_support_kind_t,
+    callbacks: *const c_void,
+    unbounded_length: usize,
+    is_bounded: *mut bool,
+) -> usize {
+    let mut full_bounded = true;
+    let mut offset = 0;
+    add_max_serialized_size(
+        &*(callbacks as *const Members),
+        unbounded_length,
+        &mut offset,
+        &mut full_bounded,
+    );
+    *is_bounded = full_bounded;
+    offset.saturating_add(4)
+}
+
+// Serialize a service introspection event: service_msgs/msg/ServiceEventInfo
+// followed by the bounded request and response sequences (<=1 element each)
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn rs_serialize_service_event(
+    request_kind: rs_type_support_kind_t,
+    request_callbacks: *const c_void,
+    ros_request: *const c_void,
+    response_kind: rs_type_support_kind_t,
+    response_callbacks: *const c_void,
+    ros_response: *const c_void,
+    event_type: u8,
+    timestamp: i64,
+    client_gid: *const u8,
+    sequence_number: i64,
+    serialized_message: *mut rmw_serialized_message_t,
+) -> bool {
+    if (!ros_request.is_null() && request_callbacks.is_null())
+        || (!ros_response.is_null() && response_callbacks.is_null())
+    {
+        return false;
+    }
+    let mut writer = match CdrWriter::new(&mut *serialized_message) {
+        Ok(writer) => writer,
+        Err(e) => return succeeded(Err(e)),
+    };
+    let result = serialize_service_event(
+        &mut writer,
+        [
+            (request_kind, request_callbacks, ros_request),
+            (response_kind, response_callbacks, ros_response),
+        ],
+        event_type,
+        timestamp,
+        std::slice::from_raw_parts(client_gid, 16),
+        sequence_number,
+    );
+    writer.finish();
+    succeeded(result)
+}
+
+// Serialize the fields of a service event after the encapsulation
+unsafe fn serialize_service_event(
+    writer: &mut CdrWriter,
+    messages: [(rs_type_support_kind_t, *const c_void, *const c_void); 2],
+    event_type: u8,
+    timestamp: i64,
+    client_gid: &[u8],
+    sequence_number: i64,
+) -> Result<(), Error> {
+    writer.write(event_type)?;
+    writer.write((timestamp / 1_000_000_000) as i32)?;
+    writer.write((timestamp % 1_000_000_000) as u32)?;
+    writer.write_bytes(client_gid)?;
+    writer.write(sequence_number)?;
+    // The request and the response are sequences of at most one element
+    for (kind, callbacks, ros_message) in messages {
+        writer.write_length(!ros_message.is_null() as usize)?;
+        if !ros_message.is_null() {
+            serialize_message(
+                writer,
+                &*(callbacks as *const Members),
+                kind == KIND_INTROSPECTION_CPP,
+                ros_message as *const u8,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Reports failures like the C++ functions do: the caller only receives false
+fn succeeded(result: Result<(), Error>) -> bool {
+    result.map_err(|e| tracing::debug!("{e}")).is_ok()
+}
+
+// Find the handle of the C or C++ introspection type support of a type
+unsafe fn find_type_support<T>(
+    type_support: *const T,
+    get_handle: unsafe extern "C" fn(*const T, *const c_char) -> *const T,
+    kind: *mut rs_type_support_kind_t,
+) -> *const T {
+    for (identifier, candidate_kind) in [
+        (INTROSPECTION_C_IDENTIFIER, KIND_INTROSPECTION_C),
+        (INTROSPECTION_CPP_IDENTIFIER, KIND_INTROSPECTION_CPP),
+    ] {
+        let handle = get_handle(type_support, identifier);
+        if !handle.is_null() {
+            *kind = candidate_kind;
+            return handle;
+        }
+        // A missing type support sets the error state, but another one may be found
+        rcutils_reset_error();
+    }
+    null()
+}
+
+// Retrieve the introspection members of the request or response of a service
+unsafe fn get_service_members(
+    type_support: *const rosidl_service_type_support_t,
+    kind: *mut rs_type_support_kind_t,
+    request: bool,
+) -> *const c_void {
+    let handle = find_type_support(type_support, get_service_typesupport_handle, kind);
+    if handle.is_null() {
+        return null();
+    }
+    // The C and C++ service members have the same layout
+    let members = &*((*handle).data as *const rosidl_typesupport_introspection_c__ServiceMembers);
+    if request {
+        members.request_members_ as *const c_void
+    } else {
+        members.response_members_ as *const c_void
+    }
+}
+
+// Returns the members of a nested message
+unsafe fn nested(member: &Member) -> &Members {
+    &*((*member.members_).data as *const Members)
+}
+
+// Returns an error naming the member
+fn member_error(member: &Member, reason: &str) -> Error {
+    let name = str_from_ptr(member.name_).unwrap_or("?");
+    Error::Serialization(format!("member {name}: {reason}"))
+}
+
+// Returns true if the member is a bounded or unbounded sequence, false for fixed arrays
+fn is_sequence(member: &Member) -> bool {
+    member.array_size_ == 0 || member.is_upper_bound_
+}
+
+// Fails if a sequence is longer than its bound
+fn check_bound(member: &Member, size: usize) -> Result<(), Error> {
+    if member.is_upper_bound_ && size > member.array_size_ {
+        return Err(member_error(member, "sequence exceeds its bound"));
+    }
+    Ok(())
+}
+
+// Fails if a string is longer than its bound
+fn check_string_bound(member: &Member, size: usize) -> Result<(), Error> {
+    if member.string_upper_bound_ != 0 && size > member.string_upper_bound_ {
+        return Err(member_error(member, "string exceeds its bound"));
+    }
+    Ok(())
+}
+
+// std::vector<bool> has no addressable elements, so it is accessed through helpers
+fn is_cpp_bool_vector(member: &Member, cpp: bool) -> bool {
+    cpp && member.type_id_ == ROS_TYPE_BOOLEAN && is_sequence(member)
+}
+
+// Returns the size of an element of a C array or sequence
+unsafe fn element_size(member: &Member) -> usize {
+    match member.type_id_ {
+        ROS_TYPE_FLOAT => std::mem::size_of::<f32>(),
+        ROS_TYPE_DOUBLE => std::mem::size_of::<f64>(),
+        ROS_TYPE_LONG_DOUBLE => 16,
+        ROS_TYPE_CHAR | ROS_TYPE_OCTET | ROS_TYPE_UINT8 | ROS_TYPE_INT8 | ROS_TYPE_BOOLEAN => 1,
+        ROS_TYPE_WCHAR | ROS_TYPE_UINT16 | ROS_TYPE_INT16 => 2,
+        ROS_TYPE_UINT32 | ROS_TYPE_INT32 => 4,
+        ROS_TYPE_UINT64 | ROS_TYPE_INT64 => 8,
+        ROS_TYPE_STRING => std::mem::size_of::<rosidl_runtime_c__String>(),
+        ROS_TYPE_WSTRING => std::mem::size_of::<rosidl_runtime_c__U16String>(),
+        ROS_TYPE_MESSAGE => nested(member).size_of_,
+        _ => 0,
+    }
+}
+
+// Returns the number of elements of an array or sequence.
+// C arrays are accessed through their layout, as older generators only provide
+// accessor functions for arrays of messages.
+unsafe fn array_size(member: &Member, cpp: bool, field: *const u8) -> Result<usize, Error> {
+    if cpp {
+        let size_function = member
+            .size_function
+            .ok_or_else(|| member_error(member, "no size function"))?;
+        return Ok(size_function(field as *const c_void));
+    }
+    if is_sequence(member) {
+        return Ok((*(field as *const Sequence)).size);
+    }
+    Ok(member.array_size_)
+}
+
+// Returns an element of an array or sequence
+unsafe fn array_element(
+    member: &Member,
+    cpp: bool,
+    field: *mut u8,
+    index: usize,
+) -> Result<*mut u8, Error> {
+    if cpp {
+        let get_function = member
+            .get_function
+            .ok_or_else(|| member_error(member, "no get function"))?;
+        return Ok(get_function(field as *mut c_void, index) as *mut u8);
+    }
+    let data = if is_sequence(member) {
+        (*(field as *const Sequence)).data as *mut u8
+    } else {
+        field
+    };
+    Ok(data.add(index * element_size(member)))
+}
+
+// Resizes a sequence to `size` elements
+unsafe fn resize_sequence(
+    member: &Member,
+    cpp: bool,
+    field: *mut u8,
+    size: usize,
+) -> Result<(), Error> {
+    if cpp {
+        let resize_function = member
+            .resize_function
+            .ok_or_else(|| member_error(member, "no resize function"))?;
+        let resize_function = std::mem::transmute::<
+            unsafe extern "C" fn(*mut c_void, usize) -> bool,
+            CppResizeFunction,
+        >(resize_function);
+        resize_function(field as *mut c_void, size);
+        return Ok(());
+    }
+    let sequence = &mut *(field as *mut Sequence);
+    // Elements are initialized up to the capacity, so shrinking needs no reallocation
+    if size <= sequence.capacity {
+        sequence.size = size;
+        return Ok(());
+    }
+    macro_rules! resize {
+        ($sequence:ty, $fini:ident, $init:ident) => {{
+            let typed = field as *mut $sequence;
+            $fini(typed);
+            $init(typed, size)
+        }};
+    }
+    let resized = match member.type_id_ {
+        ROS_TYPE_FLOAT => resize!(
+            rosidl_runtime_c__float__Sequence,
+            rosidl_runtime_c__float__Sequence__fini,
+            rosidl_runtime_c__float__Sequence__init
+        ),
+        ROS_TYPE_DOUBLE => resize!(
+            rosidl_runtime_c__double__Sequence,
+            rosidl_runtime_c__double__Sequence__fini,
+            rosidl_runtime_c__double__Sequence__init
+        ),
+        ROS_TYPE_LONG_DOUBLE => resize!(
+            rosidl_runtime_c__long_double__Sequence,
+            rosidl_runtime_c__long_double__Sequence__fini,
+            rosidl_runtime_c__long_double__Sequence__init
+        ),
+        ROS_TYPE_CHAR => resize!(
+            rosidl_runtime_c__char__Sequence,
+            rosidl_runtime_c__char__Sequence__fini,
+            rosidl_runtime_c__char__Sequence__init
+        ),
+        ROS_TYPE_WCHAR => resize!(
+            rosidl_runtime_c__wchar__Sequence,
+            rosidl_runtime_c__wchar__Sequence__fini,
+            rosidl_runtime_c__wchar__Sequence__init
+        ),
+        ROS_TYPE_BOOLEAN => resize!(
+            rosidl_runtime_c__boolean__Sequence,
+            rosidl_runtime_c__boolean__Sequence__fini,
+            rosidl_runtime_c__boolean__Sequence__init
+        ),
+        ROS_TYPE_OCTET => resize!(
+            rosidl_runtime_c__octet__Sequence,
+            rosidl_runtime_c__octet__Sequence__fini,
+            rosidl_runtime_c__octet__Sequence__init
+        ),
+        ROS_TYPE_UINT8 => resize!(
+            rosidl_runtime_c__uint8__Sequence,
+            rosidl_runtime_c__uint8__Sequence__fini,
+            rosidl_runtime_c__uint8__Sequence__init
+        ),
+        ROS_TYPE_INT8 => resize!(
+            rosidl_runtime_c__int8__Sequence,
+            rosidl_runtime_c__int8__Sequence__fini,
+            rosidl_runtime_c__int8__Sequence__init
+        ),
+        ROS_TYPE_UINT16 => resize!(
+            rosidl_runtime_c__uint16__Sequence,
+            rosidl_runtime_c__uint16__Sequence__fini,
+            rosidl_runtime_c__uint16__Sequence__init
+        ),
+        ROS_TYPE_INT16 => resize!(
+            rosidl_runtime_c__int16__Sequence,
+            rosidl_runtime_c__int16__Sequence__fini,
+            rosidl_runtime_c__int16__Sequence__init
+        ),
+        ROS_TYPE_UINT32 => resize!(
+            rosidl_runtime_c__uint32__Sequence,
+            rosidl_runtime_c__uint32__Sequence__fini,
+            rosidl_runtime_c__uint32__Sequence__init
+        ),
+        ROS_TYPE_INT32 => resize!(
+            rosidl_runtime_c__int32__Sequence,
+            rosidl_runtime_c__int32__Sequence__fini,
+            rosidl_runtime_c__int32__Sequence__init
+        ),
+        ROS_TYPE_UINT64 => resize!(
+            rosidl_runtime_c__uint64__Sequence,
+            rosidl_runtime_c__uint64__Sequence__fini,
+            rosidl_runtime_c__uint64__Sequence__init
+        ),
+        ROS_TYPE_INT64 => resize!(
+            rosidl_runtime_c__int64__Sequence,
+            rosidl_runtime_c__int64__Sequence__fini,
+            rosidl_runtime_c__int64__Sequence__init
+        ),
+        ROS_TYPE_STRING => resize!(
+            rosidl_runtime_c__String__Sequence,
+            rosidl_runtime_c__String__Sequence__fini,
+            rosidl_runtime_c__String__Sequence__init
+        ),
+        ROS_TYPE_WSTRING => resize!(
+            rosidl_runtime_c__U16String__Sequence,
+            rosidl_runtime_c__U16String__Sequence__fini,
+            rosidl_runtime_c__U16String__Sequence__init
+        ),
+        ROS_TYPE_MESSAGE => member
+            .resize_function
+            .is_some_and(|resize_function| resize_function(field as *mut c_void, size)),
+        _ => false,
+    };
+    if !resized {
+        return Err(Error::Allocation(format!(
+            "a sequence of {size} elements for {}",
+            str_from_ptr(member.name_).unwrap_or("?")
+        )));
+    }
+    Ok(())
+}
+
+// Returns the bytes of a string field
+unsafe fn string_bytes<'a>(cpp: bool, value: *const u8) -> &'a [u8] {
+    if cpp {
+        let mut size = 0;
+        let data = rs_cpp_string_data(value as *const c_void, &mut size);
+        return std::slice::from_raw_parts(data as *const u8, size);
+    }
+    let string = &*(value as *const rosidl_runtime_c__String);
+    if string.data.is_null() {
+        return &[];
+    }
+    std::slice::from_raw_parts(string.data as *const u8, string.size)
+}
+
+// Returns the characters of a wide string field
+unsafe fn u16string_chars<'a>(cpp: bool, value: *const u8) -> &'a [u16] {
+    if cpp {
+        let mut size = 0;
+        let data = rs_cpp_u16string_data(value as *const c_void, &mut size);
+        return std::slice::from_raw_parts(data, size);
+    }
+    let string = &*(value as *const rosidl_runtime_c__U16String);
+    if string.data.is_null() {
+        return &[];
+    }
+    std::slice::from_raw_parts(string.data, string.size)
+}
+
+// Serialize a single value of a member
+unsafe fn serialize_value(
+    writer: &mut CdrWriter,
+    member: &Member,
+    cpp: bool,
+    value: *const u8,
+) -> Result<(), Error> {
+    match member.type_id_ {
+        ROS_TYPE_FLOAT => writer.write(read_value::<f32>(value)),
+        ROS_TYPE_DOUBLE => writer.write(read_value::<f64>(value)),
+        ROS_TYPE_LONG_DOUBLE => writer.write_long_double(&read_value::<[u8; 16]>(value)),
+        ROS_TYPE_CHAR | ROS_TYPE_OCTET | ROS_TYPE_UINT8 => writer.write(read_value::<u8>(value)),
+        ROS_TYPE_INT8 => writer.write(read_value::<i8>(value)),
+        ROS_TYPE_BOOLEAN => writer.write(read_value::<u8>(value) != 0),
+        ROS_TYPE_WCHAR => writer.write(read_value::<u16>(value) as u32),
+        ROS_TYPE_UINT16 => writer.write(read_value::<u16>(value)),
+        ROS_TYPE_INT16 => writer.write(read_value::<i16>(value)),
+        ROS_TYPE_UINT32 => writer.write(read_value::<u32>(value)),
+        ROS_TYPE_INT32 => writer.write(read_value::<i32>(value)),
+        ROS_TYPE_UINT64 => writer.write(read_value::<u64>(value)),
+        ROS_TYPE_INT64 => writer.write(read_value::<i64>(value)),
+        ROS_TYPE_STRING => {
+            let string = string_bytes(cpp, value);
+            check_string_bound(member, string.len())?;
+            writer.write_string(string)
+        }
+        ROS_TYPE_WSTRING => {
+            let string = u16string_chars(cpp, value);
+            check_string_bound(member, string.len())?;
+            writer.write_wstring(string)
+        }
+        ROS_TYPE_MESSAGE => serialize_message(writer, nested(member), cpp, value),
+        _ => Err(member_error(member, "unknown type")),
+    }
+}
+
+// Deserialize a single value of a member
+unsafe fn deserialize_value(
+    reader: &mut CdrReader,
+    member: &Member,
+    cpp: bool,
+    value: *mut u8,
+) -> Result<(), Error> {
+    match member.type_id_ {
+        ROS_TYPE_FLOAT => write_value(value, reader.read::<f32>()?),
+        ROS_TYPE_DOUBLE => write_value(value, reader.read::<f64>()?),
+        ROS_TYPE_LONG_DOUBLE => write_value(value, reader.read_long_double()?),
+        ROS_TYPE_CHAR | ROS_TYPE_OCTET | ROS_TYPE_UINT8 => write_value(value, reader.read::<u8>()?),
+        ROS_TYPE_INT8 => write_value(value, reader.read::<i8>()?),
+        ROS_TYPE_BOOLEAN => write_value(value, reader.read::<bool>()? as u8),
+        ROS_TYPE_WCHAR => write_value(value, reader.read::<u32>()? as u16),
+        ROS_TYPE_UINT16 => write_value(value, reader.read::<u16>()?),
+        ROS_TYPE_INT16 => write_value(value, reader.read::<i16>()?),
+        ROS_TYPE_UINT32 => write_value(value, reader.read::<u32>()?),
+        ROS_TYPE_INT32 => write_value(value, reader.read::<i32>()?),
+        ROS_TYPE_UINT64 => write_value(value, reader.read::<u64>()?),
+        ROS_TYPE_INT64 => write_value(value, reader.read::<i64>()?),
+        ROS_TYPE_STRING => {
+            let string = reader.read_string()?;
+            check_string_bound(member, string.len())?;
+            let data = string.as_ptr() as *const c_char;
+            let assigned = if cpp {
+                rs_cpp_string_assign(value as *mut c_void, data, string.len())
+            } else {
+                rosidl_runtime_c__String__assignn(
+                    value as *mut rosidl_runtime_c__String,
+                    data,
+                    string.len(),
+                )
+            };
+            if !assigned {
+                return Err(Error::Allocation(format!(
+                    "a string of {} bytes",
+                    string.len()
+                )));
+            }
+            Ok(())
+        }
+        ROS_TYPE_WSTRING => {
+            let mut string = Vec::new();
+            reader.read_wstring(&mut string)?;
+            check_string_bound(member, string.len())?;
+            let assigned = if cpp {
+                rs_cpp_u16string_assign(value as *mut c_void, string.as_ptr(), string.len())
+            } else {
+                rosidl_runtime_c__U16String__assignn(
+                    value as *mut rosidl_runtime_c__U16String,
+                    string.as_ptr(),
+                    string.len(),
+                )
+            };
+            if !assigned {
+                return Err(Error::Allocation(format!(
+                    "a wide string of {} characters",
+                    string.len()
+                )));
+            }
+            Ok(())
+        }
+        ROS_TYPE_MESSAGE => deserialize_message(reader, nested(member), cpp, value),
+        _ => Err(member_error(member, "unknown type")),
+    }
+}
+
+// Serialize a member: a single value, a fixed array or a sequence prefixed by its length
+unsafe fn serialize_member(
+    writer: &mut CdrWriter,
+    member: &Member,
+    cpp: bool,
+    field: *const u8,
+) -> Result<(), Error> {
+    if !member.is_array_ {
+        return serialize_value(writer, member, cpp, field);
+    }
+    if is_cpp_bool_vector(member, cpp) {
+        let size = rs_cpp_bool_vector_size(field as *const c_void);
+        check_bound(member, size)?;
+        writer.write_length(size)?;
+        for i in 0..size {
+            writer.write(rs_cpp_bool_vector_get(field as *const c_void, i))?;
+        }
+        return Ok(());
+    }
+    let size = array_size(member, cpp, field)?;
+    if is_sequence(member) {
+        check_bound(member, size)?;
+        writer.write_length(size)?;
+    }
+    for i in 0..size {
+        let element = array_element(member, cpp, field as *mut u8, i)?;
+        serialize_value(writer, member, cpp, element)?;
+    }
+    Ok(())
+}
+
+// Deserialize a member, resizing sequences to the received length
+unsafe fn deserialize_member(
+    reader: &mut CdrReader,
+    member: &Member,
+    cpp: bool,
+    field: *mut u8,
+) -> Result<(), Error> {
+    if !member.is_array_ {
+        return deserialize_value(reader, member, cpp, field);
+    }
+    if is_cpp_bool_vector(member, cpp) {
+        let size = reader.read_length()?;
+        check_bound(member, size)?;
+        if !rs_cpp_bool_vector_resize(field as *mut c_void, size) {
+            return Err(Error::Allocation(format!("a sequence of {size} booleans")));
+        }
+        for i in 0..size {
+            rs_cpp_bool_vector_set(field as *mut c_void, i, reader.read::<bool>()?);
+        }
+        return Ok(());
+    }
+    let mut size = member.array_size_;
+    if is_sequence(member) {
+        size = reader.read_length()?;
+        check_bound(member, size)?;
+        resize_sequence(member, cpp, field, size)?;
+    }
+    for i in 0..size {
+        let element = array_element(member, cpp, field, i)?;
+        deserialize_value(reader, member, cpp, element)?;
+    }
+    Ok(())
+}
+
+// Serialize every member of a ROS message
+unsafe fn serialize_message(
+    writer: &mut CdrWriter,
+    members: &Members,
+    cpp: bool,
+    ros_message: *const u8,
+) -> Result<(), Error> {
+    for member in std::slice::from_raw_parts(members.members_, members.member_count_ as usize) {
+        serialize_member(
+            writer,
+            member,
+            cpp,
+            ros_message.add(member.offset_ as usize),
+        )?;
+    }
+    Ok(())
+}
+
+// Deserialize every member of a ROS message
+unsafe fn deserialize_message(
+    reader: &mut CdrReader,
+    members: &Members,
+    cpp: bool,
+    ros_message: *mut u8,
+) -> Result<(), Error> {
+    for member in std::slice::from_raw_parts(members.members_, members.member_count_ as usize) {
+        deserialize_member(
+            reader,
+            member,
+            cpp,
+            ros_message.add(member.offset_ as usize),
+        )?;
+    }
+    Ok(())
+}
+
+// Reads a value of a message field
+unsafe fn read_value<T: Copy>(value: *const u8) -> T {
+    std::ptr::read_unaligned(value as *const T)
+}
+
+// Writes a value to a message field
+unsafe fn write_value<T>(value: *mut u8, data: T) -> Result<(), Error> {
+    std::ptr::write_unaligned(value as *mut T, data);
+    Ok(())
+}
+
+// Returns the size and alignment of a primitive member in CDR, None for strings and messages
+fn primitive_size(member: &Member) -> Option<(usize, usize)> {
+    match member.type_id_ {
+        ROS_TYPE_CHAR | ROS_TYPE_OCTET | ROS_TYPE_UINT8 | ROS_TYPE_INT8 | ROS_TYPE_BOOLEAN => {
+            Some((1, 1))
+        }
+        ROS_TYPE_UINT16 | ROS_TYPE_INT16 => Some((2, 2)),
+        ROS_TYPE_FLOAT | ROS_TYPE_WCHAR | ROS_TYPE_UINT32 | ROS_TYPE_INT32 => Some((4, 4)),
+        ROS_TYPE_DOUBLE | ROS_TYPE_UINT64 | ROS_TYPE_INT64 => Some((8, 8)),
+        ROS_TYPE_LONG_DOUBLE => Some((16, 8)),
+        _ => None,
+    }
+}
+
+// Pads an offset from the encapsulation to the alignment, saturating at usize::MAX
+fn align_offset(offset: usize, alignment: usize) -> usize {
+    offset
+        .checked_next_multiple_of(alignment)
+        .unwrap_or(usize::MAX)
+}
+
+// Adds `count` values of `size` bytes to an offset. A size that does not fit in usize
+// saturates the offset and clears `full_bounded`, as the message cannot be preallocated.
+fn add_size(offset: &mut usize, count: usize, size: usize, full_bounded: &mut bool) {
+    match count
+        .checked_mul(size)
+        .and_then(|size| offset.checked_add(size))
+    {
+        Some(sum) => *offset = sum,
+        None => {
+            *offset = usize::MAX;
+            *full_bounded = false;
+        }
+    }
+}
+
+// Adds the largest serialized size of a message, as rosidl_typesupport_fastrtps computes it
+unsafe fn add_max_serialized_size(
+    members: &Members,
+    unbounded_length: usize,
+    offset: &mut usize,
+    full_bounded: &mut bool,
+) {
+    for member in std::slice::from_raw_parts(members.members_, members.member_count_ as usize) {
+        add_max_member_size(member, unbounded_length, offset, full_bounded);
+    }
+}
+
+// Adds the largest serialized size of a member. Unbounded sequences count
+// `unbounded_length` elements, or else count as empty and clear `full_bounded`.
+unsafe fn add_max_member_size(
+    member: &Member,
+    unbounded_length: usize,
+    offset: &mut usize,
+    full_bounded: &mut bool,
+) {
+    let mut count = 1;
+    if member.is_array_ {
+        count = member.array_size_;
+        if is_sequence(member) {
+            *offset = align_offset(*offset, 4);
+            add_size(offset, 1, 4, full_bounded);
+            if !member.is_upper_bound_ {
+                *full_bounded &= unbounded_length != 0;
+                count = unbounded_length;
+            }
+        }
+    }
+    if let Some((size, alignment)) = primitive_size(member) {
+        // The size of every primitive is a multiple of its alignment
+        if count != 0 {
+            *offset = align_offset(*offset, alignment);
+            add_size(offset, count, size, full_bounded);
+        }
+        return;
+    }
+    // Stop counting once the size overflowed
+    for _ in 0..count {
+        if *offset == usize::MAX {
+            *full_bounded = false;
+            break;
+        }
+        add_max_value_size(member, unbounded_length, offset, full_bounded);
+    }
+}
+
+// Adds the largest serialized size of a single string or message. Unbounded strings count
+// `unbounded_length` characters, or else count as empty and clear `full_bounded`.
+unsafe fn add_max_value_size(
+    member: &Member,
+    unbounded_length: usize,
+    offset: &mut usize,
+    full_bounded: &mut bool,
+) {
+    let bound = match member.string_upper_bound_ {
+        0 => unbounded_length,
+        bound => bound,
+    };
+    match member.type_id_ {
+        ROS_TYPE_STRING => {
+            *full_bounded &= bound != 0;
+            *offset = align_offset(*offset, 4);
+            add_size(offset, 1, 4, full_bounded);
+            add_size(offset, bound, 1, full_bounded);
+            add_size(offset, 1, 1, full_bounded);
+        }
+        ROS_TYPE_WSTRING => {
+            *full_bounded &= bound != 0;
+            *offset = align_offset(*offset, 4);
+            add_size(offset, 1, 4, full_bounded);
+            add_size(offset, bound, 4, full_bounded);
+        }
+        ROS_TYPE_MESSAGE => {
+            add_max_serialized_size(nested(member), unbounded_length, offset, full_bounded)
+        }
+        _ => *full_bounded = false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_allocator::{allocations, count_allocation};
+    use std::mem::{offset_of, size_of, zeroed};
+
+    const FIELD_NAME: *const c_char = "field\0".as_ptr() as *const c_char;
+
+    // Describes a field of a C message
+    fn member(type_id: u8, offset: usize) -> Member {
+        let mut member: Member = unsafe { zeroed() };
+        member.name_ = FIELD_NAME;
+        member.type_id_ = type_id;
+        member.offset_ = offset as _;
+        member
+    }
+
+    // Describes a fixed array (`bounded` false) or a sequence of at most `size` elements,
+    // unbounded if `size` is 0
+    fn array(mut member: Member, size: usize, bounded: bool) -> Member {
+        member.is_array_ = true;
+        member.array_size_ = size;
+        member.is_upper_bound_ = bounded;
+        member
+    }
+
+    // Describes a C message of `size_of` bytes; `fields` must outlive the description
+    fn members(fields: &[Member], size_of: usize) -> Members {
+        let mut members: Members = unsafe { zeroed() };
+        members.member_count_ = fields.len() as _;
+        members.members_ = fields.as_ptr();
+        members.size_of_ = size_of;
+        members
+    }
+
+    // Describes a nested message field; `type_support` must outlive the description
+    fn message(offset: usize, type_support: &rosidl_message_type_support_t) -> Member {
+        let mut member = member(ROS_TYPE_MESSAGE, offset);
+        member.members_ = type_support;
+        member
+    }
+
+    fn type_support(members: &Members) -> rosidl_message_type_support_t {
+        let mut type_support: rosidl_message_type_support_t = unsafe { zeroed() };
+        type_support.data = members as *const Members as *const c_void;
+        type_support
+    }
+
+    // Serializes a C message, returning the bytes after the encapsulation header
+    fn serialize<T>(members: &Members, ros_message: &T) -> Option<Vec<u8>> {
+        let allocator = unsafe { rcutils_get_default_allocator() };
+        let mut serialized = rmw_serialized_message_t::new(8, allocator).unwrap();
+        let serialized_ok = unsafe {
+            rs_serialize_message(
+                KIND_INTROSPECTION_C,
+                members as *const Members as *const c_void,
+                ros_message as *const T as *const c_void,
+                &mut serialized,
+            )
+        };
+        let bytes =
+            unsafe { std::slice::from_raw_parts(serialized.buffer, serialized.buffer_length) }
+                .to_vec();
+        serialized.fini();
+        serialized_ok.then(|| {
+            assert_eq!(bytes[..4], [0x00, 0x01, 0x00, 0x00]);
+            bytes[4..].to_vec()
+        })
+    }
+
+    // Deserializes a payload into a C message
+    fn deserialize<T>(members: &Members, payload: &[u8], ros_message: &mut T) -> bool {
+        let mut serialized: rmw_serialized_message_t = unsafe { zeroed() };
+        serialized.buffer = payload.as_ptr() as *mut u8;
+        serialized.buffer_length = payload.len();
+        unsafe {
+            rs_deserialize_message(
+                KIND_INTROSPECTION_C,
+                members as *const Members as *const c_void,
+                &serialized,
+                ros_message as *mut T as *mut c_void,
+            )
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Default, PartialEq)]
+    struct Primitives {
+        flag: bool,
+        octet: u8,
+        short: i16,
+        long: u32,
+        long_long: i64,
+        float: f32,
+        double: f64,
+        character: u8,
+        wide: u16,
+    }
+
+    fn primitives_members() -> Vec<Member> {
+        vec![
+            member(ROS_TYPE_BOOLEAN, offset_of!(Primitives, flag)),
+            member(ROS_TYPE_OCTET, offset_of!(Primitives, octet)),
+            member(ROS_TYPE_INT16, offset_of!(Primitives, short)),
+            member(ROS_TYPE_UINT32, offset_of!(Primitives, long)),
+            member(ROS_TYPE_INT64, offset_of!(Primitives, long_long)),
+            member(ROS_TYPE_FLOAT, offset_of!(Primitives, float)),
+            member(ROS_TYPE_DOUBLE, offset_of!(Primitives, double)),
+            member(ROS_TYPE_CHAR, offset_of!(Primitives, character)),
+            member(ROS_TYPE_WCHAR, offset_of!(Primitives, wide)),
+        ]
+    }
+
+    fn primitives() -> Primitives {
+        Primitives {
+            flag: true,
+            octet: 0xab,
+            short: -2,
+            long: 0x01020304,
+            long_long: -3,
+            float: 0.5,
+            double: 1.5,
+            character: b'A',
+            wide: 0x00e9,
+        }
+    }
+
+    #[rustfmt::skip]
+    const PRIMITIVES_LE: [u8; 40] = [
+        0x01, 0xab, 0xfe, 0xff, 0x04, 0x03, 0x02, 0x01,
+        0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf8, 0x3f,
+        b'A', 0x00, 0x00, 0x00, 0xe9, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn primitives_match_fastcdr() {
+        let fields = primitives_members();
+        let members = members(&fields, size_of::<Primitives>());
+        assert_eq!(serialize(&members, &primitives()).unwrap(), PRIMITIVES_LE);
+
+        let mut payload = vec![0x00, 0x01, 0x00, 0x00];
+        payload.extend_from_slice(&PRIMITIVES_LE);
+        let mut decoded = Primitives::default();
+        assert!(deserialize(&members, &payload, &mut decoded));
+        assert_eq!(decoded, primitives());
+    }
+
+    #[test]
+    fn big_endian_primitives_are_decoded() {
+        let fields = primitives_members();
+        let members = members(&fields, size_of::<Primitives>());
+        #[rustfmt::skip]
+        let payload = [
+            0x00, 0x00, 0x00, 0x00,
+            0x01, 0xab, 0xff, 0xfe, 0x01, 0x02, 0x03, 0x04,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfd,
+            0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            b'A', 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe9,
+        ];
+        let mut decoded = Primitives::default();
+        assert!(deserialize(&members, &payload, &mut decoded));
+        assert_eq!(decoded, primitives());
+    }
+
+    #[test]
+    fn booleans_other_than_zero_and_one_are_rejected() {
+        let fields = [member(ROS_TYPE_BOOLEAN, 0)];
+        let members = members(&fields, 1);
+        let mut decoded = 0u8;
+        assert!(deserialize(
+            &members,
+            &[0x00, 0x01, 0x00, 0x00, 0x01],
+            &mut decoded
+        ));
+        assert_eq!(decoded, 1);
+        assert!(!deserialize(
+            &members,
+            &[0x00, 0x01, 0x00, 0x00, 0x02],
+            &mut decoded
+        ));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn long_doubles_match_fastcdr() {
+        #[repr(C)]
+        struct LongDouble {
+            octet: u8,
+            value: [u8; 16],
+        }
+        let fields = [
+            member(ROS_TYPE_OCTET, offset_of!(LongDouble, octet)),
+            member(ROS_TYPE_LONG_DOUBLE, offset_of!(LongDouble, value)),
+        ];
+        let members = members(&fields, size_of::<LongDouble>());
+        // 1.0 in the x87 extended format
+        let mut value = [0; 16];
+        value[7..10].copy_from_slice(&[0x80, 0xff, 0x3f]);
+        #[rustfmt::skip]
+        assert_eq!(serialize(&members, &LongDouble { octet: 0x07, value }).unwrap(), [
+            0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+            0xff, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+    }
+
+    #[repr(C)]
+    struct Strings {
+        string: rosidl_runtime_c__String,
+        wstring: rosidl_runtime_c__U16String,
+        empty: rosidl_runtime_c__String,
+    }
+
+    #[test]
+    fn strings_match_fastcdr() {
+        let mut string = *b"hi\0";
+        let mut wstring = [0x00e9u16, 0x20ac];
+        let mut strings: Strings = unsafe { zeroed() };
+        strings.string.data = string.as_mut_ptr() as *mut c_char;
+        strings.string.size = 2;
+        strings.wstring.data = wstring.as_mut_ptr();
+        strings.wstring.size = 2;
+        let mut fields = [
+            member(ROS_TYPE_STRING, offset_of!(Strings, string)),
+            member(ROS_TYPE_WSTRING, offset_of!(Strings, wstring)),
+            member(ROS_TYPE_STRING, offset_of!(Strings, empty)),
+        ];
+        let description = members(&fields, size_of::<Strings>());
+        #[rustfmt::skip]
+        assert_eq!(serialize(&description, &strings).unwrap(), [
+            0x03, 0x00, 0x00, 0x00, b'h', b'i', 0x00, 0x00,
+            0x02, 0x00, 0x00, 0x00, 0xe9, 0x00, 0x00, 0x00,
+            0xac, 0x20, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+            0x00,
+        ]);
+
+        // Bounded strings longer than their bound are not serialized
+        fields[0].string_upper_bound_ = 1;
+        let description = members(&fields, size_of::<Strings>());
+        assert!(serialize(&description, &strings).is_none());
+    }
+
+    #[repr(C)]
+    struct Sequences {
+        unbounded: Sequence,
+        bounded: Sequence,
+        fixed: [u32; 2],
+    }
+
+    #[test]
+    fn sequences_match_fastcdr() {
+        let mut unbounded = [1i16, -1, 256];
+        let mut bounded = [7u8, 8, 9, 10];
+        let mut sequences = Sequences {
+            unbounded: Sequence {
+                data: unbounded.as_mut_ptr() as *mut c_void,
+                size: 3,
+                capacity: 3,
+            },
+            bounded: Sequence {
+                data: bounded.as_mut_ptr() as *mut c_void,
+                size: 2,
+                capacity: 4,
+            },
+            fixed: [5, 6],
+        };
+        let fields = [
+            array(
+                member(ROS_TYPE_INT16, offset_of!(Sequences, unbounded)),
+                0,
+                false,
+            ),
+            array(
+                member(ROS_TYPE_UINT8, offset_of!(Sequences, bounded)),
+                3,
+                true,
+            ),
+            array(
+                member(ROS_TYPE_UINT32, offset_of!(Sequences, fixed)),
+                2,
+                false,
+            ),
+        ];
+        let members = members(&fields, size_of::<Sequences>());
+        #[rustfmt::skip]
+        assert_eq!(serialize(&members, &sequences).unwrap(), [
+            0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0xff, 0xff,
+            0x00, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+            0x07, 0x08, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00,
+            0x06, 0x00, 0x00, 0x00,
+        ]);
+
+        // Bounded sequences longer than their bound are not serialized
+        sequences.bounded.size = 4;
+        assert!(serialize(&members, &sequences).is_none());
+    }
+
+    #[test]
+    fn message_bounds_limit_unbounded_members() {
+        let fields = [
+            member(ROS_TYPE_STRING, 0),
+            array(member(ROS_TYPE_INT16, 0), 0, false),
+        ];
+        let members = members(&fields, 0);
+        let max_size = |unbounded_length| {
+            let mut is_bounded = false;
+            let size = unsafe {
+                rs_get_max_serialized_size(
+                    KIND_INTROSPECTION_C,
+                    &members as *const Members as *const c_void,
+                    unbounded_length,
+                    &mut is_bounded,
+                )
+            };
+            (size, is_bounded)
+        };
+        // Encapsulation, then the string length, 3 characters and the NUL,
+        // then the sequence length and 3 elements
+        assert_eq!(max_size(3), (4 + 4 + 3 + 1 + 4 + 3 * 2, true));
+        // Without bounds the contents are not counted
+        assert_eq!(max_size(0), (4 + 4 + 1 + 3 + 4, false));
+        // Bounds too large for the size to fit in usize saturate it instead of wrapping
+        assert_eq!(max_size(usize::MAX), (usize::MAX, false));
+        assert_eq!(max_size(usize::MAX / 2), (usize::MAX, false));
+    }
+
+    #[repr(C)]
+    struct Inner {
+        octet: u8,
+        double: f64,
+    }
+
+    #[repr(C)]
+    struct Outer {
+        flag: bool,
+        inner: Inner,
+        inners: Sequence,
+    }
+
+    #[test]
+    fn nested_messages_match_fastcdr() {
+        let inner_fields = [
+            member(ROS_TYPE_OCTET, offset_of!(Inner, octet)),
+            member(ROS_TYPE_DOUBLE, offset_of!(Inner, double)),
+        ];
+        let inner_members = members(&inner_fields, size_of::<Inner>());
+        let inner_type_support = type_support(&inner_members);
+        let fields = [
+            member(ROS_TYPE_BOOLEAN, offset_of!(Outer, flag)),
+            message(offset_of!(Outer, inner), &inner_type_support),
+            array(
+                message(offset_of!(Outer, inners), &inner_type_support),
+                0,
+                false,
+            ),
+        ];
+        let members = members(&fields, size_of::<Outer>());
+        let mut inners = [Inner {
+            octet: 3,
+            double: -1.0,
+        }];
+        let outer = Outer {
+            flag: true,
+            inner: Inner {
+                octet: 1,
+                double: 2.0,
+            },
+            inners: Sequence {
+                data: inners.as_mut_ptr() as *mut c_void,
+                size: 1,
+                capacity: 1,
+            },
+        };
+        #[rustfmt::skip]
+        assert_eq!(serialize(&members, &outer).unwrap(), [
+            0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40,
+            0x01, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0xbf,
+        ]);
+    }
+
+    // rcutils allocator functions counting their calls, forwarding to the default allocator
+    // held in their state
+    unsafe extern "C" fn allocate(size: usize, state: *mut c_void) -> *mut c_void {
+        count_allocation();
+        let default = &*(state as *const rcutils_allocator_t);
+        default.allocate.unwrap()(size, default.state)
+    }
+
+    unsafe extern "C" fn reallocate(
+        pointer: *mut c_void,
+        size: usize,
+        state: *mut c_void,
+    ) -> *mut c_void {
+        count_allocation();
+        let default = &*(state as *const rcutils_allocator_t);
+        default.reallocate.unwrap()(pointer, size, default.state)
+    }
+
+    unsafe extern "C" fn zero_allocate(
+        number_of_elements: usize,
+        size_of_element: usize,
+        state: *mut c_void,
+    ) -> *mut c_void {
+        count_allocation();
+        let default = &*(state as *const rcutils_allocator_t);
+        default.zero_allocate.unwrap()(number_of_elements, size_of_element, default.state)
+    }
+
+    unsafe extern "C" fn deallocate(pointer: *mut c_void, state: *mut c_void) {
+        let default = &*(state as *const rcutils_allocator_t);
+        default.deallocate.unwrap()(pointer, default.state)
+    }
+
+    #[test]
+    fn serializing_into_a_message_allocation_does_not_allocate() {
+        #[repr(C)]
+        struct Bounded {
+            header: Primitives,
+            name: rosidl_runtime_c__String,
+            values: Sequence,
+        }
+        let header_fields = primitives_members();
+        let header_members = members(&header_fields, size_of::<Primitives>());
+        let header_type_support = type_support(&header_members);
+        let mut name_field = member(ROS_TYPE_STRING, offset_of!(Bounded, name));
+        name_field.string_upper_bound_ = 8;
+        let fields = [
+            message(offset_of!(Bounded, header), &header_type_support),
+            name_field,
+            array(
+                member(ROS_TYPE_DOUBLE, offset_of!(Bounded, values)),
+                4,
+                true,
+            ),
+        ];
+        let members = members(&fields, size_of::<Bounded>());
+        let mut name = *b"zenoh\0";
+        let mut values = [1.0f64, 2.0, 3.0, 4.0];
+        let mut bounded: Bounded = unsafe { zeroed() };
+        bounded.header = primitives();
+        bounded.name.data = name.as_mut_ptr() as *mut c_char;
+        bounded.name.size = 5;
+        bounded.values = Sequence {
+            data: values.as_mut_ptr() as *mut c_void,
+            size: 4,
+            capacity: 4,
+        };
+
+        // The buffer of a message allocation, sized for the largest message of the type
+        let mut is_bounded = false;
+        let max_size = unsafe {
+            rs_get_max_serialized_size(
+                KIND_INTROSPECTION_C,
+                &members as *const Members as *const c_void,
+                0,
+                &mut is_bounded,
+            )
+        };
+        assert!(is_bounded);
+        let mut default = unsafe { rcutils_get_default_allocator() };
+        let mut allocator = default;
+        allocator.allocate = Some(allocate);
+        allocator.reallocate = Some(reallocate);
+        allocator.zero_allocate = Some(zero_allocate);
+        allocator.deallocate = Some(deallocate);
+        allocator.state = &mut default as *mut rcutils_allocator_t as *mut c_void;
+        let mut message_buffer = rmw_serialized_message_t::new(max_size, allocator).unwrap();
+
+        let before = allocations();
+        for _ in 0..3 {
+            let serialized = unsafe {
+                rs_serialize_message(
+                    KIND_INTROSPECTION_C,
+                    &members as *const Members as *const c_void,
+                    &bounded as *const Bounded as *const c_void,
+                    &mut message_buffer,
+                )
+            };
+            assert!(serialized);
+        }
+        assert_eq!(allocations(), before);
+        assert!(message_buffer.buffer_length <= max_size);
+        message_buffer.fini();
+    }
+}
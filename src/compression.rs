@@ -0,0 +1,179 @@
+use std::borrow::Cow;
+use zenoh::bytes::ZBytes;
+
+use crate::Attachment;
+use crate::Error;
+
+// Payloads smaller than this number of bytes are sent raw unless a rule sets another threshold
+const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+// Compression level of zstd, its default trade-off between speed and ratio
+const ZSTD_LEVEL: i32 = 3;
+
+// Compression enum: Codec of a compressed payload, identified in the attachment by its id
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    // Returns the codec of an attachment id
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Compression::Lz4),
+            2 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+    // Returns the attachment id of the codec
+    pub fn id(&self) -> u8 {
+        match self {
+            Compression::Lz4 => 1,
+            Compression::Zstd => 2,
+        }
+    }
+    // Parses a codec name of the transport rules
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lz4" => Some(Compression::Lz4),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+    // Compresses a serialized message. lz4 prepends the uncompressed size, zstd frames record it.
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+            Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL)
+                .map_err(|e| Error::Serialization(format!("zstd compression: {e}"))),
+        }
+    }
+    // Decompresses a received payload
+    pub fn decompress(&self, payload: &ZBytes) -> Result<ZBytes, Error> {
+        let data = payload.to_bytes();
+        let decompressed = match self {
+            Compression::Lz4 => lz4_flex::decompress_size_prepended(&data)
+                .map_err(|e| Error::PayloadRead(format!("lz4 decompression: {e}")))?,
+            Compression::Zstd => zstd::stream::decode_all(&*data)
+                .map_err(|e| Error::PayloadRead(format!("zstd decompression: {e}")))?,
+        };
+        Ok(ZBytes::from(decompressed))
+    }
+}
+
+// Compresses serialized bytes with the codec of a topic if they reach the threshold size and
+// compression makes them smaller; otherwise they are sent raw
+pub fn compress_payload(
+    compression: Option<Compression>,
+    threshold: Option<usize>,
+    data: &[u8],
+) -> Result<Option<ZBytes>, Error> {
+    let Some(compression) = compression else {
+        return Ok(None);
+    };
+    if data.len() < threshold.unwrap_or(DEFAULT_COMPRESSION_THRESHOLD) {
+        return Ok(None);
+    }
+    let compressed = compression.compress(data)?;
+    Ok((compressed.len() < data.len()).then(|| ZBytes::from(compressed)))
+}
+
+// Returns the serialized message of a received payload, decompressed if its attachment names
+// a codec; raw payloads are borrowed
+pub fn decompress_payload<'a>(
+    payload: &'a ZBytes,
+    attachment: &Attachment,
+) -> Result<Cow<'a, ZBytes>, Error> {
+    match attachment.compression {
+        Some(compression) => Ok(Cow::Owned(compression.decompress(payload)?)),
+        None => Ok(Cow::Borrowed(payload)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RMW_GID_STORAGE_SIZE;
+
+    // A compressible serialized message of `size` bytes
+    fn message(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 16) as u8).collect()
+    }
+
+    #[test]
+    fn codecs_round_trip() {
+        let data = message(4096);
+        for compression in [Compression::Lz4, Compression::Zstd] {
+            let compressed = compression.compress(&data).unwrap();
+            assert!(compressed.len() < data.len());
+            let decompressed = compression.decompress(&ZBytes::from(compressed)).unwrap();
+            assert_eq!(decompressed.to_bytes(), data);
+            assert_eq!(Compression::from_id(compression.id()), Some(compression));
+        }
+    }
+
+    #[test]
+    fn payloads_below_the_threshold_are_sent_raw() {
+        let data = message(DEFAULT_COMPRESSION_THRESHOLD - 1);
+        assert!(compress_payload(Some(Compression::Lz4), None, &data)
+            .unwrap()
+            .is_none());
+        let data = message(DEFAULT_COMPRESSION_THRESHOLD);
+        assert!(compress_payload(Some(Compression::Lz4), None, &data)
+            .unwrap()
+            .is_some());
+        // A rule can lower or raise the threshold
+        assert!(
+            compress_payload(Some(Compression::Zstd), Some(256), &message(256))
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            compress_payload(Some(Compression::Zstd), Some(8192), &message(4096))
+                .unwrap()
+                .is_none()
+        );
+        // Topics without a codec are never compressed
+        assert!(compress_payload(None, Some(1), &message(4096))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn incompressible_payloads_are_sent_raw() {
+        // Bytes of a linear congruential generator, which neither codec shrinks
+        let mut state: u32 = 1;
+        let data: Vec<u8> = (0..4096)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 24) as u8
+            })
+            .collect();
+        assert!(compress_payload(Some(Compression::Lz4), Some(1), &data)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn subscriptions_decompress_by_the_attachment_codec() {
+        let data = message(4096);
+        for compression in [Compression::Lz4, Compression::Zstd] {
+            let payload = compress_payload(Some(compression), None, &data)
+                .unwrap()
+                .unwrap();
+            // The codec travels in the attachment, as the publisher sends it
+            let mut attachment = Attachment::new(1, 0, [0; RMW_GID_STORAGE_SIZE as usize]);
+            attachment.compression = Some(compression);
+            let received = Attachment::try_from(&ZBytes::try_from(attachment).unwrap()).unwrap();
+            let decompressed = decompress_payload(&payload, &received).unwrap();
+            assert!(matches!(decompressed, Cow::Owned(_)));
+            assert_eq!(decompressed.to_bytes(), data);
+        }
+        // Raw payloads are read in place
+        let payload = ZBytes::from(data.clone());
+        let attachment = Attachment::new(1, 0, [0; RMW_GID_STORAGE_SIZE as usize]);
+        let raw = decompress_payload(&payload, &attachment).unwrap();
+        assert!(matches!(raw, Cow::Borrowed(_)));
+        assert_eq!(raw.to_bytes(), data);
+    }
+}
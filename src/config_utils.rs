@@ -0,0 +1,489 @@
+use get_if_addrs::get_if_addrs;
+use serde_json::Value;
+use std::net::IpAddr;
+use strum::EnumString;
+
+use crate::Error;
+
+// Port used for static peers that do not specify one
+const DEFAULT_STATIC_PEER_PORT: u16 = 7447;
+
+// DiscoveryRange enum: How far automatic discovery reaches (ROS_AUTOMATIC_DISCOVERY_RANGE)
+#[derive(PartialEq, Clone, Copy, Debug, EnumString)]
+pub enum DiscoveryRange {
+    #[strum(serialize = "OFF")]
+    Off,
+    #[strum(serialize = "LOCALHOST")]
+    Localhost,
+    #[strum(serialize = "SUBNET")]
+    Subnet,
+    #[strum(serialize = "SYSTEM_DEFAULT")]
+    SystemDefault,
+}
+
+// DiscoveryOptions struct: Discovery settings taken from the ROS environment
+pub struct DiscoveryOptions {
+    pub range: DiscoveryRange,
+    pub static_peers: Vec<String>,
+}
+
+impl DiscoveryOptions {
+    // Reads ROS_AUTOMATIC_DISCOVERY_RANGE and ROS_STATIC_PEERS.
+    // `localhost_only` (ROS_LOCALHOST_ONLY) takes precedence over the discovery range.
+    pub fn from_env(localhost_only: bool) -> Result<Self, Error> {
+        Self::new(
+            localhost_only,
+            std::env::var("ROS_AUTOMATIC_DISCOVERY_RANGE")
+                .ok()
+                .as_deref(),
+            std::env::var("ROS_STATIC_PEERS").ok().as_deref(),
+        )
+    }
+
+    // Builds the options from the values of ROS_AUTOMATIC_DISCOVERY_RANGE and ROS_STATIC_PEERS
+    fn new(
+        localhost_only: bool,
+        range: Option<&str>,
+        static_peers: Option<&str>,
+    ) -> Result<Self, Error> {
+        let range = match range {
+            _ if localhost_only => DiscoveryRange::Localhost,
+            Some(value) if !value.is_empty() => value.parse().map_err(|_| {
+                Error::ConfigLoad(format!("invalid ROS_AUTOMATIC_DISCOVERY_RANGE: {value}"))
+            })?,
+            _ => DiscoveryRange::SystemDefault,
+        };
+        let static_peers = static_peers
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|peer| !peer.is_empty())
+            .map(to_static_peer_endpoint)
+            .collect();
+        Ok(DiscoveryOptions {
+            range,
+            static_peers,
+        })
+    }
+}
+
+// Applies the discovery options on top of a Zenoh configuration.
+// SYSTEM_DEFAULT keeps the scouting settings of the configuration file.
+pub fn apply_discovery_options(
+    config: &mut zenoh::Config,
+    options: &DiscoveryOptions,
+) -> Result<(), Error> {
+    match options.range {
+        DiscoveryRange::Off => {
+            // No automatic discovery at all, only the configured and static endpoints
+            insert_config(config, "scouting/multicast/enabled", "false")?;
+            insert_config(config, "scouting/gossip/enabled", "false")?;
+        }
+        DiscoveryRange::Localhost => set_localhost_only(config)?,
+        DiscoveryRange::Subnet => {
+            insert_config(config, "scouting/multicast/enabled", "true")?;
+            insert_config(config, "scouting/gossip/enabled", "true")?;
+        }
+        DiscoveryRange::SystemDefault => {}
+    }
+    // Static peers are added after the range is applied so that they are always reachable
+    if !options.static_peers.is_empty() {
+        append_endpoints(config, "connect/endpoints", &options.static_peers)?;
+    }
+    Ok(())
+}
+
+// Restricts a Zenoh configuration to the local host.
+// Scouting is bound to the loopback interface, listen endpoints are rewritten to loopback
+// addresses, remote connect endpoints are dropped and gossip does not autoconnect.
+pub fn set_localhost_only(config: &mut zenoh::Config) -> Result<(), Error> {
+    // Find the loopback network interface
+    let loopback_if = get_if_addrs()
+        .map_err(|e| Error::ConfigLoad(format!("failed to list the network interfaces: {e}")))?
+        .into_iter()
+        .find(|iface| iface.is_loopback())
+        .ok_or_else(|| Error::ConfigLoad("no loopback network interface".to_string()))?;
+    // Configure Zenoh to use the loopback interface
+    config
+        .scouting
+        .multicast
+        .set_interface(Some(loopback_if.name))
+        .map_err(|_| Error::ConfigLoad("invalid scouting/multicast/interface".to_string()))?;
+    // Bind every listen endpoint to a loopback address
+    map_endpoints(config, "listen/endpoints", |endpoint| {
+        Some(to_loopback_endpoint(endpoint))
+    })?;
+    // Keep only the connect endpoints that are on the local host
+    map_endpoints(config, "connect/endpoints", |endpoint| {
+        is_local_endpoint(endpoint).then(|| endpoint.to_string())
+    })?;
+    // Gossip may advertise the locators of remote peers, so never connect to them
+    insert_config(config, "scouting/gossip/multihop", "false")?;
+    insert_config(config, "scouting/gossip/autoconnect", "[]")?;
+    Ok(())
+}
+
+// Applies `f` to the endpoints at `key`, which may be a list or a per-mode object of lists
+pub(crate) fn map_endpoints<F>(config: &mut zenoh::Config, key: &str, f: F) -> Result<(), Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let json = config
+        .get_json(key)
+        .map_err(|e| Error::ConfigLoad(format!("{key}: {e}")))?;
+    let mut value: Value =
+        serde_json::from_str(&json).map_err(|e| Error::ConfigLoad(format!("{key}: {e}")))?;
+    let map_list = |list: &mut Value| {
+        if let Value::Array(endpoints) = list {
+            *endpoints = endpoints
+                .iter()
+                .filter_map(|endpoint| endpoint.as_str().and_then(&f))
+                .map(Value::String)
+                .collect();
+        }
+    };
+    match &mut value {
+        Value::Object(modes) => modes.values_mut().for_each(map_list),
+        list => map_list(list),
+    }
+    insert_config(config, key, &value.to_string())
+}
+
+// Appends endpoints to `key`, which may be a list or a per-mode object of lists
+fn append_endpoints(
+    config: &mut zenoh::Config,
+    key: &str,
+    endpoints: &[String],
+) -> Result<(), Error> {
+    let json = config
+        .get_json(key)
+        .map_err(|e| Error::ConfigLoad(format!("{key}: {e}")))?;
+    let mut value: Value =
+        serde_json::from_str(&json).map_err(|e| Error::ConfigLoad(format!("{key}: {e}")))?;
+    let append_list = |list: &mut Value| {
+        if !list.is_array() {
+            *list = Value::Array(Vec::new());
+        }
+        if let Value::Array(list) = list {
+            for endpoint in endpoints {
+                let endpoint = Value::String(endpoint.clone());
+                if !list.contains(&endpoint) {
+                    list.push(endpoint);
+                }
+            }
+        }
+    };
+    match &mut value {
+        Value::Object(modes) => modes.values_mut().for_each(append_list),
+        list => append_list(list),
+    }
+    insert_config(config, key, &value.to_string())
+}
+
+// Converts a ROS_STATIC_PEERS entry (a host, `host:port` or a Zenoh locator) to an endpoint
+fn to_static_peer_endpoint(peer: &str) -> String {
+    if peer.contains('/') {
+        return peer.to_string();
+    }
+    let has_port = match peer.rsplit_once(':') {
+        // `[v6]:port` or `v4:port`, but not a bare IPv6 address
+        Some((host, port)) => {
+            port.parse::<u16>().is_ok() && (host.ends_with(']') || !host.contains(':'))
+        }
+        None => false,
+    };
+    if has_port {
+        return format!("tcp/{peer}");
+    }
+    match peer.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("tcp/[{peer}]:{DEFAULT_STATIC_PEER_PORT}"),
+        _ => format!("tcp/{peer}:{DEFAULT_STATIC_PEER_PORT}"),
+    }
+}
+
+// Sets the JSON5 value at `key` of a Zenoh configuration
+pub(crate) fn insert_config(
+    config: &mut zenoh::Config,
+    key: &str,
+    value: &str,
+) -> Result<(), Error> {
+    config
+        .insert_json5(key, value)
+        .map_err(|e| Error::ConfigLoad(format!("{key}: {e}")))
+}
+
+// Returns the path in the environment variable `env`, or else in the `metadata/<field>` string of
+// a Zenoh configuration
+pub(crate) fn metadata_path(config: &zenoh::Config, env: &str, field: &str) -> Option<String> {
+    if let Ok(path) = std::env::var(env) {
+        if !path.is_empty() {
+            return Some(path);
+        }
+    }
+    let metadata: Value = serde_json::from_str(&config.get_json("metadata").ok()?).ok()?;
+    metadata.get(field)?.as_str().map(str::to_string)
+}
+
+// Splits an endpoint `<proto>/<host>:<port>[?metadata][#config]` into protocol, host, port and suffix
+fn split_endpoint(endpoint: &str) -> Option<(&str, &str, &str, &str)> {
+    let (protocol, rest) = endpoint.split_once('/')?;
+    let address_end = rest.find(['?', '#']).unwrap_or(rest.len());
+    let (address, suffix) = rest.split_at(address_end);
+    let (host, port) = address.rsplit_once(':')?;
+    Some((protocol, host, port, suffix))
+}
+
+// Parses the host of an endpoint as an IP address, removing the brackets of IPv6 addresses
+fn parse_host(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .ok()
+}
+
+// Returns true if the endpoint can only be reached from the local host
+fn is_local_endpoint(endpoint: &str) -> bool {
+    let Some((_, host, _, _)) = split_endpoint(endpoint) else {
+        // Endpoints without a host (e.g. unix sockets) are local
+        return !endpoint.contains(':');
+    };
+    host == "localhost" || parse_host(host).is_some_and(|ip| ip.is_loopback())
+}
+
+// Rewrites the host of an endpoint to the loopback address of the same family
+fn to_loopback_endpoint(endpoint: &str) -> String {
+    if is_local_endpoint(endpoint) {
+        return endpoint.to_string();
+    }
+    let Some((protocol, host, port, suffix)) = split_endpoint(endpoint) else {
+        return endpoint.to_string();
+    };
+    let loopback = match parse_host(host) {
+        Some(IpAddr::V6(_)) => "[::1]",
+        _ => "127.0.0.1",
+    };
+    format!("{protocol}/{loopback}:{port}{suffix}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the JSON value at `key` of a Zenoh configuration
+    fn get(config: &zenoh::Config, key: &str) -> Value {
+        serde_json::from_str(&config.get_json(key).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn local_endpoints() {
+        assert!(is_local_endpoint("tcp/127.0.0.1:7447"));
+        assert!(is_local_endpoint("tcp/127.1.2.3:7447"));
+        assert!(is_local_endpoint("udp/[::1]:7447"));
+        assert!(is_local_endpoint("tcp/localhost:7447"));
+        assert!(is_local_endpoint("unixsock-stream//tmp/zenoh.sock"));
+        assert!(!is_local_endpoint("tcp/192.168.1.1:7447"));
+        assert!(!is_local_endpoint("tcp/[fe80::1]:7447"));
+        assert!(!is_local_endpoint("tcp/0.0.0.0:7447"));
+        assert!(!is_local_endpoint("tcp/robot.local:7447"));
+    }
+
+    #[test]
+    fn loopback_endpoints() {
+        assert_eq!(
+            to_loopback_endpoint("tcp/0.0.0.0:7447"),
+            "tcp/127.0.0.1:7447"
+        );
+        assert_eq!(to_loopback_endpoint("tcp/[::]:7447"), "tcp/[::1]:7447");
+        assert_eq!(
+            to_loopback_endpoint("udp/192.168.1.2:7447?iface=eth0#rel=1"),
+            "udp/127.0.0.1:7447?iface=eth0#rel=1"
+        );
+        assert_eq!(
+            to_loopback_endpoint("tcp/localhost:7447"),
+            "tcp/localhost:7447"
+        );
+        assert_eq!(
+            to_loopback_endpoint("unixsock-stream//tmp/zenoh.sock"),
+            "unixsock-stream//tmp/zenoh.sock"
+        );
+    }
+
+    #[test]
+    fn localhost_only_config() {
+        let mut config = zenoh::Config::default();
+        insert_config(
+            &mut config,
+            "listen/endpoints",
+            r#"["tcp/0.0.0.0:7447", "tcp/[::]:7448", "udp/192.168.1.2:7449?iface=eth0",
+                "unixsock-stream//tmp/zenoh.sock"]"#,
+        )
+        .unwrap();
+        insert_config(
+            &mut config,
+            "connect/endpoints",
+            r#"["tcp/127.0.0.1:7447", "tcp/[::1]:7447", "tcp/localhost:7447",
+                "tcp/192.168.1.1:7447", "tcp/[fe80::1]:7447", "tcp/robot.local:7447",
+                "unixsock-stream//tmp/zenoh.sock"]"#,
+        )
+        .unwrap();
+        set_localhost_only(&mut config).unwrap();
+        assert_eq!(
+            get(&config, "listen/endpoints"),
+            serde_json::json!([
+                "tcp/127.0.0.1:7447",
+                "tcp/[::1]:7448",
+                "udp/127.0.0.1:7449?iface=eth0",
+                "unixsock-stream//tmp/zenoh.sock"
+            ])
+        );
+        assert_eq!(
+            get(&config, "connect/endpoints"),
+            serde_json::json!([
+                "tcp/127.0.0.1:7447",
+                "tcp/[::1]:7447",
+                "tcp/localhost:7447",
+                "unixsock-stream//tmp/zenoh.sock"
+            ])
+        );
+        let loopback = get_if_addrs()
+            .unwrap()
+            .into_iter()
+            .find(|iface| iface.is_loopback())
+            .unwrap();
+        assert_eq!(
+            get(&config, "scouting/multicast/interface"),
+            Value::String(loopback.name)
+        );
+        assert_eq!(get(&config, "scouting/gossip/multihop"), Value::Bool(false));
+        let autoconnect = get(&config, "scouting/gossip/autoconnect").to_string();
+        assert!(!autoconnect.contains("peer") && !autoconnect.contains("router"));
+    }
+
+    #[test]
+    fn discovery_range_scouting() {
+        let scouting = |range| {
+            let mut config = zenoh::Config::default();
+            let options = DiscoveryOptions {
+                range,
+                static_peers: Vec::new(),
+            };
+            apply_discovery_options(&mut config, &options).unwrap();
+            (
+                get(&config, "scouting/multicast/enabled"),
+                get(&config, "scouting/gossip/enabled"),
+            )
+        };
+        assert_eq!(
+            scouting(DiscoveryRange::Off),
+            (Value::Bool(false), Value::Bool(false))
+        );
+        assert_eq!(
+            scouting(DiscoveryRange::Subnet),
+            (Value::Bool(true), Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn static_peer_endpoints() {
+        assert_eq!(
+            to_static_peer_endpoint("192.168.1.1"),
+            "tcp/192.168.1.1:7447"
+        );
+        assert_eq!(
+            to_static_peer_endpoint("192.168.1.1:7448"),
+            "tcp/192.168.1.1:7448"
+        );
+        assert_eq!(
+            to_static_peer_endpoint("robot.local"),
+            "tcp/robot.local:7447"
+        );
+        assert_eq!(
+            to_static_peer_endpoint("robot.local:7448"),
+            "tcp/robot.local:7448"
+        );
+        assert_eq!(to_static_peer_endpoint("fe80::1"), "tcp/[fe80::1]:7447");
+        assert_eq!(to_static_peer_endpoint("[fe80::1]"), "tcp/[fe80::1]:7447");
+        assert_eq!(
+            to_static_peer_endpoint("[fe80::1]:7448"),
+            "tcp/[fe80::1]:7448"
+        );
+        assert_eq!(to_static_peer_endpoint("::1"), "tcp/[::1]:7447");
+        assert_eq!(
+            to_static_peer_endpoint("udp/192.168.1.1:7448"),
+            "udp/192.168.1.1:7448"
+        );
+        assert_eq!(
+            to_static_peer_endpoint("tls/robot.local:7448?iface=eth0"),
+            "tls/robot.local:7448?iface=eth0"
+        );
+    }
+
+    #[test]
+    fn discovery_options_precedence() {
+        // ROS_LOCALHOST_ONLY takes precedence over the discovery range
+        let options = DiscoveryOptions::new(true, Some("SUBNET"), None).unwrap();
+        assert_eq!(options.range, DiscoveryRange::Localhost);
+        let options = DiscoveryOptions::new(false, Some("SUBNET"), None).unwrap();
+        assert_eq!(options.range, DiscoveryRange::Subnet);
+        let options = DiscoveryOptions::new(false, Some("OFF"), None).unwrap();
+        assert_eq!(options.range, DiscoveryRange::Off);
+        // Unset or empty ranges keep the configuration file
+        let options = DiscoveryOptions::new(false, Some(""), None).unwrap();
+        assert_eq!(options.range, DiscoveryRange::SystemDefault);
+        let options = DiscoveryOptions::new(false, None, None).unwrap();
+        assert_eq!(options.range, DiscoveryRange::SystemDefault);
+        assert!(DiscoveryOptions::new(false, Some("subnet"), None).is_err());
+        assert!(DiscoveryOptions::new(true, Some("invalid"), None).is_ok());
+    }
+
+    #[test]
+    fn static_peers_are_appended() {
+        let options = DiscoveryOptions::new(
+            false,
+            Some("OFF"),
+            Some(" 192.168.1.1 ;;[fe80::1]:7448; tcp/127.0.0.1:7447"),
+        )
+        .unwrap();
+        assert_eq!(
+            options.static_peers,
+            vec![
+                "tcp/192.168.1.1:7447",
+                "tcp/[fe80::1]:7448",
+                "tcp/127.0.0.1:7447"
+            ]
+        );
+        let mut config = zenoh::Config::default();
+        insert_config(
+            &mut config,
+            "connect/endpoints",
+            r#"["tcp/127.0.0.1:7447"]"#,
+        )
+        .unwrap();
+        apply_discovery_options(&mut config, &options).unwrap();
+        assert_eq!(
+            get(&config, "connect/endpoints"),
+            serde_json::json!([
+                "tcp/127.0.0.1:7447",
+                "tcp/192.168.1.1:7447",
+                "tcp/[fe80::1]:7448"
+            ])
+        );
+        assert_eq!(
+            get(&config, "scouting/multicast/enabled"),
+            Value::Bool(false)
+        );
+    }
+
+    #[test]
+    fn config_override_takes_precedence() {
+        let mut config = zenoh::Config::default();
+        let options = DiscoveryOptions::new(false, Some("OFF"), None).unwrap();
+        apply_discovery_options(&mut config, &options).unwrap();
+        crate::Context::apply_config_overrides(&mut config, "scouting/multicast/enabled=true")
+            .unwrap();
+        assert_eq!(
+            get(&config, "scouting/multicast/enabled"),
+            Value::Bool(true)
+        );
+    }
+}
@@ -1,11 +1,31 @@
 use ament_rs::Ament;
-use get_if_addrs::get_if_addrs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::Arc;
+use std::time::Duration;
 use zenoh::Wait;
 
+use crate::config_utils::{apply_discovery_options, insert_config, DiscoveryOptions};
+use crate::logging::{log_info, log_warn};
+use crate::qos_overrides::QosOverrides;
 use crate::rmw::rcutils_allocator_t;
+use crate::rmw::rmw_security_options_t;
+use crate::security::{apply_security_options, SecurityOptions};
+use crate::transport_rules::TransportRules;
+use crate::Error;
+use crate::WaitSetSignal;
+
+// Configuration file of the session, overridable with ZENOH_SESSION_CONFIG_URI
+pub const SESSION_CONFIG_ENV: &str = "ZENOH_SESSION_CONFIG_URI";
+pub const DEFAULT_SESSION_CONFIG: &str = "DEFAULT_RMW_ZENOH_SESSION_CONFIG.json5";
+// Configuration file of the router, overridable with ZENOH_ROUTER_CONFIG_URI
+pub const ROUTER_CONFIG_ENV: &str = "ZENOH_ROUTER_CONFIG_URI";
+pub const DEFAULT_ROUTER_CONFIG: &str = "DEFAULT_RMW_ZENOH_ROUTER_CONFIG.json5";
+// Number of router connectivity checks at init (unset or negative: no check, 0: wait forever)
+const ROUTER_CHECK_ATTEMPTS_ENV: &str = "ZENOH_ROUTER_CHECK_ATTEMPTS";
+const ROUTER_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Overrides applied on top of the configuration file, formatted as `key/path=value;...`
+const CONFIG_OVERRIDE_ENV: &str = "ZENOH_CONFIG_OVERRIDE";
 
 // The Context struct represents the execution context of the middleware
 pub struct Context {
@@ -14,7 +34,9 @@ pub struct Context {
     pub domain_id: usize,
     pub enclave: String,
     pub allocator: rcutils_allocator_t,
-    pub wait_set_cv: Arc<(Mutex<()>, Condvar)>,
+    pub wait_set_signal: Arc<WaitSetSignal>,
+    pub transport_rules: TransportRules,
+    pub qos_overrides: QosOverrides,
 }
 
 impl Context {
@@ -23,42 +45,161 @@ impl Context {
         domain_id: usize,
         localhost_only: bool,
         enclave: &str,
+        security_options: &rmw_security_options_t,
         allocator: rcutils_allocator_t,
-    ) -> Result<Self, ()> {
-        // Attempt to get the path to the configuration file
-        let config_path = Self::get_config_path()?;
-        // Load the Zenoh configuration from the file
-        let mut config = zenoh::Config::from_file(config_path).map_err(|_| ())?;
-        if localhost_only {
-            // If localhost-only is enabled, find the loopback network interface
-            let loopback_if = get_if_addrs()
-                .map_err(|_| ())?
-                .into_iter()
-                .find(|iface| iface.is_loopback())
-                .ok_or(())?;
-            // Configure Zenoh to use the loopback interface
-            config
-                .scouting
-                .multicast
-                .set_interface(Some(loopback_if.name))
-                .map_err(|_| ())?;
-        }
+    ) -> Result<Self, Error> {
+        // Load the Zenoh configuration with the discovery settings and overrides from the environment
+        let mut config =
+            Self::load_config(SESSION_CONFIG_ENV, DEFAULT_SESSION_CONFIG, localhost_only)?;
+        // Load the per-topic transport settings referenced by the environment or the configuration
+        let transport_rules = TransportRules::load(&config)?;
+        // Load the QoS overrides referenced by the environment or the configuration
+        let qos_overrides = QosOverrides::load(&config)?;
+        // Authenticate the session with the enclave material; applied last so it cannot be overridden
+        apply_security_options(
+            &mut config,
+            &SecurityOptions::new(security_options, enclave),
+        )?;
         // Open a Zenoh session with the configured settings
-        let session = zenoh::open(config).wait().map_err(|_| ())?;
+        let session = zenoh::open(config)
+            .wait()
+            .map_err(|e| Error::SessionOpen(e.to_string()))?;
+        // Wait until discovery through a router is possible
+        Self::wait_for_router(&session)?;
         Ok(Self {
             next_node_id: AtomicUsize::new(0),
             session,
             domain_id,
             enclave: enclave.to_string(),
             allocator,
-            wait_set_cv: Arc::new((Mutex::new(()), Condvar::new())),
+            wait_set_signal: Arc::new(WaitSetSignal::new()),
+            transport_rules,
+            qos_overrides,
         })
     }
 
-    // Retrieves the path to the Zenoh configuration file
-    fn get_config_path() -> Result<PathBuf, ()> {
+    // Waits until the session is connected to at least one router. Progress is logged through
+    // rcutils directly, so that it is shown even if tracing events are not forwarded.
+    fn wait_for_router(session: &zenoh::Session) -> Result<(), Error> {
+        let Ok(attempts) = std::env::var(ROUTER_CHECK_ATTEMPTS_ENV) else {
+            return Ok(());
+        };
+        let Ok(attempts) = attempts.trim().parse::<i64>() else {
+            return Err(Error::SessionOpen(format!(
+                "invalid value of {ROUTER_CHECK_ATTEMPTS_ENV}: {attempts}"
+            )));
+        };
+        if attempts < 0 {
+            return Ok(());
+        }
+        let mut attempt = 0;
+        loop {
+            if let Some(router) = session.info().routers_zid().wait().next() {
+                log_info(&format!("Connected to the Zenoh router {router}"));
+                return Ok(());
+            }
+            attempt += 1;
+            if attempts != 0 && attempt >= attempts {
+                return Err(Error::SessionOpen(format!(
+                    "unable to connect to a Zenoh router after {attempt} attempts"
+                )));
+            }
+            log_warn(&format!(
+                "Unable to connect to a Zenoh router (attempt {attempt}). \
+                 Have you started a router with `ros2 run rmw_zenoh_rs rmw_zenohd`?"
+            ));
+            std::thread::sleep(ROUTER_CHECK_INTERVAL);
+        }
+    }
+
+    // Loads a Zenoh configuration file and applies the settings from the environment.
+    // The ROS discovery variables take precedence over the file and ZENOH_CONFIG_OVERRIDE over both.
+    pub fn load_config(
+        env_var: &str,
+        default_file_name: &str,
+        localhost_only: bool,
+    ) -> Result<zenoh::Config, Error> {
+        let config_path = Self::get_config_path(env_var, default_file_name)?;
+        let mut config = zenoh::Config::from_file(&config_path)
+            .map_err(|e| Error::ConfigLoad(format!("{}: {e}", config_path.display())))?;
+        apply_discovery_options(&mut config, &DiscoveryOptions::from_env(localhost_only)?)?;
+        if let Ok(overrides) = std::env::var(CONFIG_OVERRIDE_ENV) {
+            Self::apply_config_overrides(&mut config, &overrides)?;
+        }
+        Ok(config)
+    }
+
+    // Applies `key/path=value;...` overrides to a Zenoh configuration
+    pub fn apply_config_overrides(
+        config: &mut zenoh::Config,
+        overrides: &str,
+    ) -> Result<(), Error> {
+        for item in Self::split_config_overrides(overrides)? {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = item.split_once('=') else {
+                return Err(Error::ConfigLoad(format!(
+                    "invalid entry in {CONFIG_OVERRIDE_ENV}: {item}"
+                )));
+            };
+            insert_config(config, key.trim(), value.trim())?;
+        }
+        Ok(())
+    }
+
+    // Splits overrides on `;`, ignoring separators inside quotes, arrays and objects.
+    // A backslash escapes the next character of a quoted string.
+    fn split_config_overrides(overrides: &str) -> Result<Vec<&str>, Error> {
+        let invalid =
+            |reason: &str| Error::ConfigLoad(format!("{reason} in {CONFIG_OVERRIDE_ENV}"));
+        let mut items = Vec::new();
+        let mut depth: usize = 0;
+        let mut quote = None;
+        let mut escaped = false;
+        let mut start = 0;
+        for (i, c) in overrides.char_indices() {
+            match (quote, c) {
+                (Some(_), _) if escaped => escaped = false,
+                (Some(_), '\\') => escaped = true,
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '[' | '{') => depth += 1,
+                (None, ']' | '}') => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| invalid("unbalanced brackets"))?
+                }
+                (None, ';') if depth == 0 => {
+                    items.push(&overrides[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if quote.is_some() {
+            return Err(invalid("unterminated quote"));
+        }
+        if depth != 0 {
+            return Err(invalid("unbalanced brackets"));
+        }
+        items.push(&overrides[start..]);
+        Ok(items)
+    }
+
+    // Retrieves the path to a Zenoh configuration file
+    pub fn get_config_path(env_var: &str, default_file_name: &str) -> Result<PathBuf, Error> {
+        let config_uri = std::env::var(env_var).unwrap_or(default_file_name.to_string());
+        // Absolute paths and file URIs are used as they are
+        let config_file = config_uri.strip_prefix("file://").unwrap_or(&config_uri);
+        if Path::new(config_file).is_absolute() {
+            return Ok(PathBuf::from(config_file));
+        }
         // Create a new Ament instance for locating package directories
-        let ament = Ament::new().map_err(|_| ())?;
+        let ament = Ament::new()
+            .map_err(|_| Error::ConfigLoad("AMENT_PREFIX_PATH is not set".to_string()))?;
         let mut config_path = PathBuf::new();
         // Append the share directory of the `rmw_zenoh_rs` package to the path
         config_path.push(
@@ -67,11 +208,8 @@ impl Context {
                 .unwrap_or(".".to_string().into()),
         );
         config_path.push("config");
-        // Append the configuration file name from the environment variable or use a default value
-        config_path.push(
-            std::env::var("ZENOH_SESSION_CONFIG_URI")
-                .unwrap_or("DEFAULT_RMW_ZENOH_SESSION_CONFIG.json5".to_string()),
-        );
+        // Append the relative configuration file name
+        config_path.push(config_file);
         Ok(config_path)
     }
 
@@ -80,3 +218,76 @@ impl Context {
         return self.next_node_id.fetch_add(1, Ordering::Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_config_overrides_on_top_level_separators() {
+        assert_eq!(
+            Context::split_config_overrides("mode=\"client\";scouting/multicast/enabled=false")
+                .unwrap(),
+            vec!["mode=\"client\"", "scouting/multicast/enabled=false"]
+        );
+        assert_eq!(Context::split_config_overrides("").unwrap(), vec![""]);
+    }
+
+    #[test]
+    fn split_config_overrides_keeps_nested_values() {
+        let overrides = "connect/endpoints=[\"tcp/[::1]:7447\",\"tcp/127.0.0.1:7448\"];\
+                         transport/link={tx:{queue:{size:{data:[1,2];}}}};mode='peer'";
+        assert_eq!(
+            Context::split_config_overrides(overrides).unwrap(),
+            vec![
+                "connect/endpoints=[\"tcp/[::1]:7447\",\"tcp/127.0.0.1:7448\"]",
+                "transport/link={tx:{queue:{size:{data:[1,2];}}}}",
+                "mode='peer'",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_config_overrides_ignores_quoted_and_escaped_separators() {
+        assert_eq!(
+            Context::split_config_overrides(r#"id="a;b";metadata={name:"x\";y"};mode='c;]'"#)
+                .unwrap(),
+            vec![r#"id="a;b""#, r#"metadata={name:"x\";y"}"#, "mode='c;]'"]
+        );
+    }
+
+    #[test]
+    fn split_config_overrides_rejects_malformed_input() {
+        for overrides in [
+            "mode=\"client;listen/endpoints=[]",
+            r#"id="a\""#,
+            "listen/endpoints=[\"tcp/0.0.0.0:0\"",
+            "listen/endpoints=]",
+            "metadata={a:{b:1}",
+        ] {
+            assert!(
+                matches!(
+                    Context::split_config_overrides(overrides),
+                    Err(Error::ConfigLoad(_))
+                ),
+                "{overrides}"
+            );
+        }
+    }
+
+    #[test]
+    fn apply_config_overrides_sets_each_entry() {
+        let mut config = zenoh::Config::default();
+        Context::apply_config_overrides(
+            &mut config,
+            "mode=\"client\"; connect/endpoints=[\"tcp/127.0.0.1:7448\"] ;",
+        )
+        .unwrap();
+        assert_eq!(config.get_json("mode").unwrap(), "\"client\"");
+        assert_eq!(
+            config.get_json("connect/endpoints").unwrap(),
+            "[\"tcp/127.0.0.1:7448\"]"
+        );
+        assert!(Context::apply_config_overrides(&mut config, "mode").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Downsampler struct: Keeps one sample per interval of a subscription and counts the others.
+// Dropped samples are counted apart from the ones a full queue evicts, which are losses.
+pub struct Downsampler {
+    interval: u64,
+    origin: Instant,
+    next: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Downsampler {
+    // Creates a downsampler keeping the first sample received after each interval
+    pub fn new(interval: Duration) -> Self {
+        Downsampler {
+            interval: interval.as_nanos() as u64,
+            origin: Instant::now(),
+            next: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+    // Returns true if a sample received now is kept. Kept samples stay on the interval grid while
+    // the stream is steady, and the grid restarts after a gap longer than the interval.
+    pub fn keep(&self) -> bool {
+        self.keep_at(self.origin.elapsed().as_nanos() as u64)
+    }
+    // Returns true if a sample received `now` nanoseconds after the creation is kept
+    fn keep_at(&self, now: u64) -> bool {
+        let next = self.next.load(Ordering::Relaxed);
+        let following = if now < next {
+            None
+        } else if now - next < self.interval {
+            Some(next + self.interval)
+        } else {
+            Some(now + self.interval)
+        };
+        // A concurrent sample may have claimed the same slot first
+        let kept = following.is_some_and(|following| {
+            self.next
+                .compare_exchange(next, following, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        });
+        if !kept {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        kept
+    }
+    // Returns the number of samples dropped since the downsampler was created
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn one_sample_is_kept_per_interval() {
+        let downsampler = Downsampler::new(Duration::from_millis(200));
+        // A 100 Hz stream for one second
+        let kept = (0..100)
+            .filter(|i| downsampler.keep_at(i * 10 * MS))
+            .count();
+        assert_eq!(kept, 5);
+        assert_eq!(downsampler.dropped(), 95);
+    }
+
+    #[test]
+    fn kept_samples_stay_on_the_interval_grid() {
+        let downsampler = Downsampler::new(Duration::from_millis(10));
+        assert!(downsampler.keep_at(0));
+        assert!(!downsampler.keep_at(5 * MS));
+        assert!(downsampler.keep_at(10 * MS));
+        assert!(!downsampler.keep_at(15 * MS));
+        // A late sample does not shift the grid
+        assert!(downsampler.keep_at(25 * MS));
+        assert!(!downsampler.keep_at(29 * MS));
+        assert!(downsampler.keep_at(30 * MS));
+        // The grid restarts after a gap
+        assert!(downsampler.keep_at(100 * MS));
+        assert!(!downsampler.keep_at(105 * MS));
+        assert!(downsampler.keep_at(110 * MS));
+        assert_eq!(downsampler.dropped(), 4);
+    }
+
+    #[test]
+    fn samples_claiming_the_same_slot_are_kept_once() {
+        let downsampler = Downsampler::new(Duration::from_millis(10));
+        assert!(downsampler.keep_at(0));
+        assert!(!downsampler.keep_at(0));
+        assert_eq!(downsampler.dropped(), 1);
+    }
+}
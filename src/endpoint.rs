@@ -1,6 +1,6 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::sync::atomic::AtomicI64;
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 use zenoh::Wait;
 
@@ -8,11 +8,14 @@ use crate::rmw::rmw_qos_profile_t;
 use crate::rmw::rmw_serialized_message_t;
 use crate::EndpointInfo;
 use crate::EntityType;
+use crate::Error;
 use crate::EventCallback;
 use crate::EventMap;
 use crate::GraphCache;
 use crate::Node;
+use crate::RecvQueue;
 use crate::TypeSupport;
+use crate::WaitSetSignal;
 
 use crate::rmw::rmw_qos_history_policy_e_RMW_QOS_POLICY_HISTORY_KEEP_LAST as HISTORY_KEEP_LAST;
 
@@ -25,8 +28,8 @@ pub struct Endpoint<T> {
     pub message_buffer: Mutex<rmw_serialized_message_t>,
     pub send_type_support: Option<TypeSupport>,
     pub recv_type_support: Option<TypeSupport>,
-    pub wait_set_cv: Arc<(Mutex<()>, Condvar)>,
-    pub recv_fifo: Arc<Mutex<VecDeque<(i64, T)>>>,
+    pub wait_set_signal: Arc<WaitSetSignal>,
+    pub recv_fifo: RecvQueue<(i64, T)>,
     pub on_recv_callback: Arc<Mutex<EventCallback>>,
     #[allow(dead_code)]
     liveliness: zenoh::liveliness::LivelinessToken,
@@ -41,7 +44,7 @@ impl<T> Endpoint<T> {
         send_type_support: Option<TypeSupport>,
         recv_type_support: Option<TypeSupport>,
         qos: rmw_qos_profile_t,
-    ) -> Result<Self, ()> {
+    ) -> Result<Self, Error> {
         // Clone the node's information and configure the endpoint
         let mut info = node.info.clone();
         info.entity_id = node.generate_entity_id();
@@ -56,21 +59,36 @@ impl<T> Endpoint<T> {
             info.endpoint_type = type_support.type_name.clone();
         }
 
+        // Size the message buffer for the largest bounded message, so that bounded
+        // types are (de)serialized without reallocating it. Publishers serialize into
+        // their payloads instead, so their buffer stays empty.
+        let send_buffer_type_support = send_type_support
+            .as_ref()
+            .filter(|_| info.entity_type != EntityType::Publisher);
+        let buffer_capacity = [send_buffer_type_support, recv_type_support.as_ref()]
+            .into_iter()
+            .flatten()
+            .filter_map(TypeSupport::max_serialized_size)
+            .max()
+            .unwrap_or(0);
+
         // Create the endpoint instance
         let key_expr = info.to_string();
-        let initial_capacity = info.qos.depth as usize;
+        let keep_last = info.qos.history == HISTORY_KEEP_LAST;
+        let depth = info.qos.depth;
         let endpoint = Endpoint {
             info,
             graph_cache: node.graph_cache.clone(),
             sequence_number: AtomicI64::new(1),
             events: Mutex::new(HashMap::new()),
-            message_buffer: Mutex::new(
-                rmw_serialized_message_t::new(0, node.context.allocator.clone()).map_err(|_| ())?,
-            ),
+            message_buffer: Mutex::new(rmw_serialized_message_t::new(
+                buffer_capacity,
+                node.context.allocator.clone(),
+            )?),
             send_type_support,
             recv_type_support,
-            wait_set_cv: node.context.wait_set_cv.clone(),
-            recv_fifo: Arc::new(Mutex::new(VecDeque::with_capacity(initial_capacity))),
+            wait_set_signal: node.context.wait_set_signal.clone(),
+            recv_fifo: RecvQueue::new(keep_last, depth),
             on_recv_callback: Arc::new(Mutex::new((
                 None::<unsafe extern "C" fn(*const ::std::os::raw::c_void, usize)>,
                 0,
@@ -79,20 +97,30 @@ impl<T> Endpoint<T> {
                 .context
                 .session
                 .liveliness()
-                .declare_token(key_expr)
+                .declare_token(key_expr.clone())
                 .wait()
-                .map_err(|_| ())?,
+                .map_err(|e| Error::KeyExpr(format!("{key_expr}: {e}")))?,
         };
         Ok(endpoint)
     }
 
+    // Returns the type support used to serialize outgoing messages
+    pub fn get_send_type_support(&self) -> Result<&TypeSupport, Error> {
+        self.send_type_support
+            .as_ref()
+            .ok_or_else(|| Error::TypeSupport("no type support to send".to_string()))
+    }
+
+    // Returns the type support used to deserialize incoming messages
+    pub fn get_recv_type_support(&self) -> Result<&TypeSupport, Error> {
+        self.recv_type_support
+            .as_ref()
+            .ok_or_else(|| Error::TypeSupport("no type support to receive".to_string()))
+    }
+
     // Checks if the receive FIFO is empty
     pub fn is_empty(&self) -> bool {
-        if let Ok(fifo) = self.recv_fifo.lock() {
-            fifo.is_empty()
-        } else {
-            true
-        }
+        self.recv_fifo.is_empty()
     }
 
     // Pushes received data into the FIFO queue
@@ -102,19 +130,21 @@ impl<T> Endpoint<T> {
             Err(_) => 0,
         };
 
-        if let Ok(mut fifo) = self.recv_fifo.lock() {
-            // If QoS is keep-last and the queue is full, remove the oldest message
-            if (self.info.qos.history == HISTORY_KEEP_LAST) && fifo.len() >= self.info.qos.depth {
-                fifo.pop_front();
-            }
-            // Add the new message with a timestamp
-            fifo.push_back((timestamp, data));
-            // Notify waiting threads
-            let (_, cvar) = &*self.wait_set_cv;
-            cvar.notify_all();
-        } else {
-            return;
+        // Add the new message with a timestamp; a full keep-last queue drops its oldest message
+        if self.recv_fifo.push((timestamp, data)) {
+            tracing::debug!(
+                "{}: dropped the oldest message ({} so far)",
+                self.info.endpoint_name,
+                self.recv_fifo.evictions()
+            );
         }
+        self.notify_recv();
+    }
+
+    // Signals new data to the waiting threads and to the on-receive callback
+    pub fn notify_recv(&self) {
+        // Wake the threads waiting on the context, without locking if there are none
+        self.wait_set_signal.notify();
         // Invoke the on-receive callback if it is set
         if let Ok(callback) = self.on_recv_callback.lock() {
             if let (Some(func), userdata) = *callback {
@@ -130,11 +160,7 @@ impl<T> Endpoint<T> {
 
     // Takes a message from the FIFO queue
     pub fn take_message(&self) -> Option<(i64, T)> {
-        if let Ok(mut fifo) = self.recv_fifo.lock() {
-            fifo.pop_front()
-        } else {
-            None
-        }
+        self.recv_fifo.pop()
     }
 }
 
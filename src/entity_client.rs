@@ -1,38 +1,144 @@
+use crossbeam_queue::SegQueue;
+use std::collections::HashMap;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::time::{SystemTime, UNIX_EPOCH};
 use zenoh::bytes::ZBytes;
+use zenoh::query::QueryTarget;
 use zenoh::query::Reply;
 use zenoh::Wait;
 
+use crate::contiguous_payload;
 use crate::read_payload;
 use crate::rmw::rmw_qos_profile_t;
 use crate::rmw::rmw_service_info_t;
+use crate::to_writer_guid;
 use crate::Attachment;
+use crate::DepartureListener;
 use crate::Endpoint;
+use crate::EndpointInfo;
 use crate::EntityType;
+use crate::Error;
 use crate::Node;
+use crate::ServiceEventType;
+use crate::ServiceIntrospection;
 use crate::TypeSupport;
 use crate::WaitSetTrait;
+use crate::RMW_GID_STORAGE_SIZE;
+
+// GID of a server
+type Gid = [u8; RMW_GID_STORAGE_SIZE as usize];
+
+// Pending requests, mapping each sequence number to the GIDs of the servers it was sent to:
+// those known when it was sent, which all receive it since the querier targets every server
+type PendingRequests = Arc<Mutex<HashMap<i64, Vec<Gid>>>>;
+
+// Removes a server that left the graph from the pending requests, and returns the sequence
+// numbers of the requests whose recorded servers have all left. A server a request was not
+// sent to changes nothing, and requests sent while no server was known are left for Zenoh
+// to finalize.
+fn remove_departed_server(pending_requests: &mut HashMap<i64, Vec<Gid>>, gid: &Gid) -> Vec<i64> {
+    let mut orphaned = Vec::new();
+    pending_requests.retain(|sequence_number, servers| {
+        let known = !servers.is_empty();
+        servers.retain(|server| server != gid);
+        if known && servers.is_empty() {
+            orphaned.push(*sequence_number);
+            return false;
+        }
+        true
+    });
+    orphaned
+}
+
+// FailedRequests struct: Sequence numbers of the requests that can no longer be answered.
+// They are queued apart from the replies, so that a KEEP_LAST reply queue neither evicts
+// them nor evicts a reply for them.
+#[derive(Default)]
+struct FailedRequests {
+    queue: SegQueue<i64>,
+}
+
+impl FailedRequests {
+    // Queues a request that can no longer be answered
+    fn push(&self, sequence_number: i64) {
+        self.queue.push(sequence_number);
+    }
+    // Returns an error naming the oldest failed request, if any
+    fn take(&self, endpoint_name: &str) -> Result<(), Error> {
+        match self.queue.pop() {
+            Some(sequence_number) => Err(Error::Transport(format!(
+                "{endpoint_name}: request {sequence_number} was dropped before being answered"
+            ))),
+            None => Ok(()),
+        }
+    }
+    // Checks if no failed request is left to report
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+// Guard moved into the reply callback of a request.
+// Zenoh drops the callback when the query is finalized, so a request that is still
+// pending at that point has been dropped without any reply.
+struct PendingRequestGuard {
+    sequence_number: i64,
+    pending_requests: PendingRequests,
+    failed_requests: Arc<FailedRequests>,
+    endpoint: Arc<Endpoint<Reply>>,
+}
+
+impl PendingRequestGuard {
+    // Forwards a reply and marks the request as answered
+    fn on_reply(&self, reply: Reply) {
+        if let Ok(mut pending_requests) = self.pending_requests.lock() {
+            pending_requests.remove(&self.sequence_number);
+        }
+        self.endpoint.push_recv_data(reply);
+    }
+}
+
+impl Drop for PendingRequestGuard {
+    fn drop(&mut self) {
+        let removed = match self.pending_requests.lock() {
+            Ok(mut pending_requests) => pending_requests.remove(&self.sequence_number).is_some(),
+            Err(_) => false,
+        };
+        if removed {
+            self.failed_requests.push(self.sequence_number);
+            self.endpoint.notify_recv();
+        }
+    }
+}
 
 // Client struct: Represents a ROS 2 client entity
 pub struct Client<'a> {
     client: zenoh::query::Querier<'a>,
+    pending_requests: PendingRequests,
+    failed_requests: Arc<FailedRequests>,
+    #[allow(dead_code)]
+    departure_listener: Arc<DepartureListener>,
     pub endpoint: Arc<Endpoint<Reply>>,
+    pub introspection: ServiceIntrospection<'a>,
 }
 
 impl<'a> Client<'a> {
     // Constructor for creating a new Client instance
     pub fn new(
-        node: &mut Node,
+        node: &mut Node<'a>,
         endpoint_name: &str,
         request_type_support: TypeSupport,
         response_type_support: TypeSupport,
         mut qos: rmw_qos_profile_t,
-    ) -> Result<Self, ()> {
-        // Ensure default QoS settings are applied
+    ) -> Result<Self, Error> {
+        // Replace the requested QoS by the first matching override, then apply the defaults
+        node.context
+            .qos_overrides
+            .apply(&node.fully_qualified_name(), endpoint_name, &mut qos);
         qos.set_default_profile();
+        let introspection = ServiceIntrospection::new(node, endpoint_name, &request_type_support)?;
         let endpoint = Arc::new(Endpoint::new(
             node,
             EntityType::Client,
@@ -43,21 +149,58 @@ impl<'a> Client<'a> {
         )?);
         // Generate the key expression for the endpoint
         let key_expr = endpoint.info.get_endpoint_keyexpr();
-        let client = node
-            .context
-            .session
-            .declare_querier(key_expr)
+        // Apply the transport settings of the first rule matching the service
+        let transport = node.context.transport_rules.get(endpoint_name);
+        let client = transport
+            .apply(node.context.session.declare_querier(key_expr.clone()))
+            .target(QueryTarget::All)
             .timeout(Duration::MAX)
             .wait()
-            .map_err(|_| ())?;
-        Ok(Client { client, endpoint })
+            .map_err(|e| Error::KeyExpr(format!("{key_expr}: {e}")))?;
+        // Fail the pending requests whose servers have all left the graph, from the liveliness
+        // callback of the graph cache rather than from the wait set
+        let pending_requests: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        let failed_requests = Arc::new(FailedRequests::default());
+        let departure_listener: Arc<DepartureListener> = {
+            let pending_requests = pending_requests.clone();
+            let failed_requests = failed_requests.clone();
+            let endpoint = endpoint.clone();
+            Arc::new(move |info: &EndpointInfo| {
+                if info.entity_type != EntityType::Service
+                    || info.endpoint_name != endpoint.info.endpoint_name
+                {
+                    return;
+                }
+                let orphaned = match pending_requests.lock() {
+                    Ok(mut pending_requests) => {
+                        remove_departed_server(&mut pending_requests, &info.get_gid())
+                    }
+                    Err(_) => return,
+                };
+                for sequence_number in &orphaned {
+                    failed_requests.push(*sequence_number);
+                }
+                if !orphaned.is_empty() {
+                    endpoint.notify_recv();
+                }
+            })
+        };
+        node.graph_cache.add_departure_listener(&departure_listener);
+        Ok(Client {
+            client,
+            pending_requests,
+            failed_requests,
+            departure_listener,
+            endpoint,
+            introspection,
+        })
     }
 
     // Sends a request to the service
-    pub fn send_request(&self, ros_request: *const ::std::os::raw::c_void) -> Result<i64, ()> {
+    pub fn send_request(&self, ros_request: *const ::std::os::raw::c_void) -> Result<i64, Error> {
         // Serialize Message
-        let mut msg = self.endpoint.message_buffer.lock().map_err(|_| ())?;
-        let type_support = self.endpoint.send_type_support.as_ref().ok_or(())?;
+        let mut msg = self.endpoint.message_buffer.lock()?;
+        let type_support = self.endpoint.get_send_type_support()?;
         type_support.serialize(ros_request, &mut *msg)?;
         // Increment the sequence number
         let seq = self
@@ -73,52 +216,183 @@ impl<'a> Client<'a> {
             self.endpoint.info.get_gid(),
         )
         .try_into()?;
+        // Link the request to the servers currently in the graph, which the query is routed to
+        let servers = self
+            .get_servers()
+            .iter()
+            .map(|info| info.get_gid())
+            .collect();
+        self.pending_requests.lock()?.insert(seq, servers);
+        let guard = PendingRequestGuard {
+            sequence_number: seq,
+            pending_requests: self.pending_requests.clone(),
+            failed_requests: self.failed_requests.clone(),
+            endpoint: self.endpoint.clone(),
+        };
         // Send request
         let payload = unsafe { std::slice::from_raw_parts(msg.buffer, msg.buffer_length) };
-        let endpoint = self.endpoint.clone();
         self.client
             .get()
             .payload(payload)
             .attachment(attachment)
             .callback(move |reply| {
-                endpoint.push_recv_data(reply);
+                guard.on_reply(reply);
             })
             .wait()
-            .map_err(|_| ())?;
+            .map_err(|e| {
+                Error::Transport(format!(
+                    "query on {}: {e}",
+                    self.endpoint.info.endpoint_name
+                ))
+            })?;
+        // Publish the introspection event; a failure here does not affect the request
+        let _ = self.introspection.publish(
+            ServiceEventType::RequestSent,
+            &to_writer_guid(self.endpoint.info.get_gid()),
+            seq,
+            type_support,
+            ros_request,
+        );
         Ok(seq)
     }
-    // Takes a response from the service
+    // Takes a response from the service. A request that can no longer be answered is
+    // reported as an error, since rcl has no other way to fail it.
     pub fn take_response(
         &self,
         request_header: *mut rmw_service_info_t,
         ros_response: *mut ::std::os::raw::c_void,
-    ) -> Result<bool, ()> {
-        // Attempt to take a message from the endpoint
-        let Some(data) = self.endpoint.take_message() else {
+    ) -> Result<bool, Error> {
+        self.failed_requests
+            .take(&self.endpoint.info.endpoint_name)?;
+        // Attempt to take a reply from the endpoint
+        let Some((received_timestamp, reply)) = self.endpoint.take_message() else {
             // Return false if no message is available
             return Ok(false);
         };
-        // Deserialize the response into the ROS message
-        let mut msg = self.endpoint.message_buffer.lock().map_err(|_| ())?;
-        let type_support = self.endpoint.recv_type_support.as_ref().ok_or(())?;
-        let result = data.1.result().map_err(|_| ())?;
-        read_payload(result.payload(), &mut msg)?;
-        type_support.deserialize(&*msg, ros_response)?;
-        // Set the received timestamp
         let request_header = unsafe { &mut *request_header };
-        request_header.received_timestamp = data.0;
+        // Deserialize the response into the ROS message, in place if the payload is contiguous
+        let type_support = self.endpoint.get_recv_type_support()?;
+        let result = reply.result().map_err(|e| {
+            Error::Transport(format!(
+                "error reply: {}",
+                String::from_utf8_lossy(&e.payload().to_bytes())
+            ))
+        })?;
+        if let Some(payload) = contiguous_payload(result.payload()) {
+            type_support.deserialize_slice(payload, ros_response)?;
+        } else {
+            let mut msg = self.endpoint.message_buffer.lock()?;
+            read_payload(result.payload(), &mut msg)?;
+            type_support.deserialize(&*msg, ros_response)?;
+        }
+        // Set the received timestamp
+        request_header.received_timestamp = received_timestamp;
         // Parse the attachment
-        let attachment: Attachment = result.attachment().ok_or(())?.try_into()?;
+        let attachment: Attachment = result
+            .attachment()
+            .ok_or_else(|| Error::AttachmentDecode("missing attachment".to_string()))?
+            .try_into()?;
         request_header.source_timestamp = attachment.source_timestamp;
         request_header.request_id.sequence_number = attachment.sequence_number;
         request_header.request_id.writer_guid = attachment.source_gid;
+        // Publish the introspection event; a failure here does not affect the response
+        let _ = self.introspection.publish(
+            ServiceEventType::ResponseReceived,
+            &to_writer_guid(self.endpoint.info.get_gid()),
+            attachment.sequence_number,
+            type_support,
+            ros_response,
+        );
         Ok(true)
     }
+    // Retrieves the servers of this service from the graph cache
+    fn get_servers(&self) -> Vec<EndpointInfo> {
+        self.endpoint.graph_cache.get_endpoint_list(
+            "",
+            "",
+            &self.endpoint.info.endpoint_name,
+            &[EntityType::Service],
+        )
+    }
 }
 
 // Implements WaitSetTrait for the Client
 impl<'a> WaitSetTrait for Client<'a> {
     fn is_empty(&self) -> bool {
-        self.endpoint.is_empty()
+        self.endpoint.is_empty() && self.failed_requests.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecvQueue;
+
+    #[test]
+    fn departed_server_fails_requests_without_other_servers() {
+        let (a, b) = (
+            [1; RMW_GID_STORAGE_SIZE as usize],
+            [2; RMW_GID_STORAGE_SIZE as usize],
+        );
+        let mut pending_requests = HashMap::from([(1, vec![a]), (2, vec![a, b]), (3, vec![])]);
+        assert_eq!(remove_departed_server(&mut pending_requests, &a), vec![1]);
+        assert_eq!(pending_requests.get(&2), Some(&vec![b]));
+        // Requests sent while no server was known are left to Zenoh
+        assert_eq!(pending_requests.get(&3), Some(&vec![]));
+        assert_eq!(remove_departed_server(&mut pending_requests, &b), vec![2]);
+        assert_eq!(pending_requests.len(), 1);
+    }
+
+    #[test]
+    fn departed_server_that_was_never_queried_fails_no_request() {
+        let (queried, other) = (
+            [1; RMW_GID_STORAGE_SIZE as usize],
+            [2; RMW_GID_STORAGE_SIZE as usize],
+        );
+        let mut pending_requests = HashMap::from([(1, vec![queried])]);
+        // A server that joined after the request was sent leaves while it is pending
+        assert!(remove_departed_server(&mut pending_requests, &other).is_empty());
+        assert_eq!(pending_requests.get(&1), Some(&vec![queried]));
+        assert_eq!(
+            remove_departed_server(&mut pending_requests, &queried),
+            vec![1]
+        );
+        assert!(pending_requests.is_empty());
+    }
+
+    #[test]
+    fn failed_requests_are_reported_once_in_order() {
+        let failed_requests = FailedRequests::default();
+        assert!(failed_requests.take("/service").is_ok());
+        failed_requests.push(1);
+        failed_requests.push(2);
+        assert!(!failed_requests.is_empty());
+        for sequence_number in [1, 2] {
+            match failed_requests.take("/service") {
+                Err(Error::Transport(message)) => assert_eq!(
+                    message,
+                    format!(
+                        "/service: request {sequence_number} was dropped before being answered"
+                    )
+                ),
+                _ => panic!("request {sequence_number} was not reported"),
+            }
+        }
+        assert!(failed_requests.is_empty());
+        assert!(failed_requests.take("/service").is_ok());
+    }
+
+    #[test]
+    fn failed_requests_do_not_evict_replies() {
+        // The reply queue of a client with a KEEP_LAST depth of 1
+        let replies = RecvQueue::new(true, 1);
+        let failed_requests = FailedRequests::default();
+        assert!(!replies.push("reply"));
+        failed_requests.push(1);
+        failed_requests.push(2);
+        assert!(failed_requests.take("/service").is_err());
+        assert!(failed_requests.take("/service").is_err());
+        assert!(failed_requests.take("/service").is_ok());
+        assert_eq!(replies.pop(), Some("reply"));
     }
 }
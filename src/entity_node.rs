@@ -6,6 +6,7 @@ use crate::rmw::rmw_guard_condition_t;
 use crate::Context;
 use crate::EndpointInfo;
 use crate::EntityType;
+use crate::Error;
 use crate::GraphCache;
 
 // Node struct: Represents a ROS 2 node
@@ -21,7 +22,7 @@ pub struct Node<'a> {
 
 impl<'a> Node<'a> {
     // Constructor for creating a new Node instance
-    pub fn new(context: &'a mut Context, namespace: &str, node_name: &str) -> Result<Self, ()> {
+    pub fn new(context: &'a mut Context, namespace: &str, node_name: &str) -> Result<Self, Error> {
         // Initialize EndpointInfo with node-specific metadata
         let mut info = EndpointInfo::default();
         info.domain_id = context.domain_id;
@@ -44,11 +45,19 @@ impl<'a> Node<'a> {
             liveliness_token: context
                 .session
                 .liveliness()
-                .declare_token(key_expr)
+                .declare_token(key_expr.clone())
                 .wait()
-                .map_err(|_| ())?,
+                .map_err(|e| Error::KeyExpr(format!("{key_expr}: {e}")))?,
         })
     }
+    // Returns the namespace joined with the node name
+    pub fn fully_qualified_name(&self) -> String {
+        if self.info.namespace.ends_with('/') {
+            format!("{}{}", self.info.namespace, self.info.node_name)
+        } else {
+            format!("{}/{}", self.info.namespace, self.info.node_name)
+        }
+    }
     // Generates a unique entity ID by incrementing the counter atomically
     pub fn generate_entity_id(&mut self) -> usize {
         return self.next_entity_id.fetch_add(1, Ordering::Relaxed);
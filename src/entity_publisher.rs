@@ -5,14 +5,22 @@ use zenoh::bytes::ZBytes;
 use zenoh::Wait;
 use zenoh_ext::{AdvancedPublisher, AdvancedPublisherBuilderExt, CacheConfig};
 
+use crate::compress_payload;
+use crate::history_depth;
 use crate::rmw::rmw_qos_durability_policy_e_RMW_QOS_POLICY_DURABILITY_TRANSIENT_LOCAL as DURABILITY_TRANSIENT_LOCAL;
 use crate::rmw::rmw_qos_profile_t;
 use crate::rmw::rmw_serialized_message_t;
 use crate::Attachment;
+use crate::Compression;
 use crate::Endpoint;
 use crate::EntityType;
+use crate::Error;
+use crate::MessageAllocation;
 use crate::Node;
+use crate::PayloadBuffer;
+use crate::TransportSettings;
 use crate::TypeSupport;
+use crate::RMW_GID_STORAGE_SIZE;
 
 // Enum to represent two types of Zenoh publishers
 enum PublisherEnum<'a> {
@@ -23,6 +31,9 @@ enum PublisherEnum<'a> {
 // Publisher struct: Represents a ROS 2 publisher entity
 pub struct Publisher<'a> {
     publisher: PublisherEnum<'a>,
+    transport: TransportSettings,
+    // GID sent in the attachment of every message, hashed once
+    gid: [u8; RMW_GID_STORAGE_SIZE as usize],
     pub endpoint: Arc<Endpoint<()>>,
 }
 
@@ -33,8 +44,11 @@ impl<'a> Publisher<'a> {
         endpoint_name: &str,
         type_support: TypeSupport,
         mut qos: rmw_qos_profile_t,
-    ) -> Result<Self, ()> {
-        // Ensure default QoS settings are applied
+    ) -> Result<Self, Error> {
+        // Replace the requested QoS by the first matching override, then apply the defaults
+        node.context
+            .qos_overrides
+            .apply(&node.fully_qualified_name(), endpoint_name, &mut qos);
         qos.set_default_profile();
         let endpoint = Arc::new(Endpoint::new(
             node,
@@ -46,73 +60,140 @@ impl<'a> Publisher<'a> {
         )?);
         // Generate the key expression for the endpoint
         let key_expr = endpoint.info.get_publisher_keyexpr();
+        let gid = endpoint.info.get_gid();
+        let declare_error = |e: zenoh::Error| Error::KeyExpr(format!("{key_expr}: {e}"));
+        // Apply the transport settings of the first rule matching the topic
+        let transport = node.context.transport_rules.get(endpoint_name);
+        let mut builder = transport.apply(node.context.session.declare_publisher(key_expr.clone()));
+        if let Some(reliability) = transport.reliability {
+            builder = builder.reliability(reliability);
+        }
         // Check if durability is set to Transient Local
         if qos.durability == DURABILITY_TRANSIENT_LOCAL {
-            // Create an advanced publisher with caching
+            // Create an advanced publisher caching the depth of KEEP_LAST or a bounded KEEP_ALL history
             Ok(Publisher {
                 publisher: PublisherEnum::AdvancedPublisher(
-                    node.context
-                        .session
-                        .declare_publisher(key_expr)
-                        .cache(CacheConfig::default().max_samples(qos.depth))
+                    builder
+                        .cache(CacheConfig::default().max_samples(history_depth(&qos)))
                         .wait()
-                        .map_err(|_| ())?,
+                        .map_err(declare_error)?,
                 ),
+                transport,
+                gid,
                 endpoint,
             })
         } else {
             // Create a standard publisher without caching
             Ok(Publisher {
-                publisher: PublisherEnum::Publisher(
-                    node.context
-                        .session
-                        .declare_publisher(key_expr)
-                        .wait()
-                        .map_err(|_| ())?,
-                ),
+                publisher: PublisherEnum::Publisher(builder.wait().map_err(declare_error)?),
+                transport,
+                gid,
                 endpoint,
             })
         }
     }
-    // Publishes a ROS message
-    pub fn publish(&self, ros_message: *const ::std::os::raw::c_void) -> Result<(), ()> {
-        // Serialize the ROS message
-        let mut msg = self.endpoint.message_buffer.lock().map_err(|_| ())?;
-        let type_support = self.endpoint.send_type_support.as_ref().ok_or(())?;
-        type_support.serialize(ros_message, &mut *msg)?;
-        // Publish the serialized message
-        self.publish_serialized_message(&*msg)
+    // Publishes a ROS message, serialized into payload buffers of the allocation if one is
+    // given so that no heap allocation is made once it is warmed up. Otherwise it is
+    // serialized into new buffers, so concurrent calls do not wait on each other.
+    // In both cases the payload shares the buffer, since Zenoh keeps it after the call for
+    // local subscriptions and the history cache.
+    pub fn publish(
+        &self,
+        ros_message: *const ::std::os::raw::c_void,
+        allocation: Option<&mut MessageAllocation>,
+    ) -> Result<(), Error> {
+        let type_support = self.endpoint.get_send_type_support()?;
+        let serialize = |msg| type_support.serialize(ros_message, msg);
+        match allocation {
+            Some(allocation) => {
+                let (payload, attachment) = allocation.payload_buffers();
+                self.publish_into(payload, attachment, serialize)
+            }
+            None => self.publish_with(serialize),
+        }
+    }
+    // Publishes a message serialized by `serialize` into a new payload buffer
+    pub fn publish_with(
+        &self,
+        serialize: impl FnOnce(*mut rmw_serialized_message_t) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.publish_into(
+            &mut PayloadBuffer::default(),
+            &mut PayloadBuffer::default(),
+            serialize,
+        )
+    }
+    // Publishes a message serialized by `serialize` into `payload`, or its compression if
+    // that is smaller, with its attachment encoded into `attachment`
+    fn publish_into(
+        &self,
+        payload: &mut PayloadBuffer,
+        attachment: &mut PayloadBuffer,
+        serialize: impl FnOnce(*mut rmw_serialized_message_t) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let serialized = payload.serialize(serialize)?;
+        match self.compress(serialized)? {
+            Some(compressed) => self.put(compressed, self.transport.compression, attachment),
+            None => self.put(payload.to_payload(), None, attachment),
+        }
     }
     // Publishes a serialized message
-    pub fn publish_serialized_message(&self, msg: &rmw_serialized_message_t) -> Result<(), ()> {
+    pub fn publish_serialized_message(&self, msg: &rmw_serialized_message_t) -> Result<(), Error> {
         if msg.buffer.is_null() || msg.buffer_length == 0 {
-            return Err(());
+            return Err(Error::InvalidArgument(
+                "empty serialized message".to_string(),
+            ));
+        }
+        let data = unsafe { std::slice::from_raw_parts(msg.buffer, msg.buffer_length) };
+        let mut attachment = PayloadBuffer::default();
+        match self.compress(data)? {
+            Some(compressed) => self.put(compressed, self.transport.compression, &mut attachment),
+            None => self.put(ZBytes::from(data.to_vec()), None, &mut attachment),
         }
-        // Create an attachment with metadata
-        let attachment: ZBytes = Attachment::new(
+    }
+    // Compresses serialized bytes with the codec and threshold of the topic
+    fn compress(&self, data: &[u8]) -> Result<Option<ZBytes>, Error> {
+        compress_payload(
+            self.transport.compression,
+            self.transport.compression_threshold,
+            data,
+        )
+    }
+    // Sends a payload with its attachment through the Zenoh publisher
+    fn put(
+        &self,
+        payload: ZBytes,
+        compression: Option<Compression>,
+        attachment_buffer: &mut PayloadBuffer,
+    ) -> Result<(), Error> {
+        // Create an attachment with metadata and the codec of a compressed payload
+        let mut attachment = Attachment::new(
             self.endpoint
                 .sequence_number
                 .fetch_add(1, Ordering::Relaxed),
             SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .map_or(0, |v| v.as_nanos() as i64),
-            self.endpoint.info.get_gid(),
-        )
-        .try_into()?;
+            self.gid,
+        );
+        attachment.compression = compression;
+        attachment.encode_into(attachment_buffer)?;
 
         // Publish the message using the appropriate publisher
-        let payload = unsafe { std::slice::from_raw_parts(msg.buffer, msg.buffer_length) };
+        let put_error = |e: zenoh::Error| {
+            Error::Transport(format!("put on {}: {e}", self.endpoint.info.endpoint_name))
+        };
         match &self.publisher {
             PublisherEnum::Publisher(publisher) => publisher
                 .put(payload)
-                .attachment(attachment)
+                .attachment(attachment_buffer.to_payload())
                 .wait()
-                .map_or_else(|_| Err(()), |_| Ok(())),
+                .map_err(put_error),
             PublisherEnum::AdvancedPublisher(publisher) => publisher
                 .put(payload)
-                .attachment(attachment)
+                .attachment(attachment_buffer.to_payload())
                 .wait()
-                .map_or_else(|_| Err(()), |_| Ok(())),
+                .map_err(put_error),
         }
     }
 }
@@ -7,6 +7,7 @@ use zenoh::bytes::ZBytes;
 use zenoh::query::Query;
 use zenoh::Wait;
 
+use crate::contiguous_payload;
 use crate::read_payload;
 use crate::rmw::rmw_qos_profile_t;
 use crate::rmw::rmw_request_id_t;
@@ -14,7 +15,11 @@ use crate::rmw::rmw_service_info_t;
 use crate::Attachment;
 use crate::Endpoint;
 use crate::EntityType;
+use crate::Error;
 use crate::Node;
+use crate::ServiceEventType;
+use crate::ServiceIntrospection;
+use crate::TransportSettings;
 use crate::TypeSupport;
 use crate::WaitSetTrait;
 
@@ -29,24 +34,30 @@ impl rmw_request_id_t {
 }
 
 // Service struct: Represents a ROS 2 service entity
-pub struct Service {
+pub struct Service<'a> {
     #[allow(dead_code)]
     service: zenoh::query::Queryable<()>,
     query_map: Arc<Mutex<HashMap<u64, zenoh::query::Query>>>,
+    transport: TransportSettings,
     pub endpoint: Arc<Endpoint<Query>>,
+    pub introspection: ServiceIntrospection<'a>,
 }
 
-impl Service {
+impl<'a> Service<'a> {
     // Constructor for creating a new Service instance
     pub fn new(
-        node: &mut Node,
+        node: &mut Node<'a>,
         endpoint_name: &str,
         request_type_support: TypeSupport,
         response_type_support: TypeSupport,
         mut qos: rmw_qos_profile_t,
-    ) -> Result<Self, ()> {
-        // Ensure default QoS settings are applied
+    ) -> Result<Self, Error> {
+        // Replace the requested QoS by the first matching override, then apply the defaults
+        node.context
+            .qos_overrides
+            .apply(&node.fully_qualified_name(), endpoint_name, &mut qos);
         qos.set_default_profile();
+        let introspection = ServiceIntrospection::new(node, endpoint_name, &request_type_support)?;
         let endpoint = Arc::new(Endpoint::new(
             node,
             EntityType::Service,
@@ -61,16 +72,19 @@ impl Service {
         let service = node
             .context
             .session
-            .declare_queryable(key_expr)
+            .declare_queryable(key_expr.clone())
             .callback(move |query| {
                 endpoint_clone.push_recv_data(query);
             })
             .wait()
-            .map_err(|_| ())?;
+            .map_err(|e| Error::KeyExpr(format!("{key_expr}: {e}")))?;
         Ok(Service {
             service,
             query_map: Arc::new(Mutex::new(HashMap::new())),
+            // Transport settings of the first rule matching the service, applied to the responses
+            transport: node.context.transport_rules.get(endpoint_name),
             endpoint,
+            introspection,
         })
     }
 
@@ -79,15 +93,20 @@ impl Service {
         &self,
         request_header: *mut rmw_request_id_t,
         ros_response: *mut ::std::os::raw::c_void,
-    ) -> Result<(), ()> {
+    ) -> Result<(), Error> {
         // Serialize Message
-        let mut msg = self.endpoint.message_buffer.lock().map_err(|_| ())?;
-        let type_support = self.endpoint.send_type_support.as_ref().ok_or(())?;
+        let mut msg = self.endpoint.message_buffer.lock()?;
+        let type_support = self.endpoint.get_send_type_support()?;
         type_support.serialize(ros_response, &mut *msg)?;
         // Retrieve the query associated with the request
         let request_header = unsafe { &*request_header };
-        let mut map = self.query_map.lock().map_err(|_| ())?;
-        let query = map.remove(&request_header.get_hash()).ok_or(())?;
+        let mut map = self.query_map.lock()?;
+        let query = map.remove(&request_header.get_hash()).ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "no pending request with sequence number {}",
+                request_header.sequence_number
+            ))
+        })?;
         // Create an attachment with metadata
         let attachment: ZBytes = Attachment::new(
             request_header.sequence_number,
@@ -99,45 +118,78 @@ impl Service {
         .try_into()?;
         // Send response
         let payload = unsafe { std::slice::from_raw_parts(msg.buffer, msg.buffer_length) };
-        query
-            .reply(query.key_expr(), payload)
+        self.transport
+            .apply(query.reply(query.key_expr(), payload))
             .attachment(attachment)
             .wait()
-            .map_or_else(|_| Err(()), |_| Ok(()))
+            .map_err(|e| {
+                Error::Transport(format!(
+                    "reply on {}: {e}",
+                    self.endpoint.info.endpoint_name
+                ))
+            })?;
+        // Publish the introspection event; a failure here does not affect the response
+        let _ = self.introspection.publish(
+            ServiceEventType::ResponseSent,
+            &request_header.writer_guid,
+            request_header.sequence_number,
+            type_support,
+            ros_response,
+        );
+        Ok(())
     }
     // Takes a request from the client
     pub fn take_request(
         &self,
         request_header: *mut rmw_service_info_t,
         ros_request: *mut ::std::os::raw::c_void,
-    ) -> Result<bool, ()> {
+    ) -> Result<bool, Error> {
         // Attempt to take a message from the endpoint
         let Some(data) = self.endpoint.take_message() else {
             return Ok(false);
         };
-        // Deserialize the response into the ROS message
-        let mut msg = self.endpoint.message_buffer.lock().map_err(|_| ())?;
-        let type_support = self.endpoint.recv_type_support.as_ref().ok_or(())?;
-        let payload = data.1.payload().ok_or(())?;
-        read_payload(payload, &mut msg)?;
-        type_support.deserialize(&*msg, ros_request)?;
+        // Deserialize the request into the ROS message, in place if the payload is contiguous
+        let type_support = self.endpoint.get_recv_type_support()?;
+        let payload = data
+            .1
+            .payload()
+            .ok_or_else(|| Error::PayloadRead("request without payload".to_string()))?;
+        if let Some(payload) = contiguous_payload(payload) {
+            type_support.deserialize_slice(payload, ros_request)?;
+        } else {
+            let mut msg = self.endpoint.message_buffer.lock()?;
+            read_payload(payload, &mut msg)?;
+            type_support.deserialize(&*msg, ros_request)?;
+        }
         // Set the received timestamp
         let request_header = unsafe { &mut *request_header };
         request_header.received_timestamp = data.0;
         // Parse the attachment
-        let attachment: Attachment = data.1.attachment().ok_or(())?.try_into()?;
+        let attachment: Attachment = data
+            .1
+            .attachment()
+            .ok_or_else(|| Error::AttachmentDecode("missing attachment".to_string()))?
+            .try_into()?;
         request_header.source_timestamp = attachment.source_timestamp;
         request_header.request_id.sequence_number = attachment.sequence_number;
         request_header.request_id.writer_guid = attachment.source_gid;
+        // Publish the introspection event; a failure here does not affect the request
+        let _ = self.introspection.publish(
+            ServiceEventType::RequestReceived,
+            &attachment.source_gid,
+            attachment.sequence_number,
+            type_support,
+            ros_request,
+        );
         // Insert the request into the map
-        let mut map = self.query_map.lock().map_err(|_| ())?;
+        let mut map = self.query_map.lock()?;
         map.insert(request_header.request_id.get_hash(), data.1);
         Ok(true)
     }
 }
 
 // Implements WaitSetTrait for the Service
-impl WaitSetTrait for Service {
+impl<'a> WaitSetTrait for Service<'a> {
     fn is_empty(&self) -> bool {
         self.endpoint.is_empty()
     }
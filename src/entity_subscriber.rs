@@ -1,17 +1,26 @@
+use std::borrow::Cow;
 use std::sync::Arc;
+use zenoh::bytes::ZBytes;
 use zenoh::sample::Sample;
 use zenoh::Wait;
 use zenoh_ext::{AdvancedSubscriber, AdvancedSubscriberBuilderExt, HistoryConfig};
 
+use crate::contiguous_payload;
+use crate::decompress_payload;
+use crate::history_depth;
 use crate::read_payload;
 use crate::rmw::rmw_message_info_t;
 use crate::rmw::rmw_qos_durability_policy_e_RMW_QOS_POLICY_DURABILITY_TRANSIENT_LOCAL as DURABILITY_TRANSIENT_LOCAL;
 use crate::rmw::rmw_qos_profile_t;
 use crate::rmw::rmw_serialized_message_t;
 use crate::Attachment;
+use crate::Downsampler;
 use crate::Endpoint;
 use crate::EntityType;
+use crate::Error;
+use crate::MessageAllocation;
 use crate::Node;
+use crate::ReplayFilter;
 use crate::TypeSupport;
 use crate::WaitSetTrait;
 use crate::IMPLEMENTATION_IDENTIFIER_CHAR;
@@ -30,6 +39,7 @@ pub struct Subscriber {
     #[allow(dead_code)]
     subscriber: SubscriberEnum,
     pub endpoint: Arc<Endpoint<Sample>>,
+    downsampler: Option<Arc<Downsampler>>,
 }
 
 impl Subscriber {
@@ -40,8 +50,11 @@ impl Subscriber {
         type_support: TypeSupport,
         mut qos: rmw_qos_profile_t,
         ignore_local_publications: bool,
-    ) -> Result<Self, ()> {
-        // Ensure default QoS settings are applied
+    ) -> Result<Self, Error> {
+        // Replace the requested QoS by the first matching override, then apply the defaults
+        node.context
+            .qos_overrides
+            .apply(&node.fully_qualified_name(), endpoint_name, &mut qos);
         qos.set_default_profile();
         let endpoint = Arc::new(Endpoint::new(
             node,
@@ -54,27 +67,66 @@ impl Subscriber {
         // Generate the key expression for the endpoint
         let key_expr = endpoint.info.get_subscriber_keyexpr();
         let local_publisher_key_expr = endpoint.info.get_publisher_keyexpr();
+        let declare_error = |e: zenoh::Error| Error::KeyExpr(format!("{key_expr}: {e}"));
         let endpoint_clone = endpoint.clone();
+        // Keep one sample per interval if the first rule matching the topic downsamples it
+        let downsampler = node
+            .context
+            .transport_rules
+            .get(endpoint_name)
+            .downsampling_interval
+            .map(|interval| Arc::new(Downsampler::new(interval)));
+        let downsampler_clone = downsampler.clone();
+        // Drop the replays of messages already received live, and the reverse. A replay holds at
+        // most the history depth of each publisher, so twice that covers the live messages
+        // received while the history query is answered.
+        let transient_local = qos.durability == DURABILITY_TRANSIENT_LOCAL;
+        let depth = history_depth(&qos);
+        let replay_filter = transient_local.then(|| ReplayFilter::new(2 * depth));
+        let callback = move |sample: Sample| {
+            if ignore_local_publications && sample.key_expr().as_str() == local_publisher_key_expr {
+                return;
+            }
+            if let Some(replay_filter) = &replay_filter {
+                if replay_filter.is_duplicate(&sample) {
+                    tracing::trace!(
+                        "{}: dropped a replayed message",
+                        endpoint_clone.info.endpoint_name
+                    );
+                    return;
+                }
+            }
+            if let Some(downsampler) = &downsampler_clone {
+                if !downsampler.keep() {
+                    tracing::trace!(
+                        "{}: downsampled a message ({} so far)",
+                        endpoint_clone.info.endpoint_name,
+                        downsampler.dropped()
+                    );
+                    return;
+                }
+            }
+            endpoint_clone.push_recv_data(sample);
+        };
         // Check if durability is set to Transient Local
-        if qos.durability == DURABILITY_TRANSIENT_LOCAL {
-            // Create an advanced subscriber with caching
+        if transient_local {
+            // Create an advanced subscriber retrieving at most the history depth of each publisher
             Ok(Subscriber {
                 subscriber: SubscriberEnum::AdvancedSubscriber(
                     node.context
                         .session
-                        .declare_subscriber(key_expr)
-                        .history(HistoryConfig::default().detect_late_publishers())
-                        .callback(move |sample| {
-                            if !ignore_local_publications
-                                || sample.key_expr().as_str() != local_publisher_key_expr
-                            {
-                                endpoint_clone.push_recv_data(sample);
-                            }
-                        })
+                        .declare_subscriber(key_expr.clone())
+                        .history(
+                            HistoryConfig::default()
+                                .detect_late_publishers()
+                                .max_samples(depth),
+                        )
+                        .callback(callback)
                         .wait()
-                        .map_err(|_| ())?,
+                        .map_err(declare_error)?,
                 ),
                 endpoint,
+                downsampler,
             })
         } else {
             // Create a standard subscriber without caching
@@ -82,67 +134,158 @@ impl Subscriber {
                 subscriber: SubscriberEnum::Subscriber(
                     node.context
                         .session
-                        .declare_subscriber(key_expr)
-                        .callback(move |sample| {
-                            if !ignore_local_publications
-                                || sample.key_expr().as_str() != local_publisher_key_expr
-                            {
-                                endpoint_clone.push_recv_data(sample);
-                            }
-                        })
+                        .declare_subscriber(key_expr.clone())
+                        .callback(callback)
                         .wait()
-                        .map_err(|_| ())?,
+                        .map_err(declare_error)?,
                 ),
                 endpoint,
+                downsampler,
             })
         }
     }
-    // Takes a deserialized ROS message and its metadata
+    // Takes a deserialized ROS message and its metadata.
+    // Contiguous payloads are deserialized in place; fragmented ones are first gathered
+    // into the buffer of the allocation if one is given. Compressed payloads are decompressed first.
     pub fn take_message(
         &self,
         ros_message: *mut ::std::os::raw::c_void,
         message_info: *mut rmw_message_info_t,
-    ) -> Result<bool, ()> {
-        // Take the serialized message
-        let mut msg = self.endpoint.message_buffer.lock().map_err(|_| ())?;
-        let taken = self.take_serialized_message(&mut *msg, message_info)?;
-        if taken {
-            // Deserialize the message
-            let type_support = self.endpoint.recv_type_support.as_ref().ok_or(())?;
-            type_support.deserialize(&*msg, ros_message)?
+        allocation: Option<&mut MessageAllocation>,
+    ) -> Result<bool, Error> {
+        // Attempt to take a message from the endpoint
+        let Some(data) = self.endpoint.take_message() else {
+            return Ok(false);
+        };
+        // Deserialize the message
+        let type_support = self.endpoint.get_recv_type_support()?;
+        let attachment = Self::attachment(&data.1)?;
+        let payload = Self::payload(&data.1, &attachment)?;
+        if let Some(payload) = contiguous_payload(&payload) {
+            type_support.deserialize_slice(payload, ros_message)?;
+        } else {
+            let mut guard;
+            let msg = match allocation {
+                Some(allocation) => &mut allocation.message_buffer,
+                None => {
+                    guard = self.endpoint.message_buffer.lock()?;
+                    &mut *guard
+                }
+            };
+            read_payload(&payload, msg)?;
+            type_support.deserialize(msg, ros_message)?;
+        }
+        Self::fill_message_info(&data, &attachment, message_info)?;
+        Ok(true)
+    }
+    // Takes up to `ros_messages.len()` deserialized ROS messages and their metadata in one pass.
+    // Fragmented payloads are gathered into one buffer, locked once for the whole batch.
+    // The batch stops at the first message that fails, which is dropped.
+    pub fn take_sequence(
+        &self,
+        ros_messages: &[*mut ::std::os::raw::c_void],
+        message_infos: &mut [rmw_message_info_t],
+        allocation: Option<&mut MessageAllocation>,
+    ) -> Result<usize, Error> {
+        let type_support = self.endpoint.get_recv_type_support()?;
+        let mut guard;
+        let msg = match allocation {
+            Some(allocation) => &mut allocation.message_buffer,
+            None => {
+                guard = self.endpoint.message_buffer.lock()?;
+                &mut *guard
+            }
+        };
+        let mut taken = 0;
+        while taken < ros_messages.len().min(message_infos.len()) {
+            let Some(data) = self.endpoint.take_message() else {
+                break;
+            };
+            let result = Self::attachment(&data.1).and_then(|attachment| {
+                let payload = Self::payload(&data.1, &attachment)?;
+                match contiguous_payload(&payload) {
+                    Some(payload) => type_support.deserialize_slice(payload, ros_messages[taken]),
+                    None => read_payload(&payload, msg)
+                        .and_then(|_| type_support.deserialize(msg, ros_messages[taken])),
+                }?;
+                Self::fill_message_info(&data, &attachment, &mut message_infos[taken])
+            });
+            // Like take_message, a message that fails is consumed and its error returned,
+            // unless messages were already taken, which are returned without it
+            if let Err(e) = result {
+                if taken == 0 {
+                    return Err(e);
+                }
+                tracing::warn!(
+                    "{}: dropped a message after taking {taken}: {e}",
+                    self.endpoint.info.endpoint_name
+                );
+                break;
+            }
+            taken += 1;
         }
         Ok(taken)
     }
-    // Takes a serialized message and its metadata
+    // Takes a serialized message and its metadata, decompressing a compressed payload
     pub fn take_serialized_message(
         &self,
         serialized_message: &mut rmw_serialized_message_t,
         message_info: *mut rmw_message_info_t,
-    ) -> Result<bool, ()> {
+    ) -> Result<bool, Error> {
         // Attempt to take a message from the endpoint
         let Some(data) = self.endpoint.take_message() else {
             return Ok(false);
         };
         // Read the payload into the serialized message buffer
-        read_payload(data.1.payload(), serialized_message)?;
-        // Fill in the message metadata
-        if !message_info.is_null() {
-            // Parse the attachment
-            let attachment: Attachment = data.1.attachment().ok_or(())?.try_into()?;
-            let info = unsafe { &mut *message_info };
-            info.source_timestamp = attachment.source_timestamp;
-            info.publication_sequence_number = attachment.sequence_number as u64;
-            info.publisher_gid.implementation_identifier = IMPLEMENTATION_IDENTIFIER_CHAR;
-            info.publisher_gid.data = [0; RMW_GID_STORAGE_SIZE as usize];
-            for i in 0..attachment.source_gid.len() {
-                info.publisher_gid.data[i] = attachment.source_gid[i] as u8;
-            }
-            info.received_timestamp = data.0;
-            info.reception_sequence_number = u64::MAX;
-            info.from_intra_process = false;
-        }
+        let attachment = Self::attachment(&data.1)?;
+        read_payload(&Self::payload(&data.1, &attachment)?, serialized_message)?;
+        Self::fill_message_info(&data, &attachment, message_info)?;
         Ok(true)
     }
+    // Returns the number of messages skipped by downsampling since the subscription was created
+    pub fn downsampled(&self) -> u64 {
+        self.downsampler
+            .as_ref()
+            .map_or(0, |downsampler| downsampler.dropped())
+    }
+    // Returns the number of messages lost to a full KEEP_LAST queue since the subscription was
+    // created
+    pub fn lost(&self) -> u64 {
+        self.endpoint.recv_fifo.evictions()
+    }
+    // Parses the attachment of a received sample
+    fn attachment(sample: &Sample) -> Result<Attachment, Error> {
+        sample
+            .attachment()
+            .ok_or_else(|| Error::AttachmentDecode("missing attachment".to_string()))?
+            .try_into()
+    }
+    // Returns the serialized message of a received sample
+    fn payload<'a>(sample: &'a Sample, attachment: &Attachment) -> Result<Cow<'a, ZBytes>, Error> {
+        decompress_payload(sample.payload(), attachment)
+    }
+    // Fills in the message metadata from the attachment of a received sample
+    fn fill_message_info(
+        data: &(i64, Sample),
+        attachment: &Attachment,
+        message_info: *mut rmw_message_info_t,
+    ) -> Result<(), Error> {
+        if message_info.is_null() {
+            return Ok(());
+        }
+        let info = unsafe { &mut *message_info };
+        info.source_timestamp = attachment.source_timestamp;
+        info.publication_sequence_number = attachment.sequence_number as u64;
+        info.publisher_gid.implementation_identifier = IMPLEMENTATION_IDENTIFIER_CHAR;
+        info.publisher_gid.data = [0; RMW_GID_STORAGE_SIZE as usize];
+        for i in 0..attachment.source_gid.len() {
+            info.publisher_gid.data[i] = attachment.source_gid[i] as u8;
+        }
+        info.received_timestamp = data.0;
+        info.reception_sequence_number = u64::MAX;
+        info.from_intra_process = false;
+        Ok(())
+    }
 }
 
 // Implements WaitSetTrait for the Subscriber
@@ -1,16 +1,23 @@
 use std::ptr::copy_nonoverlapping;
 use zenoh::bytes::ZBytes;
 use zenoh_ext::ZDeserializer;
-use zenoh_ext::ZSerializer;
 
+use crate::rmw::rcutils_get_default_allocator;
+use crate::rmw::rmw_qos_history_policy_e_RMW_QOS_POLICY_HISTORY_KEEP_LAST as HISTORY_KEEP_LAST;
+use crate::rmw::rmw_qos_profile_t;
 use crate::rmw::rmw_serialized_message_t;
+use crate::Compression;
+use crate::Error;
+use crate::PayloadBuffer;
+use crate::TypeSupport;
 use crate::RMW_GID_STORAGE_SIZE;
 use crate::RMW_GID_STORAGE_SIZE_IRON;
 
 // Reads the payload data from a `ZBytes` into an `rmw_serialized_message_t` buffer.
-pub fn read_payload(payload: &ZBytes, msg: &mut rmw_serialized_message_t) -> Result<(), ()> {
+pub fn read_payload(payload: &ZBytes, msg: &mut rmw_serialized_message_t) -> Result<(), Error> {
     // Reserve space in the message buffer
-    msg.try_reserve(payload.len())?;
+    msg.try_reserve(payload.len())
+        .map_err(|e| Error::PayloadRead(e.to_string()))?;
     msg.buffer_length = payload.len();
     // Copy each slice of the payload into the buffer
     let mut offset: usize = 0;
@@ -21,17 +28,152 @@ pub fn read_payload(payload: &ZBytes, msg: &mut rmw_serialized_message_t) -> Res
     Ok(())
 }
 
+// Returns the payload data if it is a single contiguous slice, so that it can be read in place.
+pub fn contiguous_payload(payload: &ZBytes) -> Option<&[u8]> {
+    let mut slices = payload.slices();
+    match (slices.next(), slices.next()) {
+        (Some(slice), None) => Some(slice),
+        (None, _) => Some(&[]),
+        _ => None,
+    }
+}
+
+// Converts a `u8` GID into the `i8` writer GUID format used in `rmw_request_id_t`.
+pub fn to_writer_guid(
+    gid: [u8; RMW_GID_STORAGE_SIZE as usize],
+) -> [i8; RMW_GID_STORAGE_SIZE_IRON as usize] {
+    let mut writer_guid = [0i8; RMW_GID_STORAGE_SIZE_IRON];
+    for i in 0..writer_guid.len() {
+        writer_guid[i] = gid[i] as i8;
+    }
+    writer_guid
+}
+
+// Number of samples cached by transient local KEEP_ALL publishers and replayed to KEEP_ALL
+// subscriptions, overridable with RMW_ZENOH_KEEP_ALL_HISTORY_DEPTH
+const KEEP_ALL_HISTORY_DEPTH_ENV: &str = "RMW_ZENOH_KEEP_ALL_HISTORY_DEPTH";
+const DEFAULT_KEEP_ALL_HISTORY_DEPTH: usize = 1000;
+
+// Returns the number of samples of a transient local history: the depth for KEEP_LAST and the
+// configured bound for KEEP_ALL.
+pub fn history_depth(qos: &rmw_qos_profile_t) -> usize {
+    if qos.history == HISTORY_KEEP_LAST {
+        return qos.depth.max(1);
+    }
+    std::env::var(KEEP_ALL_HISTORY_DEPTH_ENV)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|depth| *depth > 0)
+        .unwrap_or(DEFAULT_KEEP_ALL_HISTORY_DEPTH)
+}
+
+// `MessageAllocation` struct: Buffers preallocated for a bounded message type, handed out through
+// `rmw_publisher_allocation_t` and `rmw_subscription_allocation_t` so that publishing and
+// taking with them make no heap allocation once warmed up, on topics without compression.
+// Publishers serialize into payload buffers shared with Zenoh, adding one more while Zenoh
+// still holds all of them, e.g. in the history cache. Subscriptions gather fragmented
+// payloads into the message buffer.
+pub struct MessageAllocation {
+    pub message_buffer: rmw_serialized_message_t,
+    payload_capacity: usize,
+    payload_buffers: Vec<(PayloadBuffer, PayloadBuffer)>,
+}
+
+impl MessageAllocation {
+    // Preallocates the payload and attachment buffers of a publisher for the largest
+    // serialized message of the type
+    pub fn new_publisher_allocation(type_support: &TypeSupport) -> Result<Self, Error> {
+        let payload_capacity = Self::max_serialized_size(type_support)?;
+        let allocator = unsafe { rcutils_get_default_allocator() };
+        let mut allocation = Self {
+            message_buffer: rmw_serialized_message_t::new(0, allocator)?,
+            payload_capacity,
+            payload_buffers: Vec::new(),
+        };
+        allocation.payload_buffers();
+        Ok(allocation)
+    }
+    // Preallocates the message buffer of a subscription for the largest serialized message
+    // of the type
+    pub fn new_subscription_allocation(type_support: &TypeSupport) -> Result<Self, Error> {
+        let size = Self::max_serialized_size(type_support)?;
+        let allocator = unsafe { rcutils_get_default_allocator() };
+        Ok(Self {
+            message_buffer: rmw_serialized_message_t::new(size, allocator)?,
+            payload_capacity: 0,
+            payload_buffers: Vec::new(),
+        })
+    }
+    // Returns payload and attachment buffers no longer shared with Zenoh, adding a pair if
+    // Zenoh still shares every one
+    pub fn payload_buffers(&mut self) -> (&mut PayloadBuffer, &mut PayloadBuffer) {
+        let index = match self
+            .payload_buffers
+            .iter()
+            .position(|(payload, attachment)| payload.is_writable() && attachment.is_writable())
+        {
+            Some(index) => index,
+            None => {
+                self.payload_buffers.push((
+                    PayloadBuffer::with_capacity(self.payload_capacity),
+                    PayloadBuffer::with_capacity(ATTACHMENT_CAPACITY),
+                ));
+                self.payload_buffers.len() - 1
+            }
+        };
+        let (payload, attachment) = &mut self.payload_buffers[index];
+        (payload, attachment)
+    }
+    // Returns the largest serialized size of a bounded type
+    fn max_serialized_size(type_support: &TypeSupport) -> Result<usize, Error> {
+        type_support.max_serialized_size().ok_or_else(|| {
+            Error::TypeSupport(format!(
+                "{} has unbounded strings or sequences",
+                type_support.type_name
+            ))
+        })
+    }
+}
+
+// Release the buffers when the allocation is finalized
+impl Drop for MessageAllocation {
+    fn drop(&mut self) {
+        self.message_buffer.fini();
+    }
+}
+
 /// A trait defining basic behaviors for wait sets.
 pub trait WaitSetTrait {
     fn is_empty(&self) -> bool;
     fn cleanup(&mut self) {}
 }
 
+// Version of the binary attachment layout. Attachments of the previous string-keyed format
+// start with 15, the length of "sequence_number", which is never used as a version.
+const ATTACHMENT_VERSION: u8 = 1;
+const LEGACY_ATTACHMENT_PREFIX: u8 = 15;
+// Size of the fixed part of the binary attachment: version, sequence number, timestamp and GID
+const ATTACHMENT_SIZE: usize = 1 + 8 + 8 + RMW_GID_STORAGE_SIZE_IRON;
+// Size of an attachment with every known extension field
+const ATTACHMENT_CAPACITY: usize = ATTACHMENT_SIZE + 3;
+// Extension field holding the id of the codec of a compressed payload
+const COMPRESSION_EXTENSION: u8 = 1;
+
 // The `Attachment` struct holds metadata for a message, such as sequence number, timestamp, and GID.
+// It is sent in a little-endian fixed layout:
+//   [0]      version
+//   [1..9]   sequence_number (i64)
+//   [9..17]  source_timestamp (i64)
+//   [17..33] source_gid
+//   [33..]   extension fields, each as an id byte, a length byte and `length` bytes.
+// Newer versions keep the fixed part and only add extensions; unknown extensions are skipped.
+// Extensions:
+//   1        compression codec of the payload (1 byte); absent for raw payloads
 pub struct Attachment {
     pub sequence_number: i64,
     pub source_timestamp: i64,
     pub source_gid: [i8; RMW_GID_STORAGE_SIZE_IRON as usize],
+    pub compression: Option<Compression>,
 }
 
 impl Attachment {
@@ -41,63 +183,228 @@ impl Attachment {
         source_timestamp: i64,
         source_gid: [u8; RMW_GID_STORAGE_SIZE as usize],
     ) -> Self {
-        // Convert the `u8` GID to `i8` format
-        let mut source_gid_i8 = [0i8; RMW_GID_STORAGE_SIZE_IRON];
-        for i in 0..source_gid_i8.len() {
-            source_gid_i8[i] = source_gid[i] as i8;
-        }
         Self {
             sequence_number,
             source_timestamp,
-            source_gid: source_gid_i8,
+            source_gid: to_writer_guid(source_gid),
+            compression: None,
         }
     }
-}
-// Implements conversion from `ZBytes` to `Attachment`.
-impl TryFrom<&ZBytes> for Attachment {
-    type Error = ();
-    fn try_from(value: &ZBytes) -> Result<Self, Self::Error> {
+    // Encodes the attachment in the binary layout into `bytes`, returning the encoded part
+    pub fn encode<'a>(&self, bytes: &'a mut [u8; ATTACHMENT_CAPACITY]) -> &'a [u8] {
+        bytes[0] = ATTACHMENT_VERSION;
+        bytes[1..9].copy_from_slice(&self.sequence_number.to_le_bytes());
+        bytes[9..17].copy_from_slice(&self.source_timestamp.to_le_bytes());
+        for (byte, gid) in bytes[17..ATTACHMENT_SIZE].iter_mut().zip(self.source_gid) {
+            *byte = gid as u8;
+        }
+        let mut length = ATTACHMENT_SIZE;
+        if let Some(compression) = self.compression {
+            bytes[length..length + 3].copy_from_slice(&[
+                COMPRESSION_EXTENSION,
+                1,
+                compression.id(),
+            ]);
+            length += 3;
+        }
+        &bytes[..length]
+    }
+    // Encodes the attachment into a payload buffer without allocating
+    pub fn encode_into(&self, buffer: &mut PayloadBuffer) -> Result<(), Error> {
+        let mut bytes = [0; ATTACHMENT_CAPACITY];
+        buffer.write(self.encode(&mut bytes))
+    }
+    // Decodes an attachment in the binary layout without allocating
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::AttachmentDecode(reason.to_string());
+        let Some((fixed, mut extensions)) = bytes.split_first_chunk::<ATTACHMENT_SIZE>() else {
+            return Err(invalid("truncated attachment"));
+        };
+        if fixed[0] == 0 || fixed[0] == LEGACY_ATTACHMENT_PREFIX {
+            return Err(invalid("unknown attachment version"));
+        }
+        // Read the known extension fields and skip the others
+        let mut compression = None;
+        while let [id, length, rest @ ..] = extensions {
+            let value = rest
+                .get(..*length as usize)
+                .ok_or_else(|| invalid("truncated extension field"))?;
+            if *id == COMPRESSION_EXTENSION {
+                let codec = value.first().copied().unwrap_or_default();
+                compression = Some(
+                    Compression::from_id(codec)
+                        .ok_or_else(|| invalid(&format!("unknown compression codec {codec}")))?,
+                );
+            }
+            extensions = &rest[value.len()..];
+        }
+        if !extensions.is_empty() {
+            return Err(invalid("truncated extension field"));
+        }
+        let mut source_gid = [0i8; RMW_GID_STORAGE_SIZE_IRON as usize];
+        for (gid, byte) in source_gid.iter_mut().zip(&fixed[17..]) {
+            *gid = *byte as i8;
+        }
+        Ok(Attachment {
+            sequence_number: i64::from_le_bytes(fixed[1..9].try_into().unwrap_or_default()),
+            source_timestamp: i64::from_le_bytes(fixed[9..17].try_into().unwrap_or_default()),
+            source_gid,
+            compression,
+        })
+    }
+    // Decodes an attachment of the previous string-keyed format
+    fn decode_legacy(value: &ZBytes) -> Result<Self, Error> {
         let mut sequence_number: Option<i64> = None;
         let mut source_timestamp: Option<i64> = None;
         let mut source_gid: Option<[i8; RMW_GID_STORAGE_SIZE_IRON as usize]> = None;
-        let mut deserializer = ZDeserializer::new(&value);
+        let invalid = |field: &str| Error::AttachmentDecode(format!("invalid {field}"));
+        let missing = |field: &str| Error::AttachmentDecode(format!("missing {field}"));
+        let mut deserializer = ZDeserializer::new(value);
         while !deserializer.done() {
             match deserializer.deserialize::<String>() {
                 Ok(val) if val == "sequence_number" => {
-                    sequence_number = Some(deserializer.deserialize::<i64>().map_err(|_| ())?)
+                    sequence_number = Some(
+                        deserializer
+                            .deserialize::<i64>()
+                            .map_err(|_| invalid("sequence_number"))?,
+                    )
                 }
                 Ok(val) if val == "source_timestamp" => {
-                    source_timestamp = Some(deserializer.deserialize::<i64>().map_err(|_| ())?)
+                    source_timestamp = Some(
+                        deserializer
+                            .deserialize::<i64>()
+                            .map_err(|_| invalid("source_timestamp"))?,
+                    )
                 }
                 Ok(val) if val == "source_gid" => {
                     source_gid = Some(
                         deserializer
                             .deserialize::<[i8; RMW_GID_STORAGE_SIZE_IRON as usize]>()
-                            .map_err(|_| ())?,
+                            .map_err(|_| invalid("source_gid"))?,
                     );
                 }
-                _ => return Err(()),
+                Ok(val) => return Err(Error::AttachmentDecode(format!("unknown field {val}"))),
+                Err(_) => return Err(invalid("field name")),
             }
         }
         Ok(Attachment {
-            sequence_number: sequence_number.ok_or(())?,
-            source_timestamp: source_timestamp.ok_or(())?,
-            source_gid: source_gid.ok_or(())?,
+            sequence_number: sequence_number.ok_or_else(|| missing("sequence_number"))?,
+            source_timestamp: source_timestamp.ok_or_else(|| missing("source_timestamp"))?,
+            source_gid: source_gid.ok_or_else(|| missing("source_gid"))?,
+            compression: None,
         })
     }
 }
+// Implements conversion from `ZBytes` to `Attachment`, accepting both formats.
+impl TryFrom<&ZBytes> for Attachment {
+    type Error = Error;
+    fn try_from(value: &ZBytes) -> Result<Self, Self::Error> {
+        // The attachment is a single slice unless it was fragmented, so this does not copy
+        let bytes = value.to_bytes();
+        match bytes.first() {
+            Some(&LEGACY_ATTACHMENT_PREFIX) => Self::decode_legacy(value),
+            _ => Self::decode(&bytes),
+        }
+    }
+}
 
 // Implements conversion from `Attachment` to `ZBytes`.
 impl TryFrom<Attachment> for ZBytes {
-    type Error = ();
+    type Error = Error;
     fn try_from(value: Attachment) -> Result<Self, Self::Error> {
+        let mut bytes = [0; ATTACHMENT_CAPACITY];
+        Ok(ZBytes::from(value.encode(&mut bytes).to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zenoh_ext::ZSerializer;
+
+    fn attachment(compression: Option<Compression>) -> Attachment {
+        let mut gid = [0u8; RMW_GID_STORAGE_SIZE as usize];
+        for (i, byte) in gid.iter_mut().enumerate() {
+            *byte = 0xf0 + i as u8;
+        }
+        Attachment {
+            compression,
+            ..Attachment::new(42, -7, gid)
+        }
+    }
+
+    fn assert_same(decoded: &Attachment, expected: &Attachment) {
+        assert_eq!(decoded.sequence_number, expected.sequence_number);
+        assert_eq!(decoded.source_timestamp, expected.source_timestamp);
+        assert_eq!(decoded.source_gid, expected.source_gid);
+        assert_eq!(decoded.compression, expected.compression);
+    }
+
+    #[test]
+    fn attachments_round_trip() {
+        for compression in [None, Some(Compression::Lz4), Some(Compression::Zstd)] {
+            let expected = attachment(compression);
+            let mut bytes = [0; ATTACHMENT_CAPACITY];
+            let encoded = expected.encode(&mut bytes);
+            assert_same(&Attachment::decode(encoded).unwrap(), &expected);
+            let payload = ZBytes::from(encoded.to_vec());
+            assert_same(&Attachment::try_from(&payload).unwrap(), &expected);
+        }
+    }
+
+    #[test]
+    fn unknown_extensions_are_skipped() {
+        let expected = attachment(Some(Compression::Zstd));
+        let mut bytes = [0; ATTACHMENT_CAPACITY];
+        let mut encoded = expected.encode(&mut bytes).to_vec();
+        // A newer version adds an extension of two bytes after the known one
+        encoded[0] = ATTACHMENT_VERSION + 1;
+        encoded.extend_from_slice(&[200, 2, 0xaa, 0xbb]);
+        assert_same(&Attachment::decode(&encoded).unwrap(), &expected);
+    }
+
+    #[test]
+    fn malformed_attachments_are_rejected() {
+        let mut bytes = [0; ATTACHMENT_CAPACITY];
+        let encoded = attachment(Some(Compression::Lz4))
+            .encode(&mut bytes)
+            .to_vec();
+        // Truncated fixed part
+        assert!(Attachment::decode(&encoded[..ATTACHMENT_SIZE - 1]).is_err());
+        assert!(Attachment::decode(&[]).is_err());
+        // Truncated extension field
+        assert!(Attachment::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(Attachment::decode(&encoded[..ATTACHMENT_SIZE + 1]).is_err());
+        // Versions that are never used
+        for version in [0, LEGACY_ATTACHMENT_PREFIX] {
+            let mut invalid = encoded.clone();
+            invalid[0] = version;
+            assert!(Attachment::decode(&invalid).is_err());
+        }
+        // Unknown compression codec
+        let mut invalid = encoded.clone();
+        invalid[ATTACHMENT_SIZE + 2] = 0xff;
+        assert!(Attachment::decode(&invalid).is_err());
+    }
+
+    #[test]
+    fn legacy_attachments_are_accepted() {
+        let expected = attachment(None);
         let mut serializer = ZSerializer::new();
         serializer.serialize("sequence_number");
-        serializer.serialize(value.sequence_number);
+        serializer.serialize(expected.sequence_number);
         serializer.serialize("source_timestamp");
-        serializer.serialize(value.source_timestamp);
+        serializer.serialize(expected.source_timestamp);
         serializer.serialize("source_gid");
-        serializer.serialize(&value.source_gid);
-        Ok(serializer.finish())
+        serializer.serialize(expected.source_gid);
+        let payload = serializer.finish();
+        assert_eq!(payload.to_bytes().first(), Some(&LEGACY_ATTACHMENT_PREFIX));
+        assert_same(&Attachment::try_from(&payload).unwrap(), &expected);
+
+        // A legacy attachment missing a field is rejected
+        let mut serializer = ZSerializer::new();
+        serializer.serialize("sequence_number");
+        serializer.serialize(expected.sequence_number);
+        assert!(Attachment::try_from(&serializer.finish()).is_err());
     }
 }
@@ -0,0 +1,76 @@
+use std::ffi::CString;
+use std::fmt;
+use std::panic::Location;
+use std::sync::PoisonError;
+
+use crate::rmw::rcutils_set_error_state;
+use crate::rmw::rmw_ret_t;
+use crate::rmw::{RET_BAD_ALLOC, RET_ERROR, RET_INVALID_ARGUMENT};
+
+// Error enum: Failures of the middleware, reported to rcl through the rmw error string
+#[derive(Debug)]
+pub enum Error {
+    ConfigLoad(String),
+    SessionOpen(String),
+    Security(String),
+    KeyExpr(String),
+    Transport(String),
+    TypeSupport(String),
+    Serialization(String),
+    PayloadRead(String),
+    AttachmentDecode(String),
+    Allocation(String),
+    InvalidArgument(String),
+    Lock,
+}
+
+impl Error {
+    // Returns the rmw return code matching the error
+    pub fn ret(&self) -> rmw_ret_t {
+        match self {
+            Error::Allocation(_) => RET_BAD_ALLOC,
+            Error::InvalidArgument(_) => RET_INVALID_ARGUMENT,
+            _ => RET_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ConfigLoad(e) => write!(f, "failed to load the Zenoh configuration: {e}"),
+            Error::SessionOpen(e) => write!(f, "failed to open the Zenoh session: {e}"),
+            Error::Security(e) => write!(f, "failed to configure security: {e}"),
+            Error::KeyExpr(e) => write!(f, "failed to declare a Zenoh entity on {e}"),
+            Error::Transport(e) => write!(f, "failed to communicate through Zenoh: {e}"),
+            Error::TypeSupport(e) => write!(f, "invalid type support: {e}"),
+            Error::Serialization(e) => write!(f, "failed to (de)serialize {e}"),
+            Error::PayloadRead(e) => write!(f, "failed to read the payload: {e}"),
+            Error::AttachmentDecode(e) => write!(f, "failed to decode the attachment: {e}"),
+            Error::Allocation(e) => write!(f, "failed to allocate {e}"),
+            Error::InvalidArgument(e) => write!(f, "invalid argument: {e}"),
+            Error::Lock => write!(f, "a mutex was poisoned by a panicking thread"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// A poisoned mutex only happens after a panic, so the guarded state is not trusted
+impl<T> From<PoisonError<T>> for Error {
+    fn from(_: PoisonError<T>) -> Self {
+        Error::Lock
+    }
+}
+
+// Sets the rmw error string from an error and returns the matching return code.
+// The location of the caller is recorded, as rmw_set_error_string does.
+#[track_caller]
+pub fn set_error_string(error: &Error) -> rmw_ret_t {
+    let location = Location::caller();
+    let message = CString::new(error.to_string().replace('\0', "")).unwrap_or_default();
+    let file = CString::new(location.file()).unwrap_or_default();
+    unsafe { rcutils_set_error_state(message.as_ptr(), file.as_ptr(), location.line() as usize) };
+    tracing::debug!("{error}");
+    error.ret()
+}
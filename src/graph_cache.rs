@@ -1,63 +1,105 @@
 use std::collections::BTreeMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use zenoh::sample::SampleKind;
 use zenoh::Wait;
 
 use crate::Context;
 use crate::EndpointInfo;
 use crate::EntityType;
+use crate::Error;
 use crate::GuardCondition;
 use crate::ADMIN_SPACE;
 
+// Callback invoked with the information of an entity that left the graph
+pub type DepartureListener = dyn Fn(&EndpointInfo) + Send + Sync;
+
 // Represents a graph cache that tracks the state of entities in the system.
 pub struct GraphCache {
     #[allow(dead_code)]
     subscriber: zenoh::pubsub::Subscriber<()>,
     endpoint_map: Arc<std::sync::Mutex<BTreeMap<String, EndpointInfo>>>,
+    departure_listeners: Arc<Mutex<Vec<Weak<DepartureListener>>>>,
     pub guard_condition: Arc<Mutex<GuardCondition>>,
 }
 
 impl GraphCache {
     // Constructor for creating a new GraphCache instance
-    pub fn new(context: &Context) -> Result<Self, ()> {
+    pub fn new(context: &Context) -> Result<Self, Error> {
         let key_expr = format!("{ADMIN_SPACE}/{0}/**", context.domain_id);
         let endpoint_map = Arc::new(Mutex::new(BTreeMap::new()));
         let endpoint_map_clone = endpoint_map.clone();
-        let guard_condition =
-            Arc::new(Mutex::new(GuardCondition::new(context.wait_set_cv.clone())));
+        let guard_condition = Arc::new(Mutex::new(GuardCondition::new(
+            context.wait_set_signal.clone(),
+        )));
         let guard_condition_clone = guard_condition.clone();
+        let departure_listeners: Arc<Mutex<Vec<Weak<DepartureListener>>>> =
+            Arc::new(Mutex::new(Vec::new()));
+        let departure_listeners_clone = departure_listeners.clone();
         Ok(GraphCache {
             subscriber: context
                 .session
                 .liveliness()
-                .declare_subscriber(key_expr)
+                .declare_subscriber(key_expr.clone())
                 .history(true)
-                .callback(move |sample| match sample.kind() {
-                    SampleKind::Put => {
-                        if let Ok(info) = EndpointInfo::try_from(sample.key_expr().as_str()) {
-                            if let Ok(mut endpoint_map) = endpoint_map_clone.lock() {
-                                endpoint_map.insert(sample.key_expr().to_string(), info);
-                                if let Ok(mut guard) = guard_condition_clone.lock() {
-                                    guard.trigger();
-                                }
+                .callback(move |sample| {
+                    // Update the map first and release it before triggering the guard condition,
+                    // since wait sets may query the graph cache while holding the wait set lock
+                    let mut departed = None;
+                    let updated = match sample.kind() {
+                        SampleKind::Put => {
+                            match EndpointInfo::try_from(sample.key_expr().as_str()) {
+                                Ok(info) => match endpoint_map_clone.lock() {
+                                    Ok(mut endpoint_map) => {
+                                        endpoint_map.insert(sample.key_expr().to_string(), info);
+                                        true
+                                    }
+                                    Err(_) => false,
+                                },
+                                Err(_) => false,
                             }
                         }
-                    }
-                    SampleKind::Delete => {
-                        if let Ok(mut endpoint_map) = endpoint_map_clone.lock() {
-                            endpoint_map.remove(sample.key_expr().as_str());
-                            if let Ok(mut guard) = guard_condition_clone.lock() {
-                                guard.trigger();
+                        SampleKind::Delete => match endpoint_map_clone.lock() {
+                            Ok(mut endpoint_map) => {
+                                departed = endpoint_map.remove(sample.key_expr().as_str());
+                                true
                             }
+                            Err(_) => false,
+                        },
+                    };
+                    if updated {
+                        if let Ok(mut guard) = guard_condition_clone.lock() {
+                            guard.trigger();
+                        }
+                    }
+                    // Notify the listeners outside of the locks, dropping the ones released
+                    if let Some(info) = departed {
+                        let listeners: Vec<Arc<DepartureListener>> =
+                            match departure_listeners_clone.lock() {
+                                Ok(mut listeners) => {
+                                    listeners.retain(|listener| listener.strong_count() > 0);
+                                    listeners.iter().filter_map(Weak::upgrade).collect()
+                                }
+                                Err(_) => Vec::new(),
+                            };
+                        for listener in listeners {
+                            listener(&info);
                         }
                     }
                 })
                 .wait()
-                .map_err(|_| ())?,
+                .map_err(|e| Error::KeyExpr(format!("{key_expr}: {e}")))?,
             endpoint_map,
+            departure_listeners,
             guard_condition,
         })
     }
+    // Registers a callback for the entities leaving the graph, called from the Zenoh RX thread
+    // until the listener is dropped
+    pub fn add_departure_listener(&self, listener: &Arc<DepartureListener>) {
+        if let Ok(mut listeners) = self.departure_listeners.lock() {
+            listeners.push(Arc::downgrade(listener));
+        }
+    }
     // Retrieves a list of endpoints matching the given filters.
     pub fn get_endpoint_list(
         &self,
@@ -3,12 +3,16 @@ use std::ffi::CString;
 use std::ptr::null_mut;
 
 use crate::check_not_null_all;
+use crate::error::set_error_string;
+use crate::fail_invalid_argument;
 use crate::rmw::*;
 use crate::rsutils::str_from_ptr;
+use crate::unwrap_or_set_error;
 use crate::validate_allocator;
 use crate::validate_implementation_identifier;
 use crate::EndpointInfo;
 use crate::EntityType;
+use crate::Error;
 use crate::Node;
 use crate::IMPLEMENTATION_IDENTIFIER_STR;
 
@@ -31,13 +35,12 @@ pub fn get_endpoint_info_by_topic(
     );
     validate_allocator!(RET_INVALID_ARGUMENT, (*allocator));
     validate_implementation_identifier!(node);
+    // The rmw and rcutils checks and initializers set the error string themselves
     if unsafe { rmw_topic_endpoint_info_array_check_zero(info_array) } != RET_OK {
         return RET_INVALID_ARGUMENT;
     }
     // Retrieve endpoint information from the graph cache
-    let Ok(endpoint_name) = str_from_ptr(endpoint_name) else {
-        return RET_INVALID_ARGUMENT;
-    };
+    let endpoint_name = unwrap_or_set_error!(RET_INVALID_ARGUMENT, str_from_ptr(endpoint_name));
     let graph_cache = unsafe { &(*((*node).data as *mut Node)).graph_cache };
     let info = graph_cache.get_endpoint_list("", "", endpoint_name, endpoint_types);
     if info.is_empty() {
@@ -54,6 +57,9 @@ pub fn get_endpoint_info_by_topic(
     {
         RET_OK
     } else {
+        set_error_string(&Error::Allocation(
+            "failed to copy the endpoint information".to_string(),
+        ));
         unsafe { rmw_topic_endpoint_info_array_fini(&mut *info_array, allocator) };
         RET_ERROR
     }
@@ -121,7 +127,7 @@ pub fn get_names_and_types(
             || NODE_NAME_VALID != name_valid
             || NAMESPACE_VALID != ns_valid
         {
-            return RET_INVALID_ARGUMENT;
+            fail_invalid_argument!(RET_INVALID_ARGUMENT, "invalid node name or namespace");
         }
     }
     // Check the node exists
@@ -130,6 +136,9 @@ pub fn get_names_and_types(
     let graph_cache = unsafe { &(*((*node).data as *mut Node)).graph_cache };
     let node_info = graph_cache.get_endpoint_list(namespace, node_name, "", &[EntityType::Node]);
     if node_info.is_empty() {
+        set_error_string(&Error::InvalidArgument(format!(
+            "node {node_name} does not exist in namespace {namespace}"
+        )));
         return RET_NODE_NAME_NON_EXISTENT;
     }
     // Retrieve endpoint information from the graph cache
@@ -180,6 +189,9 @@ pub fn get_names_and_types(
     }) {
         RET_OK
     } else {
+        set_error_string(&Error::Allocation(
+            "failed to copy the names and types".to_string(),
+        ));
         // Finalize rmw_names_and_types_t if failed.
         unsafe { rcutils_string_array_fini(&mut (*names_and_types).names) };
         unsafe {
@@ -1,27 +1,24 @@
+use crate::WaitSetSignal;
 use crate::WaitSetTrait;
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::Arc;
 
 // Represents a guard condition used to notify wait sets.
 pub struct GuardCondition {
     triggered: bool,
-    pub wait_set_cv: Arc<(Mutex<()>, Condvar)>,
+    pub wait_set_signal: Arc<WaitSetSignal>,
 }
 
 impl GuardCondition {
     // Constructor for creating a new GuardCondition instance
-    pub fn new(wait_set_cv: Arc<(Mutex<()>, Condvar)>) -> Self {
+    pub fn new(wait_set_signal: Arc<WaitSetSignal>) -> Self {
         GuardCondition {
             triggered: false,
-            wait_set_cv,
+            wait_set_signal,
         }
     }
     // Triggers the guard condition, notifying all waiting threads.
     pub fn trigger(&mut self) {
-        let (lock, cvar) = &*self.wait_set_cv;
-        if let Ok(_) = lock.lock() {
-            self.triggered = true;
-            cvar.notify_all();
-        }
+        let _ = self.wait_set_signal.notify_with(|| self.triggered = true);
     }
 }
 
@@ -1,5 +1,12 @@
 // Module declarations: Declare the modules used within the project
-mod context;
+#[cfg(feature = "rust-cdr")]
+mod cdr;
+#[cfg(feature = "rust-cdr")]
+mod cdr_type_support;
+mod compression;
+pub mod config_utils;
+pub mod context;
+mod downsampler;
 mod endpoint;
 mod endpoint_info;
 mod entity_client;
@@ -8,17 +15,34 @@ mod entity_publisher;
 mod entity_service;
 mod entity_subscriber;
 mod entity_utils;
+pub mod error;
 mod event;
 mod graph_cache;
 mod graph_cache_utils;
 mod guard_condition;
+mod logging;
+mod payload_buffer;
+mod permissions;
 mod qos;
+mod qos_overrides;
+mod recv_queue;
+mod replay_filter;
 pub mod rmw;
 pub mod rsutils;
+mod security;
+mod service_introspection;
+#[cfg(test)]
+mod test_allocator;
+pub mod transport_rules;
 mod type_support;
+mod wait_set_signal;
 
 // Import types and functions from the declared modules
+use compression::compress_payload;
+use compression::decompress_payload;
+use compression::Compression;
 use context::Context;
+use downsampler::Downsampler;
 use endpoint::Endpoint;
 use endpoint_info::EndpointInfo;
 use endpoint_info::EntityType;
@@ -27,17 +51,30 @@ use entity_node::Node;
 use entity_publisher::Publisher;
 use entity_service::Service;
 use entity_subscriber::Subscriber;
+use entity_utils::contiguous_payload;
+use entity_utils::history_depth;
 use entity_utils::read_payload;
+use entity_utils::to_writer_guid;
 use entity_utils::Attachment;
+use entity_utils::MessageAllocation;
 use entity_utils::WaitSetTrait;
+use error::Error;
 use event::Event;
 use event::EventCallback;
 use event::EventMap;
+use graph_cache::DepartureListener;
 use graph_cache::GraphCache;
 use guard_condition::GuardCondition;
+use payload_buffer::PayloadBuffer;
+use recv_queue::RecvQueue;
+use replay_filter::ReplayFilter;
 use rmw::RMW_GID_STORAGE_SIZE;
 use rsutils::StringStorage;
+use service_introspection::ServiceEventType;
+use service_introspection::ServiceIntrospection;
+use transport_rules::TransportSettings;
 use type_support::TypeSupport;
+use wait_set_signal::WaitSetSignal;
 
 // Constant definitions: Fixed values used throughout the system
 const RMW_GID_STORAGE_SIZE_IRON: usize = 16;
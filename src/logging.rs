@@ -0,0 +1,193 @@
+use std::cell::Cell;
+use std::ffi::CString;
+use std::fmt::{Debug, Write};
+use std::os::raw::{c_char, c_int};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Once;
+use tracing::field::{Field, Visit};
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+
+use crate::rmw::rcutils_log;
+use crate::rmw::rcutils_log_location_t;
+use crate::rmw::rcutils_logging_set_logger_level;
+use crate::Error;
+
+// Name of the rcutils logger receiving every event
+const LOGGER_NAME: *const c_char = "rmw_zenoh_rs\0".as_ptr() as *const c_char;
+const LOG_FORMAT: *const c_char = "%s\0".as_ptr() as *const c_char;
+
+// Values of RCUTILS_LOG_SEVERITY, shared by rmw_log_severity_t
+const SEVERITY_DEBUG: c_int = 10;
+const SEVERITY_INFO: c_int = 20;
+const SEVERITY_WARN: c_int = 30;
+const SEVERITY_ERROR: c_int = 40;
+const SEVERITY_FATAL: c_int = 50;
+
+// Events below this severity are dropped before being formatted
+static SEVERITY: AtomicI32 = AtomicI32::new(SEVERITY_WARN);
+
+thread_local! {
+    // Set while an event is forwarded, so that events emitted by the output handler
+    // (e.g. when publishing on /rosout) are not forwarded recursively
+    static FORWARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+// RcutilsLayer struct: Forwards tracing events to rcutils_log
+struct RcutilsLayer;
+
+impl<S: Subscriber> Layer<S> for RcutilsLayer {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        // Cached until set_log_severity rebuilds the interest of every callsite
+        if to_severity(metadata.level()) >= SEVERITY.load(Ordering::Relaxed) {
+            Interest::always()
+        } else {
+            Interest::never()
+        }
+    }
+
+    fn enabled(
+        &self,
+        metadata: &Metadata<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) -> bool {
+        to_severity(metadata.level()) >= SEVERITY.load(Ordering::Relaxed)
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+        if FORWARDING.with(|forwarding| forwarding.replace(true)) {
+            return;
+        }
+        let metadata = event.metadata();
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        log_to_rcutils(
+            to_severity(metadata.level()),
+            metadata.module_path().unwrap_or(metadata.target()),
+            metadata.file().unwrap_or_default(),
+            metadata.line().unwrap_or_default(),
+            &visitor.message,
+        );
+        FORWARDING.with(|forwarding| forwarding.set(false));
+    }
+}
+
+// MessageVisitor struct: Formats the message of an event followed by its other fields
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            let fields = std::mem::take(&mut self.message);
+            let _ = write!(self.message, "{value:?}{fields}");
+        } else {
+            let _ = write!(self.message, " {}={value:?}", field.name());
+        }
+    }
+}
+
+// Installs the bridge as the global tracing subscriber, once per process.
+// Nothing is installed if the application already set its own subscriber.
+pub fn install_logging_bridge() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let subscriber = tracing_subscriber::registry().with(RcutilsLayer);
+        if tracing::subscriber::set_global_default(subscriber).is_err() {
+            tracing::warn!(
+                "A tracing subscriber is already installed, logs are not forwarded to rcutils"
+            );
+        }
+    });
+}
+
+// Sets the minimum severity of the forwarded events and of the rcutils logger
+pub fn set_log_severity(severity: c_int) -> Result<(), Error> {
+    if ![
+        SEVERITY_DEBUG,
+        SEVERITY_INFO,
+        SEVERITY_WARN,
+        SEVERITY_ERROR,
+        SEVERITY_FATAL,
+    ]
+    .contains(&severity)
+    {
+        return Err(Error::InvalidArgument(format!(
+            "unknown severity {severity}"
+        )));
+    }
+    if SEVERITY.swap(severity, Ordering::Relaxed) != severity {
+        tracing::callsite::rebuild_interest_cache();
+    }
+    if unsafe { rcutils_logging_set_logger_level(LOGGER_NAME, severity) } != 0 {
+        return Err(Error::InvalidArgument(format!(
+            "failed to set the severity of the logger to {severity}"
+        )));
+    }
+    Ok(())
+}
+
+// Logs an info message through rcutils directly, whether or not the bridge is installed
+#[track_caller]
+pub fn log_info(message: &str) {
+    let caller = std::panic::Location::caller();
+    log_to_rcutils(
+        SEVERITY_INFO,
+        module_path!(),
+        caller.file(),
+        caller.line(),
+        message,
+    );
+}
+
+// Logs a warning through rcutils directly, whether or not the bridge is installed
+#[track_caller]
+pub fn log_warn(message: &str) {
+    let caller = std::panic::Location::caller();
+    log_to_rcutils(
+        SEVERITY_WARN,
+        module_path!(),
+        caller.file(),
+        caller.line(),
+        message,
+    );
+}
+
+// Writes a message to the rcutils logger of the crate
+fn log_to_rcutils(severity: c_int, function: &str, file: &str, line: u32, message: &str) {
+    let function = to_c_string(function);
+    let file = to_c_string(file);
+    let message = to_c_string(message);
+    let location = rcutils_log_location_t {
+        function_name: function.as_ptr(),
+        file_name: file.as_ptr(),
+        line_number: line as usize,
+    };
+    unsafe {
+        rcutils_log(
+            &location,
+            severity,
+            LOGGER_NAME,
+            LOG_FORMAT,
+            message.as_ptr(),
+        )
+    };
+}
+
+// Maps a tracing level to an rcutils severity; TRACE is reported as DEBUG
+fn to_severity(level: &Level) -> c_int {
+    match *level {
+        Level::ERROR => SEVERITY_ERROR,
+        Level::WARN => SEVERITY_WARN,
+        Level::INFO => SEVERITY_INFO,
+        Level::DEBUG | Level::TRACE => SEVERITY_DEBUG,
+    }
+}
+
+// Converts a string to a C string, dropping interior NUL characters
+fn to_c_string(value: &str) -> CString {
+    CString::new(value.replace('\0', "")).unwrap_or_default()
+}
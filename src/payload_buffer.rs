@@ -0,0 +1,207 @@
+use std::any::Any;
+use std::os::raw::c_void;
+use std::ptr::null_mut;
+use std::sync::Arc;
+use zenoh::bytes::ZBytes;
+use zenoh::internal::buffers::{ZBuf, ZSlice, ZSliceBuffer};
+
+use crate::rmw::rcutils_allocator_t;
+use crate::rmw::rmw_serialized_message_t;
+use crate::Error;
+
+// PayloadSlot struct: Bytes shared with Zenoh as the single slice of a payload.
+// `data` is kept at its allocated size; its first `length` bytes are the payload.
+#[derive(Debug, Default)]
+struct PayloadSlot {
+    data: Vec<u8>,
+    length: usize,
+}
+
+impl ZSliceBuffer for PayloadSlot {
+    fn as_slice(&self) -> &[u8] {
+        &self.data[..self.length]
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// PayloadBuffer struct: A buffer written in place and then handed to Zenoh as a payload
+// without copying it. It is written again once Zenoh has dropped every payload sharing it,
+// so that a preallocated buffer serves any number of messages without allocating.
+#[derive(Default)]
+pub struct PayloadBuffer {
+    slot: Arc<PayloadSlot>,
+}
+
+impl PayloadBuffer {
+    // Creates a buffer of `capacity` bytes
+    pub fn with_capacity(capacity: usize) -> Self {
+        PayloadBuffer {
+            slot: Arc::new(PayloadSlot {
+                data: vec![0; capacity],
+                length: 0,
+            }),
+        }
+    }
+    // Checks if no payload handed to Zenoh shares the buffer anymore
+    pub fn is_writable(&self) -> bool {
+        Arc::strong_count(&self.slot) == 1
+    }
+    // Serializes a message with `serialize` into the buffer, which the allocator of the
+    // serialized message grows if needed, and returns the serialized bytes
+    pub fn serialize(
+        &mut self,
+        serialize: impl FnOnce(*mut rmw_serialized_message_t) -> Result<(), Error>,
+    ) -> Result<&[u8], Error> {
+        let slot = self.slot_mut()?;
+        let data: *mut Vec<u8> = &mut slot.data;
+        let mut message = rmw_serialized_message_t {
+            buffer: unsafe { (*data).as_mut_ptr() },
+            buffer_length: 0,
+            buffer_capacity: unsafe { (*data).len() },
+            allocator: rcutils_allocator_t {
+                allocate: Some(allocate),
+                deallocate: Some(deallocate),
+                reallocate: Some(reallocate),
+                zero_allocate: Some(zero_allocate),
+                state: data as *mut c_void,
+            },
+        };
+        serialize(&mut message)?;
+        slot.length = message.buffer_length.min(slot.data.len());
+        Ok(slot.as_slice())
+    }
+    // Copies bytes into the buffer, growing it if needed
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let slot = self.slot_mut()?;
+        if slot.data.len() < bytes.len() {
+            slot.data.resize(bytes.len(), 0);
+        }
+        slot.data[..bytes.len()].copy_from_slice(bytes);
+        slot.length = bytes.len();
+        Ok(())
+    }
+    // Returns a payload sharing the bytes written last
+    pub fn to_payload(&self) -> ZBytes {
+        ZBytes::from(ZBuf::from(ZSlice::from(self.slot.clone())))
+    }
+    // Returns the bytes to write, unless a payload still shares them
+    fn slot_mut(&mut self) -> Result<&mut PayloadSlot, Error> {
+        Arc::get_mut(&mut self.slot).ok_or_else(|| {
+            Error::Transport("a payload buffer is still shared with Zenoh".to_string())
+        })
+    }
+}
+
+// The allocator of a serialized message written into a payload buffer. Its state is the
+// `Vec` of the buffer, which it resizes in place; the buffer owns a single allocation.
+
+// Resizes the `Vec` of the allocator state to `size` bytes, keeping its contents.
+// New bytes are zeroed so that padding never exposes uninitialized memory in a payload.
+unsafe extern "C" fn reallocate(
+    _pointer: *mut c_void,
+    size: usize,
+    state: *mut c_void,
+) -> *mut c_void {
+    let data = &mut *(state as *mut Vec<u8>);
+    if data
+        .try_reserve_exact(size.saturating_sub(data.len()))
+        .is_err()
+    {
+        return null_mut();
+    }
+    data.resize(size, 0);
+    data.as_mut_ptr() as *mut c_void
+}
+
+unsafe extern "C" fn allocate(size: usize, state: *mut c_void) -> *mut c_void {
+    (*(state as *mut Vec<u8>)).clear();
+    reallocate(null_mut(), size, state)
+}
+
+unsafe extern "C" fn zero_allocate(
+    number_of_elements: usize,
+    size_of_element: usize,
+    state: *mut c_void,
+) -> *mut c_void {
+    match number_of_elements.checked_mul(size_of_element) {
+        Some(size) => allocate(size, state),
+        None => null_mut(),
+    }
+}
+
+unsafe extern "C" fn deallocate(_pointer: *mut c_void, state: *mut c_void) {
+    *(state as *mut Vec<u8>) = Vec::new();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Serializes `bytes` the way the type supports do: sizing the message, then writing it
+    fn serialize_bytes(buffer: &mut PayloadBuffer, bytes: &[u8]) -> Vec<u8> {
+        buffer
+            .serialize(|message| {
+                let message = unsafe { &mut *message };
+                message.try_reserve(bytes.len())?;
+                unsafe {
+                    std::ptr::copy_nonoverlapping(bytes.as_ptr(), message.buffer, bytes.len())
+                };
+                message.buffer_length = bytes.len();
+                Ok(())
+            })
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn serialized_bytes_are_shared_with_the_payload() {
+        let mut buffer = PayloadBuffer::default();
+        assert_eq!(serialize_bytes(&mut buffer, &[1, 2, 3, 4]), [1, 2, 3, 4]);
+        let payload = buffer.to_payload();
+        assert_eq!(payload.to_bytes().as_ref(), [1, 2, 3, 4]);
+        // The payload shares the buffer, which cannot be written until it is dropped
+        assert!(!buffer.is_writable());
+        assert!(buffer.write(&[5]).is_err());
+        drop(payload);
+        assert!(buffer.is_writable());
+    }
+
+    #[test]
+    fn a_preallocated_buffer_is_reused_in_place() {
+        let mut buffer = PayloadBuffer::with_capacity(16);
+        let data = buffer.slot.data.as_ptr();
+        serialize_bytes(&mut buffer, &[1; 16]);
+        drop(buffer.to_payload());
+        assert_eq!(serialize_bytes(&mut buffer, &[2; 8]), [2; 8]);
+        assert_eq!(buffer.to_payload().to_bytes().as_ref(), [2; 8]);
+        assert_eq!(buffer.slot.data.as_ptr(), data);
+    }
+
+    #[test]
+    fn a_small_buffer_grows_and_keeps_its_contents() {
+        let mut buffer = PayloadBuffer::with_capacity(2);
+        let grown = buffer
+            .serialize(|message| {
+                let message = unsafe { &mut *message };
+                unsafe { *message.buffer = 7 };
+                message.try_reserve(64)?;
+                message.buffer_length = 3;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(grown, [7, 0, 0]);
+    }
+
+    #[test]
+    fn written_bytes_replace_the_previous_ones() {
+        let mut buffer = PayloadBuffer::with_capacity(2);
+        buffer.write(&[1, 2, 3]).unwrap();
+        buffer.write(&[4]).unwrap();
+        assert_eq!(buffer.to_payload().to_bytes().as_ref(), [4]);
+    }
+}
@@ -0,0 +1,632 @@
+use roxmltree::{Document, Node};
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::EndpointInfo;
+use crate::Error;
+use crate::ADMIN_SPACE;
+
+// Zenoh messages controlled by each DDS permission.
+// Transient local publishers serve their history through a queryable and a liveliness token
+// under `@adv`, which subscribers query and watch. Those messages are only granted on the
+// `@adv` keys, so that a topic permission does not allow calls to a service of the same name.
+const PUBLISH_MESSAGES: &[&str] = &["put", "delete"];
+const PUBLISH_CACHE_MESSAGES: &[&str] = &["declare_queryable", "reply", "liveliness_token"];
+const SUBSCRIBE_MESSAGES: &[&str] = &["declare_subscriber"];
+const SUBSCRIBE_CACHE_MESSAGES: &[&str] =
+    &["query", "declare_liveliness_subscriber", "liveliness_query"];
+const CALL_MESSAGES: &[&str] = &["query"];
+const SERVE_MESSAGES: &[&str] = &["declare_queryable"];
+const REPLY_MESSAGES: &[&str] = &["reply"];
+const ALL_MESSAGES: &[&str] = &[
+    "put",
+    "delete",
+    "declare_subscriber",
+    "query",
+    "declare_queryable",
+    "reply",
+    "liveliness_token",
+    "declare_liveliness_subscriber",
+    "liveliness_query",
+];
+
+// Key expressions below a topic or service: its entities, and the `@adv` keys of the
+// AdvancedPublisher and AdvancedSubscriber, which `**` does not match since `@adv` is verbatim
+const ENTITY_KEYS: &str = "**";
+const CACHE_KEYS: &str = "**/@adv/**";
+
+// Grant struct: Rules of one <grant> of permissions.xml
+struct Grant {
+    common_name: String,
+    default_allow: bool,
+    rules: Vec<Rule>,
+}
+
+// Rule struct: One <allow_rule> or <deny_rule>
+struct Rule {
+    allow: bool,
+    domains: Vec<String>,
+    publish: Vec<String>,
+    subscribe: Vec<String>,
+}
+
+// AccessControl struct: Builds the access_control section of a Zenoh configuration
+#[derive(Default)]
+struct AccessControl {
+    rules: Vec<Value>,
+    subjects: Vec<Value>,
+    policies: Vec<Value>,
+}
+
+impl AccessControl {
+    // Adds a subject, matching every remote when no common name is given
+    fn add_subject(&mut self, common_name: Option<&str>) -> String {
+        let id = format!("s{}", self.subjects.len());
+        let subject = match common_name {
+            Some(common_name) => json!({ "id": id, "cert_common_names": [common_name] }),
+            None => json!({ "id": id }),
+        };
+        self.subjects.push(subject);
+        id
+    }
+
+    // Adds a rule applied to `subject` and its policy
+    fn add_rule(
+        &mut self,
+        subject: &str,
+        allow: bool,
+        flow: &str,
+        messages: &[&str],
+        key_exprs: Vec<String>,
+    ) {
+        if key_exprs.is_empty() {
+            return;
+        }
+        let id = format!("r{}", self.rules.len());
+        self.rules.push(json!({
+            "id": id,
+            "permission": if allow { "allow" } else { "deny" },
+            "flows": [flow],
+            "messages": messages,
+            "key_exprs": key_exprs,
+        }));
+        self.policies
+            .push(json!({ "rules": [id], "subjects": [subject] }));
+    }
+
+    // Adds the rules of a grant; `flow` is egress for our own grant and ingress for remote ones
+    fn add_grant(&mut self, subject: &str, flow: &str, grant: &Grant) {
+        if grant.default_allow {
+            self.add_rule(subject, true, flow, ALL_MESSAGES, all_key_exprs());
+        }
+        for rule in &grant.rules {
+            let mut add = |messages: &[&str], key_exprs: Vec<String>| {
+                self.add_rule(subject, rule.allow, flow, messages, key_exprs)
+            };
+            add(
+                PUBLISH_MESSAGES,
+                rule.key_exprs(&rule.publish, "rt/", ENTITY_KEYS),
+            );
+            add(
+                PUBLISH_CACHE_MESSAGES,
+                rule.key_exprs(&rule.publish, "rt/", CACHE_KEYS),
+            );
+            add(
+                SUBSCRIBE_MESSAGES,
+                rule.key_exprs(&rule.subscribe, "rt/", ENTITY_KEYS),
+            );
+            add(
+                SUBSCRIBE_CACHE_MESSAGES,
+                rule.key_exprs(&rule.subscribe, "rt/", CACHE_KEYS),
+            );
+            add(
+                CALL_MESSAGES,
+                rule.key_exprs(&rule.publish, "rq/", ENTITY_KEYS),
+            );
+            add(
+                SERVE_MESSAGES,
+                rule.key_exprs(&rule.subscribe, "rq/", ENTITY_KEYS),
+            );
+            add(
+                REPLY_MESSAGES,
+                rule.key_exprs(&rule.publish, "rr/", ENTITY_KEYS),
+            );
+        }
+    }
+}
+
+impl Rule {
+    // Translates the DDS topic expressions with `prefix` into the `keys` below them in every domain
+    fn key_exprs(&self, topics: &[String], prefix: &str, keys: &str) -> Vec<String> {
+        topics
+            .iter()
+            .filter_map(|topic| match topic.as_str() {
+                "*" => Some("*".to_string()),
+                topic => topic.strip_prefix(prefix).map(|name| {
+                    // Services are named rq/<name>Request and rr/<name>Reply
+                    let name = name
+                        .strip_suffix("Request")
+                        .or_else(|| name.strip_suffix("Reply"))
+                        .unwrap_or(name);
+                    to_key_chunk(&EndpointInfo::mangle_name(&format!("/{name}")))
+                }),
+            })
+            .flat_map(|chunk| {
+                self.domains
+                    .iter()
+                    .map(move |domain| format!("{domain}/{chunk}/{keys}"))
+            })
+            .collect()
+    }
+}
+
+// Loads permissions.xml and builds the access_control section for the enclave.
+// Rules of our own grant restrict what the session sends; the grants of other
+// participants restrict what is accepted from the remotes presenting their certificate.
+pub fn load_access_control(path: &Path, enclave: &str) -> Result<Value, Error> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| Error::Security(format!("{}: {e}", path.display())))?;
+    let document =
+        Document::parse(&text).map_err(|e| Error::Security(format!("{}: {e}", path.display())))?;
+    let grants: Vec<Grant> = document
+        .descendants()
+        .filter(|node| node.has_tag_name("grant"))
+        .map(parse_grant)
+        .collect();
+    let own_grant = match grants.as_slice() {
+        [grant] => Some(grant),
+        grants => grants.iter().find(|grant| grant.common_name == enclave),
+    };
+    let Some(own_grant) = own_grant else {
+        return Err(Error::Security(format!(
+            "no grant for enclave {enclave} in {}",
+            path.display()
+        )));
+    };
+
+    let mut access_control = AccessControl::default();
+    // The graph is always visible so that discovery keeps working
+    let everyone = access_control.add_subject(None);
+    for flow in ["egress", "ingress"] {
+        access_control.add_rule(
+            &everyone,
+            true,
+            flow,
+            ALL_MESSAGES,
+            vec![format!("{ADMIN_SPACE}/**")],
+        );
+    }
+    access_control.add_grant(&everyone, "egress", own_grant);
+    let remote_grants: Vec<&Grant> = grants
+        .iter()
+        .filter(|grant| !std::ptr::eq(*grant, own_grant))
+        .collect();
+    if remote_grants.is_empty() {
+        // Without the grants of the other participants, accept everything they send
+        access_control.add_rule(&everyone, true, "ingress", ALL_MESSAGES, all_key_exprs());
+    }
+    for grant in remote_grants {
+        let subject = access_control.add_subject(Some(&grant.common_name));
+        access_control.add_grant(&subject, "ingress", grant);
+    }
+    Ok(json!({
+        "enabled": true,
+        "default_permission": "deny",
+        "rules": access_control.rules,
+        "subjects": access_control.subjects,
+        "policies": access_control.policies,
+    }))
+}
+
+// Returns the key expressions matching every key, including the `@adv` ones
+fn all_key_exprs() -> Vec<String> {
+    vec![ENTITY_KEYS.to_string(), CACHE_KEYS.to_string()]
+}
+
+// Parses a <grant> element
+fn parse_grant(grant: Node) -> Grant {
+    let subject_name = child_text(grant, "subject_name").unwrap_or_default();
+    let common_name = subject_name
+        .split(',')
+        .find_map(|item| item.trim().strip_prefix("CN="))
+        .unwrap_or(subject_name)
+        .to_string();
+    let default_allow = child_text(grant, "default").is_some_and(|v| v == "ALLOW");
+    let rules = grant
+        .children()
+        .filter(|node| node.has_tag_name("allow_rule") || node.has_tag_name("deny_rule"))
+        .map(|rule| Rule {
+            allow: rule.has_tag_name("allow_rule"),
+            domains: parse_domains(rule),
+            publish: parse_topics(rule, "publish"),
+            subscribe: parse_topics(rule, "subscribe"),
+        })
+        .collect();
+    Grant {
+        common_name,
+        default_allow,
+        rules,
+    }
+}
+
+// Returns the domain ids of a rule, or a wildcard for ranges and missing domains
+fn parse_domains(rule: Node) -> Vec<String> {
+    let Some(domains) = rule.children().find(|node| node.has_tag_name("domains")) else {
+        return vec!["*".to_string()];
+    };
+    if domains.children().any(|node| node.has_tag_name("id_range")) {
+        return vec!["*".to_string()];
+    }
+    domains
+        .children()
+        .filter(|node| node.has_tag_name("id"))
+        .filter_map(|node| node.text())
+        .map(|id| id.trim().to_string())
+        .collect()
+}
+
+// Returns the topic expressions of the <publish> or <subscribe> elements of a rule
+fn parse_topics(rule: Node, tag: &str) -> Vec<String> {
+    rule.children()
+        .filter(|node| node.has_tag_name(tag))
+        .flat_map(|node| node.descendants())
+        .filter(|node| node.has_tag_name("topic"))
+        .filter_map(|node| node.text())
+        .map(|topic| topic.trim().to_string())
+        .collect()
+}
+
+// Returns the trimmed text of the first child element named `tag`
+fn child_text<'a>(node: Node<'a, '_>, tag: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(tag))
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+// Converts a DDS topic expression into a key expression chunk.
+// `*` becomes a sub-chunk wildcard; `?` and `[...]` cannot be expressed and are widened to it.
+fn to_key_chunk(expression: &str) -> String {
+    let mut chunk = String::new();
+    let mut chars = expression.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '?' | '[' => {
+                if c == '[' {
+                    chars.by_ref().find(|&c| c == ']');
+                }
+                if c != '*' {
+                    tracing::warn!("Widening `{expression}` to a `*` wildcard");
+                }
+                if !chunk.ends_with("$*") {
+                    chunk.push_str("$*");
+                }
+            }
+            c => chunk.push(c),
+        }
+    }
+    if chunk == "$*" {
+        "*".to_string()
+    } else {
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zenoh::key_expr::keyexpr;
+
+    const TALKER_GRANT: &str = r#"
+    <grant name="/talker">
+      <subject_name>CN=/talker</subject_name>
+      <validity><not_before>2024-01-01T00:00:00</not_before><not_after>2034-01-01T00:00:00</not_after></validity>
+      <allow_rule>
+        <domains><id>0</id></domains>
+        <publish><topics><topic>rt/chatter</topic><topic>rq/add_two_intsRequest</topic></topics></publish>
+        <subscribe><topics><topic>rt/parameter_events</topic><topic>rr/add_two_intsReply</topic></topics></subscribe>
+      </allow_rule>
+      <default>DENY</default>
+    </grant>"#;
+
+    const LISTENER_GRANT: &str = r#"
+    <grant name="/listener">
+      <subject_name>CN=/listener,O=ROS</subject_name>
+      <deny_rule>
+        <domains><id>0</id></domains>
+        <subscribe><topics><topic>rt/secret</topic></topics></subscribe>
+      </deny_rule>
+      <allow_rule>
+        <domains><id_range><min>0</min><max>10</max></id_range></domains>
+        <subscribe><topics><topic>*</topic></topics></subscribe>
+      </allow_rule>
+      <default>DENY</default>
+    </grant>"#;
+
+    // Writes a permissions.xml holding `grants` and loads the access control of `enclave`
+    fn load(name: &str, grants: &[&str], enclave: &str) -> Result<Value, Error> {
+        let path = std::env::temp_dir().join(format!(
+            "rmw_zenoh_permissions_{}_{name}.xml",
+            std::process::id()
+        ));
+        let text = format!(
+            "<?xml version=\"1.0\"?><dds><permissions>{}</permissions></dds>",
+            grants.concat()
+        );
+        std::fs::write(&path, text).unwrap();
+        let access_control = load_access_control(&path, enclave);
+        std::fs::remove_file(&path).unwrap();
+        access_control
+    }
+
+    // Returns the key expressions of the rules applied to a subject with a permission, a flow
+    // and a message. `None` is the subject matching every remote.
+    fn key_exprs(
+        access_control: &Value,
+        common_name: Option<&str>,
+        permission: &str,
+        flow: &str,
+        message: &str,
+    ) -> Vec<String> {
+        let subject = access_control["subjects"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|subject| match common_name {
+                Some(common_name) => subject["cert_common_names"] == json!([common_name]),
+                None => subject.get("cert_common_names").is_none(),
+            })
+            .map(|subject| subject["id"].clone())
+            .unwrap();
+        let rule_ids: Vec<&Value> = access_control["policies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|policy| policy["subjects"].as_array().unwrap().contains(&subject))
+            .flat_map(|policy| policy["rules"].as_array().unwrap())
+            .collect();
+        access_control["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|rule| rule_ids.contains(&&rule["id"]))
+            .filter(|rule| rule["permission"] == permission)
+            .filter(|rule| rule["flows"].as_array().unwrap().contains(&json!(flow)))
+            .filter(|rule| {
+                rule["messages"]
+                    .as_array()
+                    .unwrap()
+                    .contains(&json!(message))
+            })
+            .flat_map(|rule| rule["key_exprs"].as_array().unwrap())
+            .map(|key_expr| key_expr.as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn topics_map_to_publisher_and_subscriber_messages() {
+        let acl = load("topics", &[TALKER_GRANT], "/talker").unwrap();
+        assert_eq!(acl["default_permission"], "deny");
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "egress", "put"),
+            vec!["@ros2_lv/**", "0/%chatter/**"]
+        );
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "egress", "liveliness_token"),
+            vec!["@ros2_lv/**", "0/%chatter/**/@adv/**"]
+        );
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "egress", "declare_subscriber"),
+            vec!["@ros2_lv/**", "0/%parameter_events/**"]
+        );
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "egress", "liveliness_query"),
+            vec!["@ros2_lv/**", "0/%parameter_events/**/@adv/**"]
+        );
+        // A single grant is our own, and everything the remotes send is accepted
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "ingress", "put"),
+            vec!["@ros2_lv/**", "**", "**/@adv/**"]
+        );
+    }
+
+    #[test]
+    fn services_map_to_queries_and_replies() {
+        let acl = load("services", &[TALKER_GRANT], "/talker").unwrap();
+        // Publishing the request topic calls the service, subscribing to it serves the service
+        let queries = key_exprs(&acl, None, "allow", "egress", "query");
+        assert!(queries.contains(&"0/%add_two_ints/**".to_string()));
+        assert!(!queries.contains(&"0/%chatter/**".to_string()));
+        assert!(
+            !key_exprs(&acl, None, "allow", "egress", "declare_queryable")
+                .contains(&"0/%add_two_ints/**".to_string())
+        );
+        assert!(key_exprs(&acl, None, "allow", "egress", "reply")
+            .iter()
+            .all(|key_expr| key_expr != "0/%add_two_ints/**"));
+
+        let server = r#"
+        <grant name="/server">
+          <subject_name>CN=/server</subject_name>
+          <allow_rule>
+            <publish><topics><topic>rr/add_two_intsReply</topic></topics></publish>
+            <subscribe><topics><topic>rq/add_two_intsRequest</topic></topics></subscribe>
+          </allow_rule>
+        </grant>"#;
+        let acl = load("server", &[server], "/server").unwrap();
+        assert!(
+            key_exprs(&acl, None, "allow", "egress", "declare_queryable")
+                .contains(&"*/%add_two_ints/**".to_string())
+        );
+        assert!(key_exprs(&acl, None, "allow", "egress", "reply")
+            .contains(&"*/%add_two_ints/**".to_string()));
+    }
+
+    #[test]
+    fn actions_map_to_their_services_and_topics() {
+        let grant = r#"
+        <grant name="/fibonacci_client">
+          <subject_name>CN=/fibonacci_client</subject_name>
+          <allow_rule>
+            <domains><id>7</id></domains>
+            <publish><topics>
+              <topic>rq/fibonacci/_action/send_goalRequest</topic>
+              <topic>rq/fibonacci/_action/get_resultRequest</topic>
+            </topics></publish>
+            <subscribe><topics>
+              <topic>rr/fibonacci/_action/send_goalReply</topic>
+              <topic>rt/fibonacci/_action/feedback</topic>
+              <topic>rt/fibonacci/_action/status</topic>
+            </topics></subscribe>
+          </allow_rule>
+        </grant>"#;
+        let acl = load("actions", &[grant], "/fibonacci_client").unwrap();
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "egress", "query"),
+            vec![
+                "@ros2_lv/**",
+                "7/%fibonacci%_action%feedback/**/@adv/**",
+                "7/%fibonacci%_action%status/**/@adv/**",
+                "7/%fibonacci%_action%send_goal/**",
+                "7/%fibonacci%_action%get_result/**",
+            ]
+        );
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "egress", "declare_subscriber"),
+            vec![
+                "@ros2_lv/**",
+                "7/%fibonacci%_action%feedback/**",
+                "7/%fibonacci%_action%status/**",
+            ]
+        );
+    }
+
+    #[test]
+    fn wildcard_topics_become_key_expression_wildcards() {
+        assert_eq!(to_key_chunk("%*"), "%$*");
+        assert_eq!(to_key_chunk("*"), "*");
+        assert_eq!(to_key_chunk("%robot?%cmd_vel"), "%robot$*%cmd_vel");
+        assert_eq!(to_key_chunk("%robot[12]%*%cmd_vel"), "%robot$*%$*%cmd_vel");
+
+        let grant = TALKER_GRANT.replace("rt/chatter", "rt/robot*/chatter");
+        let acl = load("wildcards", &[&grant], "/talker").unwrap();
+        assert!(key_exprs(&acl, None, "allow", "egress", "put")
+            .contains(&"0/%robot$*%chatter/**".to_string()));
+    }
+
+    #[test]
+    fn remote_deny_rules_are_kept_next_to_their_allow_rules() {
+        let acl = load("remote", &[TALKER_GRANT, LISTENER_GRANT], "/talker").unwrap();
+        // Zenoh denies a message matching both an allow and a deny rule
+        assert_eq!(
+            key_exprs(
+                &acl,
+                Some("/listener"),
+                "deny",
+                "ingress",
+                "declare_subscriber"
+            ),
+            vec!["0/%secret/**"]
+        );
+        assert_eq!(
+            key_exprs(
+                &acl,
+                Some("/listener"),
+                "allow",
+                "ingress",
+                "declare_subscriber"
+            ),
+            vec!["*/*/**"]
+        );
+        assert!(key_exprs(&acl, Some("/listener"), "allow", "egress", "put").is_empty());
+        // The grants of the remotes replace the rule accepting everything
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "ingress", "put"),
+            vec!["@ros2_lv/**"]
+        );
+    }
+
+    #[test]
+    fn rules_are_scoped_to_their_domains() {
+        let grant = TALKER_GRANT.replace("<id>0</id>", "<id>3</id><id>5</id>");
+        let acl = load("domains", &[&grant], "/talker").unwrap();
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "egress", "put"),
+            vec!["@ros2_lv/**", "3/%chatter/**", "5/%chatter/**"]
+        );
+
+        let grant = TALKER_GRANT.replace("<domains><id>0</id></domains>", "");
+        let acl = load("any_domain", &[&grant], "/talker").unwrap();
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "egress", "put"),
+            vec!["@ros2_lv/**", "*/%chatter/**"]
+        );
+    }
+
+    #[test]
+    fn own_grant_is_selected_by_enclave() {
+        let acl = load("enclave", &[TALKER_GRANT, LISTENER_GRANT], "/listener").unwrap();
+        assert_eq!(
+            key_exprs(&acl, None, "allow", "egress", "declare_subscriber"),
+            vec!["@ros2_lv/**", "*/*/**"]
+        );
+        assert!(key_exprs(&acl, Some("/talker"), "allow", "ingress", "put")
+            .contains(&"0/%chatter/**".to_string()));
+        assert!(matches!(
+            load("missing", &[TALKER_GRANT, LISTENER_GRANT], "/other"),
+            Err(Error::Security(_))
+        ));
+    }
+
+    // Checks if a rule of the subject matching every remote allows `message` on `key`
+    fn allows(access_control: &Value, flow: &str, message: &str, key: &str) -> bool {
+        let key = keyexpr::new(key).unwrap();
+        key_exprs(access_control, None, "allow", flow, message)
+            .iter()
+            .any(|key_expr| keyexpr::new(key_expr).unwrap().intersects(key))
+    }
+
+    #[test]
+    fn transient_local_keys_are_granted_explicitly() {
+        let acl = load("transient_local", &[TALKER_GRANT], "/talker").unwrap();
+        // The history queryable and liveliness token of a transient local publisher
+        let cache =
+            "0/%chatter/std_msgs::msg::dds_::String_/RIHS01_df66/1a2b3c/1/@adv/pub/1a2b3c/7/_";
+        assert!(!keyexpr::new("0/%chatter/**")
+            .unwrap()
+            .intersects(keyexpr::new(cache).unwrap()));
+        for message in PUBLISH_CACHE_MESSAGES {
+            assert!(allows(&acl, "egress", message, cache), "{message}");
+        }
+        // The history of a transient local subscription
+        let cache = "0/%parameter_events/rcl_interfaces::msg::dds_::ParameterEvent_/RIHS01_4c2b/**/@adv/pub/**";
+        for message in SUBSCRIBE_CACHE_MESSAGES {
+            assert!(allows(&acl, "egress", message, cache), "{message}");
+        }
+        assert!(!allows(
+            &acl,
+            "egress",
+            "query",
+            &cache.replace("parameter_events", "chatter")
+        ));
+    }
+
+    #[test]
+    fn topic_permissions_do_not_allow_service_calls() {
+        let grant = r#"
+        <grant name="/talker">
+          <subject_name>CN=/talker</subject_name>
+          <allow_rule>
+            <publish><topics><topic>rt/add_two_ints</topic></topics></publish>
+            <subscribe><topics><topic>rt/add_two_ints</topic></topics></subscribe>
+          </allow_rule>
+        </grant>"#;
+        let acl = load("topic_only", &[grant], "/talker").unwrap();
+        // The key of a service shares its first chunks with a topic of the same name
+        let service = "0/%add_two_ints/example_interfaces::srv::dds_::AddTwoInts_/RIHS01_e118";
+        assert!(allows(&acl, "egress", "put", service));
+        for message in ["query", "declare_queryable", "reply"] {
+            assert!(!allows(&acl, "egress", message, service), "{message}");
+        }
+    }
+}
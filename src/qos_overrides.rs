@@ -0,0 +1,349 @@
+use serde_json::Value;
+use std::path::Path;
+
+use crate::config_utils::metadata_path;
+use crate::rmw::rmw_qos_durability_policy_e_RMW_QOS_POLICY_DURABILITY_TRANSIENT_LOCAL as DURABILITY_TRANSIENT_LOCAL;
+use crate::rmw::rmw_qos_durability_policy_e_RMW_QOS_POLICY_DURABILITY_VOLATILE as DURABILITY_VOLATILE;
+use crate::rmw::rmw_qos_durability_policy_t;
+use crate::rmw::rmw_qos_history_policy_e_RMW_QOS_POLICY_HISTORY_KEEP_ALL as HISTORY_KEEP_ALL;
+use crate::rmw::rmw_qos_history_policy_e_RMW_QOS_POLICY_HISTORY_KEEP_LAST as HISTORY_KEEP_LAST;
+use crate::rmw::rmw_qos_history_policy_t;
+use crate::rmw::rmw_qos_liveliness_policy_e_RMW_QOS_POLICY_LIVELINESS_AUTOMATIC as LIVELINESS_AUTOMATIC;
+use crate::rmw::rmw_qos_liveliness_policy_e_RMW_QOS_POLICY_LIVELINESS_MANUAL_BY_TOPIC as LIVELINESS_MANUAL_BY_TOPIC;
+use crate::rmw::rmw_qos_liveliness_policy_t;
+use crate::rmw::rmw_qos_profile_t;
+use crate::rmw::rmw_qos_reliability_policy_e_RMW_QOS_POLICY_RELIABILITY_BEST_EFFORT as RELIABILITY_BEST_EFFORT;
+use crate::rmw::rmw_qos_reliability_policy_e_RMW_QOS_POLICY_RELIABILITY_RELIABLE as RELIABILITY_RELIABLE;
+use crate::rmw::rmw_qos_reliability_policy_t;
+use crate::rmw::rmw_time_t;
+use crate::transport_rules::matches_name;
+use crate::Error;
+
+// QoS override file matching topic, service and node names to QoS policies
+pub const QOS_OVERRIDES_ENV: &str = "RMW_ZENOH_QOS_OVERRIDES_URI";
+// Field of the session configuration metadata that may reference the override file instead
+const QOS_OVERRIDES_METADATA_FIELD: &str = "ros_qos_overrides";
+
+// QosOverride struct: Policies replacing the ones requested by an entity.
+// Policies left unset keep the requested value.
+#[derive(Default)]
+struct QosOverride {
+    history: Option<rmw_qos_history_policy_t>,
+    depth: Option<usize>,
+    reliability: Option<rmw_qos_reliability_policy_t>,
+    durability: Option<rmw_qos_durability_policy_t>,
+    deadline: Option<rmw_time_t>,
+    lifespan: Option<rmw_time_t>,
+    liveliness: Option<rmw_qos_liveliness_policy_t>,
+    liveliness_lease_duration: Option<rmw_time_t>,
+}
+
+impl QosOverride {
+    // Replaces the policies of a QoS profile by the ones set in the override
+    fn apply(&self, qos: &mut rmw_qos_profile_t) {
+        if let Some(history) = self.history {
+            qos.history = history;
+        }
+        if let Some(depth) = self.depth {
+            qos.depth = depth;
+        }
+        if let Some(reliability) = self.reliability {
+            qos.reliability = reliability;
+        }
+        if let Some(durability) = self.durability {
+            qos.durability = durability;
+        }
+        if let Some(deadline) = self.deadline {
+            qos.deadline = deadline;
+        }
+        if let Some(lifespan) = self.lifespan {
+            qos.lifespan = lifespan;
+        }
+        if let Some(liveliness) = self.liveliness {
+            qos.liveliness = liveliness;
+        }
+        if let Some(liveliness_lease_duration) = self.liveliness_lease_duration {
+            qos.liveliness_lease_duration = liveliness_lease_duration;
+        }
+    }
+}
+
+// QosOverrideRule struct: Override applied to the entities whose name matches any of the
+// patterns, and whose node matches any of the node patterns if there are some
+struct QosOverrideRule {
+    patterns: Vec<String>,
+    node_patterns: Vec<String>,
+    qos: QosOverride,
+}
+
+// QosOverrides struct: Ordered rules, the first one matching an entity applies
+#[derive(Default)]
+pub struct QosOverrides {
+    rules: Vec<QosOverrideRule>,
+}
+
+impl QosOverrides {
+    // Loads the override file referenced by RMW_ZENOH_QOS_OVERRIDES_URI, or else by
+    // `metadata/ros_qos_overrides` in the session configuration. No file means no overrides.
+    pub fn load(config: &zenoh::Config) -> Result<Self, Error> {
+        match metadata_path(config, QOS_OVERRIDES_ENV, QOS_OVERRIDES_METADATA_FIELD) {
+            Some(path) => Self::from_file(Path::new(&path)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    // Reads a JSON override file:
+    // { "overrides": [ { "names": ["/map"], "nodes": ["/map_server"], "history": "keep_last",
+    //                    "depth": 1, "durability": "volatile", "deadline": { "sec": 1, "nsec": 0 } } ] }
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let invalid = |e: String| Error::ConfigLoad(format!("{}: {e}", path.display()));
+        let text = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let value: Value = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        let rules = value
+            .get("overrides")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("missing the \"overrides\" array".to_string()))?
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                Self::parse_rule(rule).map_err(|e| invalid(format!("override {index}: {e}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(QosOverrides { rules })
+    }
+
+    // Applies the first override matching the fully qualified name of an entity and of its node
+    pub fn apply(&self, node_name: &str, name: &str, qos: &mut rmw_qos_profile_t) {
+        let rule = self.rules.iter().find(|rule| {
+            rule.patterns
+                .iter()
+                .any(|pattern| matches_name(pattern, name))
+                && (rule.node_patterns.is_empty()
+                    || rule
+                        .node_patterns
+                        .iter()
+                        .any(|pattern| matches_name(pattern, node_name)))
+        });
+        if let Some(rule) = rule {
+            tracing::debug!("Overriding the QoS of {name} in {node_name}");
+            rule.qos.apply(qos);
+        }
+    }
+
+    // Parses an override object
+    fn parse_rule(rule: &Value) -> Result<QosOverrideRule, String> {
+        let patterns = parse_patterns(rule, "names")?.ok_or("missing the \"names\" array")?;
+        let node_patterns = parse_patterns(rule, "nodes")?.unwrap_or_default();
+        let field = |key: &str| rule.get(key).filter(|value| !value.is_null());
+        let string_field = |key: &str| match field(key) {
+            Some(value) => value
+                .as_str()
+                .map(Some)
+                .ok_or(format!("{key} must be a string")),
+            None => Ok(None),
+        };
+        let time_field = |key: &str| field(key).map(|value| parse_time(key, value)).transpose();
+        let history = string_field("history")?
+            .map(|value| parse_history(value).ok_or(format!("unknown history {value}")))
+            .transpose()?;
+        let depth = field("depth")
+            .map(|value| {
+                value
+                    .as_u64()
+                    .map(|depth| depth as usize)
+                    .ok_or("depth must be a positive integer")
+            })
+            .transpose()?;
+        let reliability = string_field("reliability")?
+            .map(|value| parse_reliability(value).ok_or(format!("unknown reliability {value}")))
+            .transpose()?;
+        let durability = string_field("durability")?
+            .map(|value| parse_durability(value).ok_or(format!("unknown durability {value}")))
+            .transpose()?;
+        let liveliness = string_field("liveliness")?
+            .map(|value| parse_liveliness(value).ok_or(format!("unknown liveliness {value}")))
+            .transpose()?;
+        Ok(QosOverrideRule {
+            patterns,
+            node_patterns,
+            qos: QosOverride {
+                history,
+                depth,
+                reliability,
+                durability,
+                deadline: time_field("deadline")?,
+                lifespan: time_field("lifespan")?,
+                liveliness,
+                liveliness_lease_duration: time_field("liveliness_lease_duration")?,
+            },
+        })
+    }
+}
+
+// Parses an optional array of name patterns
+fn parse_patterns(rule: &Value, key: &str) -> Result<Option<Vec<String>>, String> {
+    let Some(value) = rule.get(key).filter(|value| !value.is_null()) else {
+        return Ok(None);
+    };
+    value
+        .as_array()
+        .ok_or(format!("{key} must be an array"))?
+        .iter()
+        .map(|name| {
+            name.as_str()
+                .map(str::to_string)
+                .ok_or(format!("{key} must be strings"))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+// Parses a `{ "sec": .., "nsec": .. }` duration
+fn parse_time(key: &str, value: &Value) -> Result<rmw_time_t, String> {
+    let part = |name: &str| match value.get(name) {
+        Some(part) => part
+            .as_u64()
+            .ok_or(format!("{key}.{name} must be a positive integer")),
+        None => Ok(0),
+    };
+    if !value.is_object() {
+        return Err(format!("{key} must be an object with sec and nsec"));
+    }
+    Ok(rmw_time_t {
+        sec: part("sec")?,
+        nsec: part("nsec")?,
+    })
+}
+
+// Parses a history policy name
+fn parse_history(value: &str) -> Option<rmw_qos_history_policy_t> {
+    match value {
+        "keep_last" => Some(HISTORY_KEEP_LAST),
+        "keep_all" => Some(HISTORY_KEEP_ALL),
+        _ => None,
+    }
+}
+
+// Parses a reliability policy name
+fn parse_reliability(value: &str) -> Option<rmw_qos_reliability_policy_t> {
+    match value {
+        "reliable" => Some(RELIABILITY_RELIABLE),
+        "best_effort" => Some(RELIABILITY_BEST_EFFORT),
+        _ => None,
+    }
+}
+
+// Parses a durability policy name
+fn parse_durability(value: &str) -> Option<rmw_qos_durability_policy_t> {
+    match value {
+        "volatile" => Some(DURABILITY_VOLATILE),
+        "transient_local" => Some(DURABILITY_TRANSIENT_LOCAL),
+        _ => None,
+    }
+}
+
+// Parses a liveliness policy name
+fn parse_liveliness(value: &str) -> Option<rmw_qos_liveliness_policy_t> {
+    match value {
+        "automatic" => Some(LIVELINESS_AUTOMATIC),
+        "manual_by_topic" => Some(LIVELINESS_MANUAL_BY_TOPIC),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::DEFAULT_QOS;
+
+    // Returns the overrides of an "overrides" array
+    fn overrides(rules: Value) -> QosOverrides {
+        QosOverrides {
+            rules: rules
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|rule| QosOverrides::parse_rule(rule).unwrap())
+                .collect(),
+        }
+    }
+
+    // Returns the QoS of an entity requesting the default profile
+    fn qos_of(overrides: &QosOverrides, node_name: &str, name: &str) -> rmw_qos_profile_t {
+        let mut qos = DEFAULT_QOS;
+        overrides.apply(node_name, name, &mut qos);
+        qos
+    }
+
+    #[test]
+    fn the_first_matching_override_applies() {
+        let overrides = overrides(json!([
+            { "names": ["/camera/front"], "depth": 1 },
+            { "names": ["/camera/**"], "depth": 5 },
+            { "names": ["/camera/front", "/camera/rear"], "depth": 20 }
+        ]));
+        // An exact name listed first wins over a wildcard
+        assert_eq!(qos_of(&overrides, "/driver", "/camera/front").depth, 1);
+        // A wildcard listed first wins over an exact name
+        assert_eq!(qos_of(&overrides, "/driver", "/camera/rear").depth, 5);
+        // Unmatched entities keep the requested QoS
+        assert_eq!(
+            qos_of(&overrides, "/driver", "/scan").depth,
+            DEFAULT_QOS.depth
+        );
+    }
+
+    #[test]
+    fn node_patterns_restrict_an_override() {
+        let overrides = overrides(json!([
+            { "names": ["/map"], "nodes": ["/map_server"], "depth": 1 },
+            { "names": ["/map"], "nodes": ["/planning/*"], "depth": 2 }
+        ]));
+        assert_eq!(qos_of(&overrides, "/map_server", "/map").depth, 1);
+        assert_eq!(qos_of(&overrides, "/planning/planner", "/map").depth, 2);
+        assert_eq!(qos_of(&overrides, "/rviz", "/map").depth, DEFAULT_QOS.depth);
+    }
+
+    #[test]
+    fn policies_left_out_keep_the_requested_value() {
+        let overrides = overrides(json!([
+            { "names": ["/scan"], "reliability": "best_effort", "deadline": { "sec": 2 } }
+        ]));
+        let qos = qos_of(&overrides, "/lidar", "/scan");
+        assert_eq!(qos.reliability, RELIABILITY_BEST_EFFORT);
+        assert_eq!((qos.deadline.sec, qos.deadline.nsec), (2, 0));
+        assert_eq!(qos.history, DEFAULT_QOS.history);
+        assert_eq!(qos.depth, DEFAULT_QOS.depth);
+        assert_eq!(qos.durability, DEFAULT_QOS.durability);
+        assert_eq!(qos.liveliness, DEFAULT_QOS.liveliness);
+        assert_eq!(
+            (qos.lifespan.sec, qos.lifespan.nsec),
+            (DEFAULT_QOS.lifespan.sec, DEFAULT_QOS.lifespan.nsec)
+        );
+        assert_eq!(
+            (
+                qos.liveliness_lease_duration.sec,
+                qos.liveliness_lease_duration.nsec
+            ),
+            (
+                DEFAULT_QOS.liveliness_lease_duration.sec,
+                DEFAULT_QOS.liveliness_lease_duration.nsec
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_overrides_are_rejected() {
+        for rule in [
+            json!({ "depth": 1 }),
+            json!({ "names": "/scan" }),
+            json!({ "names": ["/scan"], "history": "keep_some" }),
+            json!({ "names": ["/scan"], "depth": -1 }),
+            json!({ "names": ["/scan"], "deadline": 1 }),
+        ] {
+            assert!(QosOverrides::parse_rule(&rule).is_err(), "{rule}");
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crossbeam_queue::{ArrayQueue, SegQueue};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Storage of the queue: a ring buffer of `depth` entries for KEEP_LAST, unbounded for KEEP_ALL
+enum Queue<T> {
+    KeepLast(ArrayQueue<T>),
+    KeepAll(SegQueue<T>),
+}
+
+// RecvQueue struct: Lock-free FIFO between the Zenoh RX threads and the executor threads
+pub struct RecvQueue<T> {
+    queue: Queue<T>,
+    evictions: AtomicU64,
+}
+
+impl<T> RecvQueue<T> {
+    // Creates a queue keeping the last `depth` entries, or every entry if `keep_last` is false
+    pub fn new(keep_last: bool, depth: usize) -> Self {
+        let queue = if keep_last {
+            Queue::KeepLast(ArrayQueue::new(depth.max(1)))
+        } else {
+            Queue::KeepAll(SegQueue::new())
+        };
+        RecvQueue {
+            queue,
+            evictions: AtomicU64::new(0),
+        }
+    }
+    // Pushes an entry, overwriting the oldest one if a KEEP_LAST queue is full.
+    // Returns true if an entry was evicted.
+    pub fn push(&self, data: T) -> bool {
+        match &self.queue {
+            Queue::KeepLast(queue) => {
+                let evicted = queue.force_push(data).is_some();
+                if evicted {
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+                evicted
+            }
+            Queue::KeepAll(queue) => {
+                queue.push(data);
+                false
+            }
+        }
+    }
+    // Pops the oldest entry
+    pub fn pop(&self) -> Option<T> {
+        match &self.queue {
+            Queue::KeepLast(queue) => queue.pop(),
+            Queue::KeepAll(queue) => queue.pop(),
+        }
+    }
+    // Checks if the queue is empty
+    pub fn is_empty(&self) -> bool {
+        match &self.queue {
+            Queue::KeepLast(queue) => queue.is_empty(),
+            Queue::KeepAll(queue) => queue.is_empty(),
+        }
+    }
+    // Returns the number of entries evicted by newer ones since the queue was created
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_last_evicts_the_oldest_entries() {
+        let queue = RecvQueue::new(true, 3);
+        assert!(queue.is_empty());
+        assert!(!queue.push(1));
+        assert!(!queue.push(2));
+        assert!(!queue.push(3));
+        assert!(queue.push(4));
+        assert!(queue.push(5));
+        assert_eq!(queue.evictions(), 2);
+        assert_eq!(queue.pop(), Some(3));
+        // Space freed by taking is reused before evicting again
+        assert!(!queue.push(6));
+        assert_eq!(queue.evictions(), 2);
+        assert_eq!(
+            std::iter::from_fn(|| queue.pop()).collect::<Vec<_>>(),
+            [4, 5, 6]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn keep_last_with_zero_depth_keeps_one_entry() {
+        let queue = RecvQueue::new(true, 0);
+        assert!(!queue.push(1));
+        assert!(queue.push(2));
+        assert_eq!(queue.evictions(), 1);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn keep_all_never_evicts() {
+        let queue = RecvQueue::new(false, 2);
+        for i in 0..100 {
+            assert!(!queue.push(i));
+        }
+        assert_eq!(queue.evictions(), 0);
+        assert_eq!(
+            std::iter::from_fn(|| queue.pop()).collect::<Vec<_>>(),
+            (0..100).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn concurrent_pushes_count_every_eviction() {
+        let queue = RecvQueue::new(true, 4);
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let queue = &queue;
+                scope.spawn(move || {
+                    for i in 0..1000 {
+                        queue.push(thread * 1000 + i);
+                    }
+                });
+            }
+        });
+        // Every entry beyond the depth evicted exactly one older entry
+        assert_eq!(queue.evictions(), 4000 - 4);
+        assert_eq!(std::iter::from_fn(|| queue.pop()).count(), 4);
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use zenoh::sample::Sample;
+
+use crate::Attachment;
+use crate::RMW_GID_STORAGE_SIZE_IRON;
+
+// SeenWindow struct: Sequence numbers of the last messages delivered from one publisher
+#[derive(Default)]
+struct SeenWindow {
+    set: HashSet<i64>,
+    order: VecDeque<i64>,
+}
+
+// ReplayFilter struct: Drops the messages of a transient local subscription already delivered
+// once, identified by the GID and sequence number of their publisher. A message can arrive both
+// live and in the reply to the history query, in either order.
+pub struct ReplayFilter {
+    window: usize,
+    seen: Mutex<HashMap<[i8; RMW_GID_STORAGE_SIZE_IRON], SeenWindow>>,
+}
+
+impl ReplayFilter {
+    // Creates a filter remembering the last `window` sequence numbers of each publisher
+    pub fn new(window: usize) -> Self {
+        ReplayFilter {
+            window: window.max(1),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+    // Returns true if the message of a sample was already delivered, and remembers it otherwise.
+    // Samples without a readable attachment are let through to fail when taken.
+    pub fn is_duplicate(&self, sample: &Sample) -> bool {
+        let Some(Ok(attachment)) = sample.attachment().map(Attachment::try_from) else {
+            return false;
+        };
+        let Ok(mut seen) = self.seen.lock() else {
+            return false;
+        };
+        let window = seen.entry(attachment.source_gid).or_default();
+        if !window.set.insert(attachment.sequence_number) {
+            return true;
+        }
+        window.order.push_back(attachment.sequence_number);
+        if window.order.len() > self.window {
+            if let Some(oldest) = window.order.pop_front() {
+                window.set.remove(&oldest);
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zenoh::bytes::ZBytes;
+    use zenoh::internal::traits::SampleBuilderTrait;
+    use zenoh::key_expr::KeyExpr;
+    use zenoh::sample::SampleBuilder;
+
+    use crate::RMW_GID_STORAGE_SIZE;
+
+    // A sample of publisher `publisher` with sequence number `sequence_number`
+    fn sample(publisher: u8, sequence_number: i64) -> Sample {
+        let attachment = Attachment::new(
+            sequence_number,
+            0,
+            [publisher; RMW_GID_STORAGE_SIZE as usize],
+        );
+        SampleBuilder::put(KeyExpr::try_from("replay").unwrap(), ZBytes::default())
+            .attachment(ZBytes::try_from(attachment).unwrap())
+            .into()
+    }
+
+    #[test]
+    fn replayed_messages_are_delivered_once() {
+        let filter = ReplayFilter::new(4);
+        // Live samples, then the history replaying them
+        for sequence_number in 0..3 {
+            assert!(!filter.is_duplicate(&sample(1, sequence_number)));
+        }
+        for sequence_number in 0..3 {
+            assert!(filter.is_duplicate(&sample(1, sequence_number)));
+        }
+        // The history first, then the same samples live
+        assert!(!filter.is_duplicate(&sample(2, 7)));
+        assert!(filter.is_duplicate(&sample(2, 7)));
+        // Publishers are told apart by their GID
+        assert!(!filter.is_duplicate(&sample(3, 0)));
+    }
+
+    #[test]
+    fn the_window_rolls_over_old_sequence_numbers() {
+        let filter = ReplayFilter::new(2);
+        assert!(!filter.is_duplicate(&sample(1, 0)));
+        assert!(!filter.is_duplicate(&sample(1, 1)));
+        assert!(!filter.is_duplicate(&sample(1, 2)));
+        // 0 left the window and is delivered again; 2 is still in it
+        assert!(filter.is_duplicate(&sample(1, 2)));
+        assert!(!filter.is_duplicate(&sample(1, 0)));
+        // Remembering 0 again pushed 1 out
+        assert!(!filter.is_duplicate(&sample(1, 1)));
+    }
+
+    #[test]
+    fn live_messages_after_the_replay_are_delivered() {
+        let depth = 3;
+        let filter = ReplayFilter::new(2 * depth);
+        // The history replays the last `depth` samples, which also arrived live
+        for sequence_number in 10..13 {
+            assert!(!filter.is_duplicate(&sample(1, sequence_number)));
+        }
+        for sequence_number in 10..13 {
+            assert!(filter.is_duplicate(&sample(1, sequence_number)));
+        }
+        // Every new sample is delivered, even long after the window rolled past the replay
+        for sequence_number in 13..100 {
+            assert!(!filter.is_duplicate(&sample(1, sequence_number)));
+        }
+    }
+
+    #[test]
+    fn samples_without_attachment_pass() {
+        let filter = ReplayFilter::new(1);
+        let sample: Sample =
+            SampleBuilder::put(KeyExpr::try_from("replay").unwrap(), ZBytes::default()).into();
+        assert!(!filter.is_duplicate(&sample));
+        assert!(!filter.is_duplicate(&sample));
+    }
+}
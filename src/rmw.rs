@@ -18,19 +18,27 @@ pub use rmw::*;
 use crate::check_implementation_identifier_empty;
 use crate::check_is_null_all;
 use crate::check_not_null_all;
+use crate::fail_invalid_argument;
+use crate::get_allocation;
+use crate::unwrap_or_set_error;
 use crate::validate_allocator;
 use crate::validate_implementation_identifier;
 
 // Some staff
+use crate::error::set_error_string;
 use crate::graph_cache_utils::get_endpoint_info_by_topic;
 use crate::graph_cache_utils::get_names_and_types;
 use crate::graph_cache_utils::get_node_names;
+use crate::logging::install_logging_bridge;
+use crate::logging::set_log_severity;
 use crate::rsutils::str_from_ptr;
 use crate::Client;
 use crate::Context;
 use crate::EntityType;
+use crate::Error;
 use crate::Event;
 use crate::GuardCondition;
+use crate::MessageAllocation;
 use crate::Node;
 use crate::Publisher;
 use crate::Service;
@@ -57,6 +65,46 @@ pub const NODE_NAME_VALID: rmw_ret_t = RMW_NODE_NAME_VALID as rmw_ret_t;
 pub const NAMESPACE_VALID: rmw_ret_t = RMW_NAMESPACE_VALID as rmw_ret_t;
 pub const TOPIC_VALID: rmw_ret_t = RMW_TOPIC_VALID as rmw_ret_t;
 
+// Checks that a QoS profile is valid and that a topic or service name is a fully qualified
+// name, unless the profile avoids the ROS namespace conventions
+fn validate_qos_and_name(
+    qos: &rmw_qos_profile_t,
+    name: *const ::std::os::raw::c_char,
+) -> Result<(), Error> {
+    if !qos.is_valid() {
+        return Err(Error::InvalidArgument("invalid QoS profile".to_string()));
+    }
+    let mut name_valid: i32 = 0;
+    if unsafe { rmw_validate_full_topic_name(name, &mut name_valid, null_mut()) } != RET_OK {
+        return Err(Error::InvalidArgument(
+            "failed to validate the name".to_string(),
+        ));
+    }
+    if name_valid != TOPIC_VALID && !qos.avoid_ros_namespace_conventions {
+        return Err(Error::InvalidArgument(format!(
+            "{} is not a fully qualified name",
+            str_from_ptr(name)?
+        )));
+    }
+    Ok(())
+}
+
+// Frees the strings of an entity allocated with `allocator`
+fn deallocate_strings(
+    allocator: &rcutils_allocator_t,
+    strings: &[*mut ::std::os::raw::c_char],
+) -> Result<(), Error> {
+    let Some(deallocate) = allocator.deallocate else {
+        return Err(Error::InvalidArgument(
+            "the allocator has no deallocate function".to_string(),
+        ));
+    };
+    for string in strings {
+        unsafe { deallocate(*string as *mut ::std::os::raw::c_void, allocator.state) };
+    }
+    Ok(())
+}
+
 #[no_mangle]
 pub extern "C" fn rmw_get_implementation_identifier() -> *const ::std::os::raw::c_char {
     IMPLEMENTATION_IDENTIFIER_CHAR
@@ -101,9 +149,10 @@ pub extern "C" fn rmw_init_options_copy(
 
     let src = unsafe { &*src };
     let mut tmp = src.clone();
-    let Ok(mut enclave) = StringStorage::copy_from(src.enclave, src.allocator) else {
-        return RET_ERROR;
-    };
+    let mut enclave = unwrap_or_set_error!(
+        RET_ERROR,
+        StringStorage::copy_from(src.enclave, src.allocator)
+    );
     if unsafe {
         rmw_security_options_copy(
             &src.security_options,
@@ -112,7 +161,9 @@ pub extern "C" fn rmw_init_options_copy(
         )
     } != RET_OK
     {
-        return RET_ERROR;
+        return set_error_string(&Error::Security(
+            "failed to copy the security options".to_string(),
+        ));
     }
 
     tmp.implementation_identifier = rmw_get_implementation_identifier();
@@ -129,7 +180,9 @@ pub extern "C" fn rmw_init_options_fini(init_options: *mut rmw_init_options_t) -
 
     let opt = unsafe { &mut *init_options };
     if unsafe { rmw_security_options_fini(&mut opt.security_options, &opt.allocator) } != RET_OK {
-        return RET_ERROR;
+        return set_error_string(&Error::Security(
+            "failed to finalize the security options".to_string(),
+        ));
     }
     unsafe { *opt = rmw_get_zero_initialized_init_options() };
     RET_OK
@@ -144,11 +197,12 @@ pub extern "C" fn rmw_init(
     validate_implementation_identifier!(options);
     check_implementation_identifier_empty!(RET_INVALID_ARGUMENT, context);
 
+    // Forward the logs of Zenoh and of this crate to rcutils
+    install_logging_bridge();
+
     let options = unsafe { &*options };
     let context = unsafe { &mut *context };
-    let Ok(enclave) = str_from_ptr(options.enclave) else {
-        return RET_ERROR;
-    };
+    let enclave = unwrap_or_set_error!(RET_ERROR, str_from_ptr(options.enclave));
     // Determine the domain ID; default to 0 if unspecified
     let domain_id = if options.domain_id == RMW_DEFAULT_DOMAIN_ID as usize {
         0
@@ -156,23 +210,27 @@ pub extern "C" fn rmw_init(
         options.domain_id
     };
 
-    if let Ok(ctx) = Context::new(
+    match Context::new(
         domain_id,
         options.localhost_only == LOCALHOST_ONLY_ENABLED,
         enclave,
+        &options.security_options,
         options.allocator,
     ) {
-        if rmw_init_options_copy(options, &mut context.options) != RET_OK {
-            return RET_ERROR;
-        }
-        context.instance_id = options.instance_id;
-        context.implementation_identifier = rmw_get_implementation_identifier();
-        context.actual_domain_id = domain_id;
-        context.impl_ = Box::into_raw(Box::new(ctx)) as *mut rmw_context_impl_t;
+        Ok(ctx) => {
+            // rmw_init_options_copy sets the error string
+            let ret = rmw_init_options_copy(options, &mut context.options);
+            if ret != RET_OK {
+                return ret;
+            }
+            context.instance_id = options.instance_id;
+            context.implementation_identifier = rmw_get_implementation_identifier();
+            context.actual_domain_id = domain_id;
+            context.impl_ = Box::into_raw(Box::new(ctx)) as *mut rmw_context_impl_t;
 
-        RET_OK
-    } else {
-        RET_ERROR
+            RET_OK
+        }
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -194,8 +252,10 @@ pub extern "C" fn rmw_context_fini(context: *mut rmw_context_t) -> rmw_ret_t {
     validate_implementation_identifier!(context);
     check_is_null_all!(RET_INVALID_ARGUMENT, (*context).impl_);
 
-    if unsafe { rmw_init_options_fini(&mut (*context).options) } != RET_OK {
-        return RET_ERROR;
+    // rmw_init_options_fini sets the error string
+    let ret = rmw_init_options_fini(unsafe { &mut (*context).options });
+    if ret != RET_OK {
+        return ret;
     }
     unsafe { *context = rmw_get_zero_initialized_context() };
     RET_OK
@@ -209,7 +269,7 @@ pub extern "C" fn rmw_create_guard_condition(
     validate_implementation_identifier!(null_mut(), context);
 
     let ctx_impl = unsafe { &mut *((*context).impl_ as *mut Context) };
-    let guard_condition = GuardCondition::new(ctx_impl.wait_set_cv.clone());
+    let guard_condition = GuardCondition::new(ctx_impl.wait_set_signal.clone());
 
     Box::into_raw(Box::new(rmw_guard_condition_t {
         implementation_identifier: rmw_get_implementation_identifier(),
@@ -250,28 +310,29 @@ pub extern "C" fn rmw_create_node(
         || NODE_NAME_VALID != name_valid
         || NAMESPACE_VALID != namespace_valid
     {
-        return null_mut();
+        fail_invalid_argument!(null_mut(), "invalid node name or namespace");
     }
 
     let ctx = unsafe { &mut *context };
-    let (Ok(mut name), Ok(mut namespace)) = (
-        StringStorage::copy_from(name, ctx.options.allocator),
-        StringStorage::copy_from(namespace, ctx.options.allocator),
-    ) else {
-        return null_mut();
-    };
+    let mut name = unwrap_or_set_error!(
+        null_mut(),
+        StringStorage::copy_from(name, ctx.options.allocator)
+    );
+    let mut namespace = unwrap_or_set_error!(
+        null_mut(),
+        StringStorage::copy_from(namespace, ctx.options.allocator)
+    );
 
     let ctx_impl = unsafe { &mut *(ctx.impl_ as *mut Context) };
-    let Ok(mut node) = Node::new(ctx_impl, namespace.ref_str, name.ref_str) else {
-        return null_mut();
-    };
+    let mut node = unwrap_or_set_error!(
+        null_mut(),
+        Node::new(ctx_impl, namespace.ref_str, name.ref_str)
+    );
 
-    let addr_of_guard_condition;
-    if let Ok(guard_condition) = node.graph_cache.guard_condition.lock() {
-        addr_of_guard_condition = addr_of!(*guard_condition);
-    } else {
-        return null_mut();
-    };
+    let addr_of_guard_condition = addr_of!(*unwrap_or_set_error!(
+        null_mut(),
+        node.graph_cache.guard_condition.lock().map_err(Error::from)
+    ));
     node.graph_guard_condition = Some(Box::new(rmw_guard_condition_t {
         implementation_identifier: rmw_get_implementation_identifier(),
         data: addr_of_guard_condition as *mut ::std::os::raw::c_void,
@@ -293,13 +354,12 @@ pub extern "C" fn rmw_destroy_node(node: *mut rmw_node_t) -> rmw_ret_t {
     validate_implementation_identifier!(node);
 
     let node_impl = unsafe { &mut *((*node).data as *mut Node) };
-    let allocator = &node_impl.context.allocator;
-    if let Some(deallocate) = allocator.deallocate {
-        unsafe { deallocate((*node).name as *mut std::ffi::c_void, allocator.state) };
-        unsafe { deallocate((*node).namespace_ as *mut std::ffi::c_void, allocator.state) };
-    } else {
-        return RET_ERROR;
-    }
+    unwrap_or_set_error!(
+        RET_ERROR,
+        deallocate_strings(&node_impl.context.allocator, unsafe {
+            &[(*node).name, (*node).namespace_]
+        })
+    );
 
     let _ = unsafe { *Box::from_raw((*node).data as *mut Node) };
     RET_OK
@@ -307,18 +367,37 @@ pub extern "C" fn rmw_destroy_node(node: *mut rmw_node_t) -> rmw_ret_t {
 
 #[no_mangle]
 pub extern "C" fn rmw_init_publisher_allocation(
-    _type_support: *const rosidl_message_type_support_t,
-    _message_bounds: *const rosidl_runtime_c__Sequence__bound,
-    _allocation: *mut rmw_publisher_allocation_t,
+    type_support: *const rosidl_message_type_support_t,
+    message_bounds: *const rosidl_runtime_c__Sequence__bound,
+    allocation: *mut rmw_publisher_allocation_t,
 ) -> rmw_ret_t {
-    RET_UNSUPPORTED // Not used in rcl
+    check_not_null_all!(RET_INVALID_ARGUMENT, type_support, allocation);
+    let type_support = unwrap_or_set_error!(
+        RET_ERROR,
+        TypeSupport::new_bounded_message_type_support(type_support, message_bounds)
+    );
+    let message_allocation = unwrap_or_set_error!(
+        RET_ERROR,
+        MessageAllocation::new_publisher_allocation(&type_support)
+    );
+    unsafe {
+        (*allocation).implementation_identifier = rmw_get_implementation_identifier();
+        (*allocation).data =
+            Box::into_raw(Box::new(message_allocation)) as *mut ::std::os::raw::c_void;
+    }
+    RET_OK
 }
 
 #[no_mangle]
 pub extern "C" fn rmw_fini_publisher_allocation(
-    _allocation: *mut rmw_publisher_allocation_t,
+    allocation: *mut rmw_publisher_allocation_t,
 ) -> rmw_ret_t {
-    RET_UNSUPPORTED // Not used in rcl
+    check_not_null_all!(RET_INVALID_ARGUMENT, allocation, (*allocation).data);
+    validate_implementation_identifier!(allocation);
+
+    let _ = unsafe { *Box::from_raw((*allocation).data as *mut MessageAllocation) };
+    unsafe { (*allocation).data = null_mut() };
+    RET_OK
 }
 
 #[no_mangle]
@@ -343,30 +422,22 @@ pub extern "C" fn rmw_create_publisher(
     let node_impl = unsafe { &mut *((*node).data as *mut Node) };
 
     let qos = unsafe { &*qos_profile };
+    unwrap_or_set_error!(null_mut(), validate_qos_and_name(qos, topic_name));
 
-    if !qos.is_valid() {
-        return null_mut();
-    }
-
-    let mut topic_valid: i32 = 0;
-    if unsafe { rmw_validate_full_topic_name(topic_name, &mut topic_valid, null_mut()) } != RET_OK
-        || (topic_valid != TOPIC_VALID && !qos.avoid_ros_namespace_conventions)
-    {
-        return null_mut();
-    }
-
-    let Ok(mut topic_name) = StringStorage::copy_from(topic_name, node_impl.context.allocator)
-    else {
-        return null_mut();
-    };
+    let mut topic_name = unwrap_or_set_error!(
+        null_mut(),
+        StringStorage::copy_from(topic_name, node_impl.context.allocator)
+    );
 
-    let Ok(type_support) = TypeSupport::new_message_type_support(type_support) else {
-        return null_mut();
-    };
+    let type_support = unwrap_or_set_error!(
+        null_mut(),
+        TypeSupport::new_message_type_support(type_support)
+    );
 
-    let Ok(publisher) = Publisher::new(node_impl, topic_name.ref_str, type_support, *qos) else {
-        return null_mut();
-    };
+    let publisher = unwrap_or_set_error!(
+        null_mut(),
+        Publisher::new(node_impl, topic_name.ref_str, type_support, *qos)
+    );
 
     Box::into_raw(Box::new(rmw_publisher_t {
         implementation_identifier: rmw_get_implementation_identifier(),
@@ -394,16 +465,10 @@ pub extern "C" fn rmw_destroy_publisher(
 
     let node_impl = unsafe { &mut *((*node).data as *mut Node) };
     let allocator = &node_impl.context.allocator;
-    if let Some(deallocate) = allocator.deallocate {
-        unsafe {
-            deallocate(
-                (*publisher).topic_name as *mut std::ffi::c_void,
-                allocator.state,
-            )
-        };
-    } else {
-        return RET_ERROR;
-    }
+    unwrap_or_set_error!(
+        RET_ERROR,
+        deallocate_strings(allocator, unsafe { &[(*publisher).topic_name] })
+    );
 
     let publisher = unsafe { Box::from_raw(publisher) };
     let _ = unsafe { Box::from_raw(publisher.data as *mut Publisher) };
@@ -441,14 +506,14 @@ pub extern "C" fn rmw_publish_serialized_message(
     );
     validate_implementation_identifier!(publisher);
     let serialized_message = unsafe { &*serialized_message };
-    if serialized_message.buffer_length <= 0 {
-        return RET_INVALID_ARGUMENT;
+    if serialized_message.buffer_length == 0 {
+        fail_invalid_argument!(RET_INVALID_ARGUMENT, "empty serialized message");
     }
 
     let pub_impl = unsafe { &mut *((*publisher).data as *mut Publisher) };
     match pub_impl.publish_serialized_message(serialized_message) {
         Ok(_) => RET_OK,
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -456,7 +521,7 @@ pub extern "C" fn rmw_publish_serialized_message(
 pub extern "C" fn rmw_publish(
     publisher: *const rmw_publisher_t,
     ros_message: *const ::std::os::raw::c_void,
-    _allocation: *mut rmw_publisher_allocation_t,
+    allocation: *mut rmw_publisher_allocation_t,
 ) -> rmw_ret_t {
     check_not_null_all!(
         RET_INVALID_ARGUMENT,
@@ -465,11 +530,12 @@ pub extern "C" fn rmw_publish(
         ros_message
     );
     validate_implementation_identifier!(publisher);
+    let allocation = get_allocation!(allocation);
 
     let pub_impl = unsafe { &mut *((*publisher).data as *mut Publisher) };
-    match pub_impl.publish(ros_message) {
+    match pub_impl.publish(ros_message, allocation) {
         Ok(_) => RET_OK,
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -521,11 +587,26 @@ pub extern "C" fn rmw_publisher_get_actual_qos(
 
 #[no_mangle]
 pub extern "C" fn rmw_get_serialized_message_size(
-    _type_support: *const rosidl_message_type_support_t,
-    _message_bounds: *const rosidl_runtime_c__Sequence__bound,
-    _size: *mut usize,
+    type_support: *const rosidl_message_type_support_t,
+    message_bounds: *const rosidl_runtime_c__Sequence__bound,
+    size: *mut usize,
 ) -> rmw_ret_t {
-    RET_UNSUPPORTED // Not used in rcl
+    check_not_null_all!(RET_INVALID_ARGUMENT, type_support, size);
+    let type_support = unwrap_or_set_error!(
+        RET_ERROR,
+        TypeSupport::new_bounded_message_type_support(type_support, message_bounds)
+    );
+    // Types with unbounded strings or sequences need message bounds to have a maximum size
+    match type_support.max_serialized_size() {
+        Some(max_size) => {
+            unsafe { *size = max_size };
+            RET_OK
+        }
+        None => set_error_string(&Error::TypeSupport(format!(
+            "{} has unbounded strings or sequences",
+            type_support.type_name
+        ))),
+    }
 }
 
 #[no_mangle]
@@ -557,12 +638,13 @@ pub extern "C" fn rmw_serialize(
         type_support,
         serialized_message
     );
-    let Ok(type_support) = TypeSupport::new_message_type_support(type_support) else {
-        return RET_ERROR;
-    };
+    let type_support = unwrap_or_set_error!(
+        RET_ERROR,
+        TypeSupport::new_message_type_support(type_support)
+    );
     match type_support.serialize(ros_message, serialized_message) {
         Ok(_) => RET_OK,
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -578,29 +660,49 @@ pub extern "C" fn rmw_deserialize(
         type_support,
         ros_message
     );
-    let Ok(type_support) = TypeSupport::new_message_type_support(type_support) else {
-        return RET_ERROR;
-    };
+    let type_support = unwrap_or_set_error!(
+        RET_ERROR,
+        TypeSupport::new_message_type_support(type_support)
+    );
     match type_support.deserialize(serialized_message, ros_message) {
         Ok(_) => RET_OK,
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
 #[no_mangle]
 pub extern "C" fn rmw_init_subscription_allocation(
-    _type_support: *const rosidl_message_type_support_t,
-    _message_bounds: *const rosidl_runtime_c__Sequence__bound,
-    _allocation: *mut rmw_subscription_allocation_t,
+    type_support: *const rosidl_message_type_support_t,
+    message_bounds: *const rosidl_runtime_c__Sequence__bound,
+    allocation: *mut rmw_subscription_allocation_t,
 ) -> rmw_ret_t {
-    RET_UNSUPPORTED // Not used in rcl
+    check_not_null_all!(RET_INVALID_ARGUMENT, type_support, allocation);
+    let type_support = unwrap_or_set_error!(
+        RET_ERROR,
+        TypeSupport::new_bounded_message_type_support(type_support, message_bounds)
+    );
+    let message_allocation = unwrap_or_set_error!(
+        RET_ERROR,
+        MessageAllocation::new_subscription_allocation(&type_support)
+    );
+    unsafe {
+        (*allocation).implementation_identifier = rmw_get_implementation_identifier();
+        (*allocation).data =
+            Box::into_raw(Box::new(message_allocation)) as *mut ::std::os::raw::c_void;
+    }
+    RET_OK
 }
 
 #[no_mangle]
 pub extern "C" fn rmw_fini_subscription_allocation(
-    _allocation: *mut rmw_subscription_allocation_t,
+    allocation: *mut rmw_subscription_allocation_t,
 ) -> rmw_ret_t {
-    RET_UNSUPPORTED // Not used in rcl
+    check_not_null_all!(RET_INVALID_ARGUMENT, allocation, (*allocation).data);
+    validate_implementation_identifier!(allocation);
+
+    let _ = unsafe { *Box::from_raw((*allocation).data as *mut MessageAllocation) };
+    unsafe { (*allocation).data = null_mut() };
+    RET_OK
 }
 
 #[no_mangle]
@@ -623,33 +725,23 @@ pub extern "C" fn rmw_create_subscription(
     validate_implementation_identifier!(null_mut(), node);
     let node_impl = unsafe { &mut *((*node).data as *mut Node) };
     let qos = unsafe { &*qos_policies };
-    if !qos.is_valid() {
-        return null_mut();
-    }
-
-    // Validate topic name
-    let mut topic_valid: i32 = 0;
-    if unsafe { rmw_validate_full_topic_name(topic_name, &mut topic_valid, null_mut()) } != RET_OK
-        || (topic_valid != TOPIC_VALID && !qos.avoid_ros_namespace_conventions)
-    {
-        return null_mut();
-    }
-    let Ok(mut topic_name) = StringStorage::copy_from(topic_name, node_impl.context.allocator)
-    else {
-        return null_mut();
-    };
+    unwrap_or_set_error!(null_mut(), validate_qos_and_name(qos, topic_name));
+    let mut topic_name = unwrap_or_set_error!(
+        null_mut(),
+        StringStorage::copy_from(topic_name, node_impl.context.allocator)
+    );
 
-    let Ok(type_support) = TypeSupport::new_message_type_support(type_support) else {
-        return null_mut();
-    };
+    let type_support = unwrap_or_set_error!(
+        null_mut(),
+        TypeSupport::new_message_type_support(type_support)
+    );
 
-    let Ok(subscriber) =
+    let subscriber = unwrap_or_set_error!(
+        null_mut(),
         Subscriber::new(node_impl, topic_name.ref_str, type_support, *qos, unsafe {
             (*subscription_options).ignore_local_publications
         })
-    else {
-        return null_mut();
-    };
+    );
 
     Box::into_raw(Box::new(rmw_subscription_t {
         implementation_identifier: rmw_get_implementation_identifier(),
@@ -678,16 +770,10 @@ pub extern "C" fn rmw_destroy_subscription(
 
     let node_impl = unsafe { &mut *((*node).data as *mut Node) };
     let allocator = &node_impl.context.allocator;
-    if let Some(deallocate) = allocator.deallocate {
-        unsafe {
-            deallocate(
-                (*subscription).topic_name as *mut std::ffi::c_void,
-                allocator.state,
-            )
-        };
-    } else {
-        return RET_ERROR;
-    }
+    unwrap_or_set_error!(
+        RET_ERROR,
+        deallocate_strings(allocator, unsafe { &[(*subscription).topic_name] })
+    );
 
     let subscriber = unsafe { Box::from_raw(subscription) };
     let _ = unsafe { Box::from_raw(subscriber.data as *mut Subscriber) };
@@ -778,7 +864,7 @@ pub extern "C" fn rmw_take_serialized_message_with_info(
             unsafe { *taken = res_taken };
             RET_OK
         }
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -805,7 +891,7 @@ pub extern "C" fn rmw_take_serialized_message(
             unsafe { *taken = res_taken };
             RET_OK
         }
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -815,7 +901,7 @@ pub extern "C" fn rmw_take_with_info(
     ros_message: *mut ::std::os::raw::c_void,
     taken: *mut bool,
     message_info: *mut rmw_message_info_t,
-    _allocation: *mut rmw_subscription_allocation_t,
+    allocation: *mut rmw_subscription_allocation_t,
 ) -> rmw_ret_t {
     check_not_null_all!(
         RET_INVALID_ARGUMENT,
@@ -826,14 +912,15 @@ pub extern "C" fn rmw_take_with_info(
         message_info
     );
     validate_implementation_identifier!(subscription);
+    let allocation = get_allocation!(allocation);
 
     let sub_impl = unsafe { &mut *((*subscription).data as *mut Subscriber) };
-    match sub_impl.take_message(ros_message, message_info) {
+    match sub_impl.take_message(ros_message, message_info, allocation) {
         Ok(res_taken) => {
             unsafe { *taken = res_taken };
             RET_OK
         }
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -842,7 +929,7 @@ pub extern "C" fn rmw_take(
     subscription: *const rmw_subscription_t,
     ros_message: *mut ::std::os::raw::c_void,
     taken: *mut bool,
-    _allocation: *mut rmw_subscription_allocation_t,
+    allocation: *mut rmw_subscription_allocation_t,
 ) -> rmw_ret_t {
     check_not_null_all!(
         RET_INVALID_ARGUMENT,
@@ -852,14 +939,15 @@ pub extern "C" fn rmw_take(
         taken
     );
     validate_implementation_identifier!(subscription);
+    let allocation = get_allocation!(allocation);
 
     let sub_impl = unsafe { &mut *((*subscription).data as *mut Subscriber) };
-    match sub_impl.take_message(ros_message, null_mut()) {
+    match sub_impl.take_message(ros_message, null_mut(), allocation) {
         Ok(res_taken) => {
             unsafe { *taken = res_taken };
             RET_OK
         }
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -870,7 +958,7 @@ pub extern "C" fn rmw_take_sequence(
     message_sequence: *mut rmw_message_sequence_t,
     message_info_sequence: *mut rmw_message_info_sequence_t,
     taken: *mut usize,
-    _allocation: *mut rmw_subscription_allocation_t,
+    allocation: *mut rmw_subscription_allocation_t,
 ) -> rmw_ret_t {
     check_not_null_all!(
         RET_INVALID_ARGUMENT,
@@ -883,30 +971,28 @@ pub extern "C" fn rmw_take_sequence(
         taken
     );
     validate_implementation_identifier!(subscription);
+    let allocation = get_allocation!(allocation);
 
     let taken = unsafe { &mut *taken };
     let message_sequence = unsafe { &mut *message_sequence };
     let message_info_sequence = unsafe { &mut *message_info_sequence };
     if count == 0 || count > message_sequence.capacity || count > message_info_sequence.capacity {
-        return RET_INVALID_ARGUMENT;
+        fail_invalid_argument!(
+            RET_INVALID_ARGUMENT,
+            "cannot take {count} messages into sequences of capacities {} and {}",
+            message_sequence.capacity,
+            message_info_sequence.capacity
+        );
     }
 
     let sub_impl = unsafe { &mut *((*subscription).data as *mut Subscriber) };
-    let mut taken_count = 0;
-    for _ in 0..count {
-        match sub_impl.take_message(unsafe { *message_sequence.data.add(taken_count) }, unsafe {
-            message_info_sequence.data.add(taken_count)
-        }) {
-            Ok(res_taken) => {
-                if res_taken {
-                    taken_count += 1;
-                } else {
-                    break;
-                }
-            }
-            Err(_) => break,
-        }
-    }
+    let ros_messages = unsafe { std::slice::from_raw_parts(message_sequence.data, count) };
+    let message_infos =
+        unsafe { std::slice::from_raw_parts_mut(message_info_sequence.data, count) };
+    let taken_count = match sub_impl.take_sequence(ros_messages, message_infos, allocation) {
+        Ok(taken_count) => taken_count,
+        Err(e) => return set_error_string(&e),
+    };
 
     *taken = taken_count;
     message_sequence.size = taken_count;
@@ -961,39 +1047,31 @@ pub extern "C" fn rmw_create_client(
     validate_implementation_identifier!(null_mut(), node);
     let node_impl = unsafe { &mut *((*node).data as *mut Node) };
     let qos = unsafe { &*qos_policies };
-    if !qos.is_valid() {
-        return null_mut();
-    }
-
-    // Validate service name
-    let mut service_valid: i32 = 0;
-    if unsafe { rmw_validate_full_topic_name(service_name, &mut service_valid, null_mut()) }
-        != RET_OK
-        || (service_valid != TOPIC_VALID && !qos.avoid_ros_namespace_conventions)
-    {
-        return null_mut();
-    }
-    let Ok(mut service_name) = StringStorage::copy_from(service_name, node_impl.context.allocator)
-    else {
-        return null_mut();
-    };
+    unwrap_or_set_error!(null_mut(), validate_qos_and_name(qos, service_name));
+    let mut service_name = unwrap_or_set_error!(
+        null_mut(),
+        StringStorage::copy_from(service_name, node_impl.context.allocator)
+    );
 
-    let (Ok(request_type_support), Ok(response_type_support)) = (
-        TypeSupport::new_request_type_support(type_support),
-        TypeSupport::new_response_type_support(type_support),
-    ) else {
-        return null_mut();
-    };
+    let request_type_support = unwrap_or_set_error!(
+        null_mut(),
+        TypeSupport::new_request_type_support(type_support)
+    );
+    let response_type_support = unwrap_or_set_error!(
+        null_mut(),
+        TypeSupport::new_response_type_support(type_support)
+    );
 
-    let Ok(client) = Client::new(
-        node_impl,
-        service_name.ref_str,
-        request_type_support,
-        response_type_support,
-        *qos,
-    ) else {
-        return null_mut();
-    };
+    let client = unwrap_or_set_error!(
+        null_mut(),
+        Client::new(
+            node_impl,
+            service_name.ref_str,
+            request_type_support,
+            response_type_support,
+            *qos,
+        )
+    );
 
     Box::into_raw(Box::new(rmw_client_t {
         implementation_identifier: rmw_get_implementation_identifier(),
@@ -1019,16 +1097,10 @@ pub extern "C" fn rmw_destroy_client(
 
     let node_impl = unsafe { &mut *((*node).data as *mut Node) };
     let allocator = &node_impl.context.allocator;
-    if let Some(deallocate) = allocator.deallocate {
-        unsafe {
-            deallocate(
-                (*client).service_name as *mut std::ffi::c_void,
-                allocator.state,
-            )
-        };
-    } else {
-        return RET_ERROR;
-    }
+    unwrap_or_set_error!(
+        RET_ERROR,
+        deallocate_strings(allocator, unsafe { &[(*client).service_name] })
+    );
 
     let client = unsafe { Box::from_raw(client) };
     let _ = unsafe { Box::from_raw(client.data as *mut Client) };
@@ -1056,7 +1128,7 @@ pub extern "C" fn rmw_send_request(
             unsafe { *sequence_id = seq };
             RET_OK
         }
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -1082,7 +1154,7 @@ pub extern "C" fn rmw_take_response(
             unsafe { *taken = res_taken };
             RET_OK
         }
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -1157,39 +1229,31 @@ pub extern "C" fn rmw_create_service(
     validate_implementation_identifier!(null_mut(), node);
     let node_impl = unsafe { &mut *((*node).data as *mut Node) };
     let qos = unsafe { &*qos_profile };
-    if !qos.is_valid() {
-        return null_mut();
-    }
-
-    // Validate service name
-    let mut service_valid: i32 = 0;
-    if unsafe { rmw_validate_full_topic_name(service_name, &mut service_valid, null_mut()) }
-        != RET_OK
-        || (service_valid != TOPIC_VALID && !qos.avoid_ros_namespace_conventions)
-    {
-        return null_mut();
-    }
-    let Ok(mut service_name) = StringStorage::copy_from(service_name, node_impl.context.allocator)
-    else {
-        return null_mut();
-    };
+    unwrap_or_set_error!(null_mut(), validate_qos_and_name(qos, service_name));
+    let mut service_name = unwrap_or_set_error!(
+        null_mut(),
+        StringStorage::copy_from(service_name, node_impl.context.allocator)
+    );
 
-    let (Ok(request_type_support), Ok(response_type_support)) = (
-        TypeSupport::new_request_type_support(type_support),
-        TypeSupport::new_response_type_support(type_support),
-    ) else {
-        return null_mut();
-    };
+    let request_type_support = unwrap_or_set_error!(
+        null_mut(),
+        TypeSupport::new_request_type_support(type_support)
+    );
+    let response_type_support = unwrap_or_set_error!(
+        null_mut(),
+        TypeSupport::new_response_type_support(type_support)
+    );
 
-    let Ok(service) = Service::new(
-        node_impl,
-        service_name.ref_str,
-        request_type_support,
-        response_type_support,
-        *qos,
-    ) else {
-        return null_mut();
-    };
+    let service = unwrap_or_set_error!(
+        null_mut(),
+        Service::new(
+            node_impl,
+            service_name.ref_str,
+            request_type_support,
+            response_type_support,
+            *qos,
+        )
+    );
 
     Box::into_raw(Box::new(rmw_service_t {
         implementation_identifier: rmw_get_implementation_identifier(),
@@ -1215,16 +1279,10 @@ pub extern "C" fn rmw_destroy_service(
 
     let node_impl = unsafe { &mut *((*node).data as *mut Node) };
     let allocator = &node_impl.context.allocator;
-    if let Some(deallocate) = allocator.deallocate {
-        unsafe {
-            deallocate(
-                (*service).service_name as *mut std::ffi::c_void,
-                allocator.state,
-            )
-        };
-    } else {
-        return RET_ERROR;
-    }
+    unwrap_or_set_error!(
+        RET_ERROR,
+        deallocate_strings(allocator, unsafe { &[(*service).service_name] })
+    );
 
     let service = unsafe { Box::from_raw(service) };
     let _ = unsafe { Box::from_raw(service.data as *mut Service) };
@@ -1254,7 +1312,7 @@ pub extern "C" fn rmw_take_request(
             unsafe { *taken = res_taken };
             return RET_OK;
         }
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -1276,7 +1334,7 @@ pub extern "C" fn rmw_send_response(
     let service_impl = unsafe { &mut *((*service).data as *mut Service) };
     match service_impl.send_response(request_header, ros_response) {
         Ok(_) => RET_OK,
-        Err(_) => RET_ERROR,
+        Err(e) => set_error_string(&e),
     }
 }
 
@@ -1407,38 +1465,29 @@ pub extern "C" fn rmw_wait(
             }
         }
 
-        // Prepare the condition variable associated with the guard condition in the wait set.
+        // Prepare the signal associated with the guard condition in the wait set.
         let mut data_ready = false;
         let guard_condition =
             (*((*wait_set).data as *mut rmw_guard_condition_t)).data as *mut GuardCondition;
-        let (lock, cvar) = &*(*guard_condition).wait_set_cv;
-        if let Ok(lock) = lock.lock() {
-            // Wait for data to become available.
-            if wait_timeout.is_null() {
-                // Wait indefinitely if wait_timeout is null.
-                let _unused = cvar.wait_while(lock, |_| items.iter().all(|item| item.is_empty()));
-            } else if (*wait_timeout).sec != 0 || (*wait_timeout).nsec != 0 {
-                // Wait for the specified duration.
-                let duration = Duration::new((*wait_timeout).sec, (*wait_timeout).nsec as u32);
-                let _unused = cvar.wait_timeout_while(lock, duration, |_| {
-                    items.iter().all(|item| item.is_empty())
-                });
+        let signal = &(*guard_condition).wait_set_signal;
+        // Wait indefinitely if wait_timeout is null, and not at all if it is 0.
+        let timeout = (!wait_timeout.is_null())
+            .then(|| Duration::new((*wait_timeout).sec, (*wait_timeout).nsec as u32));
+        // Wait for data to become available.
+        let _lock = unwrap_or_set_error!(
+            RET_ERROR,
+            signal.wait_while(timeout, || items.iter().all(|item| item.is_empty()))
+        );
+        // Process the items after the wait.
+        for i in 0..items.len() {
+            if items[i].is_empty() {
+                // Mark empty items as null.
+                *items_ptr[i] = std::ptr::null_mut();
             } else {
-                // No wait if wait_timeout is 0.
-            }
-            // Process the items after the wait.
-            for i in 0..items.len() {
-                if items[i].is_empty() {
-                    // Mark empty items as null.
-                    *items_ptr[i] = std::ptr::null_mut();
-                } else {
-                    // Cleanup the item and mark data as ready.
-                    items[i].cleanup();
-                    data_ready = true;
-                }
+                // Cleanup the item and mark data as ready.
+                items[i].cleanup();
+                data_ready = true;
             }
-        } else {
-            return RET_ERROR;
         }
 
         match data_ready {
@@ -1448,6 +1497,31 @@ pub extern "C" fn rmw_wait(
     }
 }
 
+// Reports the messages a subscription dropped since it was created: the ones skipped by the
+// downsampling of its transport rule, and apart from them the ones lost to a full queue
+#[no_mangle]
+pub extern "C" fn rmw_zenoh_rs_subscription_get_dropped_messages(
+    subscription: *const rmw_subscription_t,
+    downsampled: *mut u64,
+    lost: *mut u64,
+) -> rmw_ret_t {
+    check_not_null_all!(
+        RET_INVALID_ARGUMENT,
+        subscription,
+        (*subscription).data,
+        downsampled,
+        lost
+    );
+    validate_implementation_identifier!(subscription);
+
+    let sub_impl = unsafe { &*((*subscription).data as *const Subscriber) };
+    unsafe {
+        *downsampled = sub_impl.downsampled();
+        *lost = sub_impl.lost();
+    }
+    RET_OK
+}
+
 #[no_mangle]
 pub extern "C" fn rmw_subscription_set_on_new_message_callback(
     subscription: *mut rmw_subscription_t,
@@ -1458,12 +1532,16 @@ pub extern "C" fn rmw_subscription_set_on_new_message_callback(
     validate_implementation_identifier!(subscription);
 
     let subscriber = unsafe { &mut *((*subscription).data as *mut Subscriber) };
-    if let Ok(mut on_recv_callback) = subscriber.endpoint.on_recv_callback.lock() {
-        *on_recv_callback = (callback, user_data as usize);
-        RET_OK
-    } else {
-        RET_ERROR
-    }
+    let mut on_recv_callback = unwrap_or_set_error!(
+        RET_ERROR,
+        subscriber
+            .endpoint
+            .on_recv_callback
+            .lock()
+            .map_err(Error::from)
+    );
+    *on_recv_callback = (callback, user_data as usize);
+    RET_OK
 }
 
 #[no_mangle]
@@ -1476,12 +1554,16 @@ pub extern "C" fn rmw_service_set_on_new_request_callback(
     validate_implementation_identifier!(service);
 
     let service = unsafe { &mut *((*service).data as *mut Service) };
-    if let Ok(mut on_recv_callback) = service.endpoint.on_recv_callback.lock() {
-        *on_recv_callback = (callback, user_data as usize);
-        RET_OK
-    } else {
-        RET_ERROR
-    }
+    let mut on_recv_callback = unwrap_or_set_error!(
+        RET_ERROR,
+        service
+            .endpoint
+            .on_recv_callback
+            .lock()
+            .map_err(Error::from)
+    );
+    *on_recv_callback = (callback, user_data as usize);
+    RET_OK
 }
 
 #[no_mangle]
@@ -1494,12 +1576,12 @@ pub extern "C" fn rmw_client_set_on_new_response_callback(
     validate_implementation_identifier!(client);
 
     let client = unsafe { &mut *((*client).data as *mut Client) };
-    if let Ok(mut on_recv_callback) = client.endpoint.on_recv_callback.lock() {
-        *on_recv_callback = (callback, user_data as usize);
-        RET_OK
-    } else {
-        RET_ERROR
-    }
+    let mut on_recv_callback = unwrap_or_set_error!(
+        RET_ERROR,
+        client.endpoint.on_recv_callback.lock().map_err(Error::from)
+    );
+    *on_recv_callback = (callback, user_data as usize);
+    RET_OK
 }
 
 #[no_mangle]
@@ -1526,7 +1608,7 @@ pub extern "C" fn rmw_qos_profile_check_compatible(
 ) -> rmw_ret_t {
     check_not_null_all!(RET_INVALID_ARGUMENT, compatibility, reason);
     if !publisher_profile.is_valid() || !subscription_profile.is_valid() {
-        return RET_INVALID_ARGUMENT;
+        fail_invalid_argument!(RET_INVALID_ARGUMENT, "invalid QoS profile");
     }
     unsafe {
         if reason_size != 0 {
@@ -1553,22 +1635,24 @@ pub extern "C" fn rmw_publisher_event_init(
     validate_implementation_identifier!(publisher);
 
     let pub_impl = unsafe { &mut *((*publisher).data as *mut Publisher) };
-    if let Ok(mut events) = pub_impl.endpoint.events.lock() {
-        if events.contains_key(&event_type) {
-            return RET_ERROR;
-        }
-        let mut event = Box::new(Event::new(event_type));
-        unsafe {
-            (*rmw_event).implementation_identifier = rmw_get_implementation_identifier();
-            (*rmw_event).data =
-                Box::as_mut(&mut event) as *mut Event as *mut ::std::os::raw::c_void;
-            (*rmw_event).event_type = event_type;
-        }
-        events.insert(event_type, event);
-        RET_OK
-    } else {
-        RET_ERROR
+    let mut events = unwrap_or_set_error!(
+        RET_ERROR,
+        pub_impl.endpoint.events.lock().map_err(Error::from)
+    );
+    if events.contains_key(&event_type) {
+        set_error_string(&Error::InvalidArgument(format!(
+            "event {event_type} is already initialized"
+        )));
+        return RET_ERROR;
+    }
+    let mut event = Box::new(Event::new(event_type));
+    unsafe {
+        (*rmw_event).implementation_identifier = rmw_get_implementation_identifier();
+        (*rmw_event).data = Box::as_mut(&mut event) as *mut Event as *mut ::std::os::raw::c_void;
+        (*rmw_event).event_type = event_type;
     }
+    events.insert(event_type, event);
+    RET_OK
 }
 
 #[no_mangle]
@@ -1587,22 +1671,24 @@ pub extern "C" fn rmw_subscription_event_init(
     validate_implementation_identifier!(subscription);
 
     let sub_impl = unsafe { &mut *((*subscription).data as *mut Subscriber) };
-    if let Ok(mut events) = sub_impl.endpoint.events.lock() {
-        if events.contains_key(&event_type) {
-            return RET_ERROR;
-        }
-        let mut event = Box::new(Event::new(event_type));
-        unsafe {
-            (*rmw_event).implementation_identifier = rmw_get_implementation_identifier();
-            (*rmw_event).data =
-                Box::as_mut(&mut event) as *mut Event as *mut ::std::os::raw::c_void;
-            (*rmw_event).event_type = event_type;
-        }
-        events.insert(event_type, event);
-        RET_OK
-    } else {
-        RET_ERROR
+    let mut events = unwrap_or_set_error!(
+        RET_ERROR,
+        sub_impl.endpoint.events.lock().map_err(Error::from)
+    );
+    if events.contains_key(&event_type) {
+        set_error_string(&Error::InvalidArgument(format!(
+            "event {event_type} is already initialized"
+        )));
+        return RET_ERROR;
     }
+    let mut event = Box::new(Event::new(event_type));
+    unsafe {
+        (*rmw_event).implementation_identifier = rmw_get_implementation_identifier();
+        (*rmw_event).data = Box::as_mut(&mut event) as *mut Event as *mut ::std::os::raw::c_void;
+        (*rmw_event).event_type = event_type;
+    }
+    events.insert(event_type, event);
+    RET_OK
 }
 
 #[no_mangle]
@@ -1684,12 +1770,10 @@ pub extern "C" fn rmw_count_publishers(
     if unsafe { rmw_validate_full_topic_name(topic_name, &mut topic_valid, null_mut()) } != RET_OK
         || topic_valid != TOPIC_VALID
     {
-        return RET_INVALID_ARGUMENT;
+        fail_invalid_argument!(RET_INVALID_ARGUMENT, "invalid topic name");
     }
 
-    let Ok(topic_name) = str_from_ptr(topic_name) else {
-        return RET_INVALID_ARGUMENT;
-    };
+    let topic_name = unwrap_or_set_error!(RET_INVALID_ARGUMENT, str_from_ptr(topic_name));
 
     let node = unsafe { &*((*node).data as *mut Node) };
     let endpoint_count =
@@ -1714,12 +1798,10 @@ pub extern "C" fn rmw_count_subscribers(
     if unsafe { rmw_validate_full_topic_name(topic_name, &mut topic_valid, null_mut()) } != RET_OK
         || topic_valid != TOPIC_VALID
     {
-        return RET_INVALID_ARGUMENT;
+        fail_invalid_argument!(RET_INVALID_ARGUMENT, "invalid topic name");
     }
 
-    let Ok(topic_name) = str_from_ptr(topic_name) else {
-        return RET_INVALID_ARGUMENT;
-    };
+    let topic_name = unwrap_or_set_error!(RET_INVALID_ARGUMENT, str_from_ptr(topic_name));
 
     let node = unsafe { &*((*node).data as *mut Node) };
     let endpoint_count =
@@ -1942,11 +2024,329 @@ pub extern "C" fn rmw_compare_gids_equal(
 }
 
 #[no_mangle]
-pub extern "C" fn rmw_set_log_severity(_severity: rmw_log_severity_t) -> rmw_ret_t {
-    RET_OK
+pub extern "C" fn rmw_set_log_severity(severity: rmw_log_severity_t) -> rmw_ret_t {
+    match set_log_severity(severity as ::std::os::raw::c_int) {
+        Ok(_) => RET_OK,
+        Err(e) => set_error_string(&e),
+    }
 }
 
 #[no_mangle]
 pub extern "C" fn rmw_feature_supported(_feature: rmw_feature_t) -> bool {
     false // Not used in rcl
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_allocator::allocations;
+    use std::ffi::{CStr, CString};
+    use std::mem::{offset_of, size_of, zeroed};
+    use std::os::raw::{c_char, c_void};
+
+    type Member = rosidl_typesupport_introspection_c__MessageMember;
+    type Members = rosidl_typesupport_introspection_c__MessageMembers;
+
+    const INTROSPECTION_C_IDENTIFIER: *const c_char =
+        "rosidl_typesupport_introspection_c\0".as_ptr() as *const c_char;
+    const ROS_TYPE_INT64: u8 =
+        rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_INT64 as u8;
+    const ROS_TYPE_UINT32: u8 =
+        rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_UINT32 as u8;
+    const ROS_TYPE_DOUBLE: u8 =
+        rosidl_typesupport_introspection_c_field_types_rosidl_typesupport_introspection_c__ROS_TYPE_DOUBLE as u8;
+
+    const TEST_TRANSPORT_RULES: &str =
+        r#"{ "rules": [{ "names": ["/downsampled"], "downsampling_interval_ms": 60000 }] }"#;
+
+    // Message of the tests, described by an introspection type support
+    #[repr(C)]
+    #[derive(Debug, Default, PartialEq)]
+    struct Sample {
+        stamp: i64,
+        count: u32,
+        value: f64,
+    }
+
+    fn member(name: &'static str, type_id: u8, offset: usize) -> Member {
+        let mut member: Member = unsafe { zeroed() };
+        member.name_ = name.as_ptr() as *const c_char;
+        member.type_id_ = type_id;
+        member.offset_ = offset as _;
+        member
+    }
+
+    // Returns the type support itself if it is the requested one, like the rosidl lookup
+    unsafe extern "C" fn handle_function(
+        handle: *const rosidl_message_type_support_t,
+        identifier: *const c_char,
+    ) -> *const rosidl_message_type_support_t {
+        if CStr::from_ptr((*handle).typesupport_identifier) == CStr::from_ptr(identifier) {
+            handle
+        } else {
+            null()
+        }
+    }
+
+    // Introspection type support of `Sample`, leaked so that it outlives the entities
+    fn sample_type_support() -> *const rosidl_message_type_support_t {
+        let fields = Box::leak(Box::new([
+            member("stamp\0", ROS_TYPE_INT64, offset_of!(Sample, stamp)),
+            member("count\0", ROS_TYPE_UINT32, offset_of!(Sample, count)),
+            member("value\0", ROS_TYPE_DOUBLE, offset_of!(Sample, value)),
+        ]));
+        let mut members: Members = unsafe { zeroed() };
+        members.message_namespace_ = "test_msgs__msg\0".as_ptr() as *const c_char;
+        members.message_name_ = "Sample\0".as_ptr() as *const c_char;
+        members.member_count_ = fields.len() as _;
+        members.size_of_ = size_of::<Sample>();
+        members.members_ = fields.as_ptr();
+        let members = Box::leak(Box::new(members));
+        let mut type_support: rosidl_message_type_support_t = unsafe { zeroed() };
+        type_support.typesupport_identifier = INTROSPECTION_C_IDENTIFIER;
+        type_support.data = members as *const Members as *const c_void;
+        type_support.func = Some(handle_function);
+        Box::leak(Box::new(type_support))
+    }
+
+    // Initializes a context on an isolated session: no scouting, no listening, no router
+    fn init_context() -> rmw_context_t {
+        std::env::set_var(
+            "ZENOH_SESSION_CONFIG_URI",
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/config/DEFAULT_RMW_ZENOH_SESSION_CONFIG.json5"
+            ),
+        );
+        std::env::set_var(
+            "ZENOH_CONFIG_OVERRIDE",
+            "scouting/multicast/enabled=false;connect/endpoints=[];listen/endpoints=[]",
+        );
+        // Every test writes the same rules, so that concurrent tests agree on them
+        let rules = std::env::temp_dir().join("rmw_zenoh_rs_test_transport_rules.json");
+        std::fs::write(&rules, TEST_TRANSPORT_RULES).unwrap();
+        std::env::set_var("ZENOH_TRANSPORT_RULES_URI", &rules);
+        let mut options = unsafe { rmw_get_zero_initialized_init_options() };
+        assert_eq!(
+            rmw_init_options_init(&mut options, unsafe { rcutils_get_default_allocator() }),
+            RET_OK
+        );
+        options.enclave = "/\0".as_ptr() as *mut c_char;
+        let mut context = unsafe { rmw_get_zero_initialized_context() };
+        assert_eq!(rmw_init(&options, &mut context), RET_OK);
+        context
+    }
+
+    #[test]
+    fn publishing_and_taking_with_allocations_does_not_allocate() {
+        let mut context = init_context();
+        let node = rmw_create_node(
+            &mut context,
+            "allocations\0".as_ptr() as *const c_char,
+            "/\0".as_ptr() as *const c_char,
+        );
+        assert!(!node.is_null());
+        let type_support = sample_type_support();
+        let topic = "/allocation_free\0".as_ptr() as *const c_char;
+        let mut qos: rmw_qos_profile_t = unsafe { zeroed() };
+        qos.history = rmw_qos_history_policy_e_RMW_QOS_POLICY_HISTORY_KEEP_LAST;
+        qos.depth = 10;
+        qos.reliability = rmw_qos_reliability_policy_e_RMW_QOS_POLICY_RELIABILITY_RELIABLE;
+        qos.durability = rmw_qos_durability_policy_e_RMW_QOS_POLICY_DURABILITY_VOLATILE;
+        let publisher_options: rmw_publisher_options_t = unsafe { zeroed() };
+        let subscription_options: rmw_subscription_options_t = unsafe { zeroed() };
+        let publisher = rmw_create_publisher(node, type_support, topic, &qos, &publisher_options);
+        assert!(!publisher.is_null());
+        let subscription =
+            rmw_create_subscription(node, type_support, topic, &qos, &subscription_options);
+        assert!(!subscription.is_null());
+        let mut publisher_allocation: rmw_publisher_allocation_t = unsafe { zeroed() };
+        assert_eq!(
+            rmw_init_publisher_allocation(type_support, null(), &mut publisher_allocation),
+            RET_OK
+        );
+        let mut subscription_allocation: rmw_subscription_allocation_t = unsafe { zeroed() };
+        assert_eq!(
+            rmw_init_subscription_allocation(type_support, null(), &mut subscription_allocation),
+            RET_OK
+        );
+
+        // Publishes a sample and takes it back; local subscriptions receive it during the put
+        let mut round_trip = |count: u32| {
+            let sent = Sample {
+                stamp: -(count as i64),
+                count,
+                value: count as f64 / 2.0,
+            };
+            assert_eq!(
+                rmw_publish(
+                    publisher,
+                    &sent as *const Sample as *const c_void,
+                    &mut publisher_allocation,
+                ),
+                RET_OK
+            );
+            let mut received = Sample::default();
+            let mut taken = false;
+            assert_eq!(
+                rmw_take(
+                    subscription,
+                    &mut received as *mut Sample as *mut c_void,
+                    &mut taken,
+                    &mut subscription_allocation,
+                ),
+                RET_OK
+            );
+            assert!(taken);
+            assert_eq!(received, sent);
+        };
+        // Warm up the routes of the publisher and the buffers of the allocations
+        for count in 0..10 {
+            round_trip(count);
+        }
+        let before = allocations();
+        for count in 10..110 {
+            round_trip(count);
+        }
+        assert_eq!(allocations(), before);
+
+        assert_eq!(
+            rmw_fini_subscription_allocation(&mut subscription_allocation),
+            RET_OK
+        );
+        assert_eq!(
+            rmw_fini_publisher_allocation(&mut publisher_allocation),
+            RET_OK
+        );
+        assert_eq!(rmw_destroy_subscription(node, subscription), RET_OK);
+        assert_eq!(rmw_destroy_publisher(node, publisher), RET_OK);
+        assert_eq!(rmw_destroy_node(node), RET_OK);
+        assert_eq!(rmw_shutdown(&mut context), RET_OK);
+        assert_eq!(rmw_context_fini(&mut context), RET_OK);
+    }
+
+    #[test]
+    fn downsampled_and_lost_messages_are_counted_apart() {
+        let mut context = init_context();
+        let node = rmw_create_node(
+            &mut context,
+            "dropped_messages\0".as_ptr() as *const c_char,
+            "/\0".as_ptr() as *const c_char,
+        );
+        assert!(!node.is_null());
+        let type_support = sample_type_support();
+        let mut qos: rmw_qos_profile_t = unsafe { zeroed() };
+        qos.history = rmw_qos_history_policy_e_RMW_QOS_POLICY_HISTORY_KEEP_LAST;
+        qos.depth = 1;
+        qos.reliability = rmw_qos_reliability_policy_e_RMW_QOS_POLICY_RELIABILITY_RELIABLE;
+        qos.durability = rmw_qos_durability_policy_e_RMW_QOS_POLICY_DURABILITY_VOLATILE;
+        let publisher_options: rmw_publisher_options_t = unsafe { zeroed() };
+        let subscription_options: rmw_subscription_options_t = unsafe { zeroed() };
+
+        // Publishes three samples to a subscription of depth 1, returning its dropped messages
+        let dropped_messages = |topic: &str| {
+            let topic = CString::new(topic).unwrap();
+            let publisher =
+                rmw_create_publisher(node, type_support, topic.as_ptr(), &qos, &publisher_options);
+            assert!(!publisher.is_null());
+            let subscription = rmw_create_subscription(
+                node,
+                type_support,
+                topic.as_ptr(),
+                &qos,
+                &subscription_options,
+            );
+            assert!(!subscription.is_null());
+            for count in 0..3 {
+                let sample = Sample {
+                    count,
+                    ..Sample::default()
+                };
+                assert_eq!(
+                    rmw_publish(
+                        publisher,
+                        &sample as *const Sample as *const c_void,
+                        null_mut()
+                    ),
+                    RET_OK
+                );
+            }
+            let (mut downsampled, mut lost) = (0, 0);
+            assert_eq!(
+                rmw_zenoh_rs_subscription_get_dropped_messages(
+                    subscription,
+                    &mut downsampled,
+                    &mut lost
+                ),
+                RET_OK
+            );
+            assert_eq!(rmw_destroy_subscription(node, subscription), RET_OK);
+            assert_eq!(rmw_destroy_publisher(node, publisher), RET_OK);
+            (downsampled, lost)
+        };
+        // The rule keeps the first sample of each minute on /downsampled
+        assert_eq!(dropped_messages("/downsampled"), (2, 0));
+        assert_eq!(dropped_messages("/overflowed"), (0, 2));
+
+        assert_eq!(rmw_destroy_node(node), RET_OK);
+        assert_eq!(rmw_shutdown(&mut context), RET_OK);
+        assert_eq!(rmw_context_fini(&mut context), RET_OK);
+    }
+
+    #[test]
+    fn message_bounds_of_other_type_supports_are_rejected() {
+        let type_support = sample_type_support();
+        let length: usize = 8;
+        let mut bounds: rosidl_runtime_c__Sequence__bound = unsafe { zeroed() };
+        bounds.typesupport_identifier = INTROSPECTION_C_IDENTIFIER;
+        bounds.data = &length as *const usize as *const c_void;
+        let mut allocation: rmw_publisher_allocation_t = unsafe { zeroed() };
+        assert_eq!(
+            rmw_init_publisher_allocation(type_support, &bounds, &mut allocation),
+            RET_ERROR
+        );
+        let error = take_error_string();
+        assert!(error.contains("message bounds"), "{error}");
+
+        bounds.typesupport_identifier = IMPLEMENTATION_IDENTIFIER_CHAR;
+        assert_eq!(
+            rmw_init_publisher_allocation(type_support, &bounds, &mut allocation),
+            RET_OK
+        );
+        assert_eq!(rmw_fini_publisher_allocation(&mut allocation), RET_OK);
+    }
+
+    // Returns the error string of the current thread and resets it
+    fn take_error_string() -> String {
+        let error = unsafe { rcutils_get_error_string() };
+        let error = unsafe { CStr::from_ptr(error.str_.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { rcutils_reset_error() };
+        error
+    }
+
+    #[test]
+    fn null_arguments_set_the_error_string() {
+        unsafe { rcutils_reset_error() };
+        let message = Sample::default();
+        assert_eq!(
+            rmw_publish(
+                null(),
+                &message as *const Sample as *const c_void,
+                null_mut()
+            ),
+            RET_INVALID_ARGUMENT
+        );
+        let error = take_error_string();
+        assert!(error.contains("publisher is null"), "{error}");
+
+        let mut count = 0;
+        assert_eq!(
+            rmw_count_publishers(null(), null(), &mut count),
+            RET_INVALID_ARGUMENT
+        );
+        let error = take_error_string();
+        assert!(error.contains("node is null"), "{error}");
+    }
+}
@@ -2,6 +2,18 @@ use std::ffi::{c_void, CStr};
 use std::ptr::null_mut;
 
 use crate::rmw::*;
+use crate::Error;
+
+// Macro to set the rmw error string to an invalid argument message and return
+#[macro_export]
+macro_rules! fail_invalid_argument {
+    ($return_value:expr, $($message:tt)+) => {{
+        $crate::error::set_error_string(&$crate::error::Error::InvalidArgument(format!(
+            $($message)+
+        )));
+        return $return_value;
+    }};
+}
 
 // Macro to validate the allocator's function pointers
 #[macro_export]
@@ -14,7 +26,11 @@ macro_rules! validate_allocator {
                 || $allocator.reallocate.is_none()
                 || $allocator.zero_allocate.is_none()
         } {
-            return $return_value;
+            $crate::fail_invalid_argument!(
+                $return_value,
+                "{} is missing functions",
+                stringify!($allocator)
+            );
         }
     };
 }
@@ -26,7 +42,7 @@ macro_rules! check_not_null_all {
         $(
             #[allow(unused_unsafe)]
             if unsafe{$ptr.is_null()} {
-                return $return_value;
+                $crate::fail_invalid_argument!($return_value, "{} is null", stringify!($ptr));
             }
         )+
     };
@@ -39,7 +55,7 @@ macro_rules! check_is_null_all {
         $(
             #[allow(unused_unsafe)]
             if !unsafe{$ptr.is_null()} {
-                return $return_value;
+                $crate::fail_invalid_argument!($return_value, "{} is not null", stringify!($ptr));
             }
         )+
     };
@@ -51,19 +67,48 @@ macro_rules! validate_implementation_identifier {
     ($p:expr) => {
         if let Ok(identifier) = unsafe { str_from_ptr((*$p).implementation_identifier) } {
             if identifier != IMPLEMENTATION_IDENTIFIER_STR {
-                return RET_INCORRECT_RMW_IMPLEMENTATION;
+                $crate::fail_invalid_argument!(
+                    RET_INCORRECT_RMW_IMPLEMENTATION,
+                    "{} belongs to the {identifier} implementation",
+                    stringify!($p)
+                );
             }
         } else {
-            return RET_INVALID_ARGUMENT;
+            $crate::fail_invalid_argument!(
+                RET_INVALID_ARGUMENT,
+                "{} has no implementation identifier",
+                stringify!($p)
+            );
         }
     };
     ($return_value:expr, $p:expr) => {
         if let Ok(identifier) = unsafe { str_from_ptr((*$p).implementation_identifier) } {
             if identifier != IMPLEMENTATION_IDENTIFIER_STR {
-                return $return_value;
+                $crate::fail_invalid_argument!(
+                    $return_value,
+                    "{} belongs to the {identifier} implementation",
+                    stringify!($p)
+                );
             }
         } else {
-            return $return_value;
+            $crate::fail_invalid_argument!(
+                $return_value,
+                "{} has no implementation identifier",
+                stringify!($p)
+            );
+        }
+    };
+}
+
+// Macro to get the preallocated buffers of an optional publisher or subscription allocation
+#[macro_export]
+macro_rules! get_allocation {
+    ($p:expr) => {
+        if $p.is_null() || unsafe { (*$p).data.is_null() } {
+            None
+        } else {
+            validate_implementation_identifier!($p);
+            Some(unsafe { &mut *((*$p).data as *mut MessageAllocation) })
         }
     };
 }
@@ -73,7 +118,25 @@ macro_rules! validate_implementation_identifier {
 macro_rules! check_implementation_identifier_empty {
     ($return_value:expr, $p:expr) => {
         if unsafe { !(*$p).implementation_identifier.is_null() } {
-            return $return_value;
+            $crate::fail_invalid_argument!(
+                $return_value,
+                "{} is already initialized",
+                stringify!($p)
+            );
+        }
+    };
+}
+
+// Macro to unwrap a result, setting the rmw error string and returning on failure
+#[macro_export]
+macro_rules! unwrap_or_set_error {
+    ($return_value:expr, $result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(error) => {
+                $crate::error::set_error_string(&error);
+                return $return_value;
+            }
         }
     };
 }
@@ -87,19 +150,19 @@ unsafe impl Sync for rcutils_allocator_t {}
 // Methods for `rmw_serialized_message_t` for managing serialized message memory
 impl rmw_serialized_message_t {
     // Creates a new serialized message with the specified size.
-    pub fn new(size: usize, allocator: rcutils_allocator_t) -> Result<Self, ()> {
+    pub fn new(size: usize, allocator: rcutils_allocator_t) -> Result<Self, Error> {
         let mut res = unsafe { rcutils_get_zero_initialized_uint8_array() };
         (unsafe { rcutils_uint8_array_init(&mut res, size, &allocator) }
             == RMW_RET_OK as rcutils_ret_t)
             .then_some(res)
-            .ok_or(())
+            .ok_or_else(|| Error::Allocation(format!("a serialized message of {size} bytes")))
     }
     // Reserves or resizes memory for the serialized message.
-    pub fn try_reserve(&mut self, new_size: usize) -> Result<(), ()> {
+    pub fn try_reserve(&mut self, new_size: usize) -> Result<(), Error> {
         (new_size <= self.buffer_capacity
             || unsafe { rcutils_uint8_array_resize(self, new_size) == RMW_RET_OK as rcutils_ret_t })
         .then_some(())
-        .ok_or(())
+        .ok_or_else(|| Error::Allocation(format!("a serialized message of {new_size} bytes")))
     }
     // Releases memory for the serialized message.
     pub fn fini(&mut self) {
@@ -119,8 +182,15 @@ impl<'a> StringStorage<'a> {
     pub fn copy_from(
         src: *const ::std::os::raw::c_char,
         allocator: rcutils_allocator_t,
-    ) -> Result<Self, ()> {
-        validate_allocator!(Err(()), allocator);
+    ) -> Result<Self, Error> {
+        // Not validate_allocator!, which would set the error string before the caller
+        if allocator.allocate.is_none()
+            || allocator.deallocate.is_none()
+            || allocator.reallocate.is_none()
+            || allocator.zero_allocate.is_none()
+        {
+            return Err(Error::InvalidArgument("incomplete allocator".to_string()));
+        }
 
         if src.is_null() {
             return Ok(Self {
@@ -131,13 +201,16 @@ impl<'a> StringStorage<'a> {
         }
         let res = unsafe { rcutils_strdup(src, allocator) };
         if res.is_null() {
-            Err(())
+            Err(Error::Allocation("a string".to_string()))
         } else {
-            let Ok(ref_str) = str_from_ptr(res) else {
-                if let Some(deallocate) = allocator.deallocate {
-                    unsafe { deallocate(res as *mut c_void, allocator.state) };
+            let ref_str = match str_from_ptr(res) {
+                Ok(ref_str) => ref_str,
+                Err(error) => {
+                    if let Some(deallocate) = allocator.deallocate {
+                        unsafe { deallocate(res as *mut c_void, allocator.state) };
+                    }
+                    return Err(error);
                 }
-                return Err(());
             };
             Ok(Self {
                 string: res,
@@ -166,10 +239,12 @@ impl<'a> Drop for StringStorage<'a> {
 }
 
 // Converts a C-style string pointer to a Rust string slice.
-pub fn str_from_ptr<'a>(ptr: *const ::std::os::raw::c_char) -> Result<&'a str, ()> {
+pub fn str_from_ptr<'a>(ptr: *const ::std::os::raw::c_char) -> Result<&'a str, Error> {
     if ptr.is_null() {
-        Err(())
+        Err(Error::InvalidArgument("null string".to_string()))
     } else {
-        Ok(unsafe { CStr::from_ptr(ptr).to_str().map_err(|_| ())? })
+        unsafe { CStr::from_ptr(ptr) }
+            .to_str()
+            .map_err(|e| Error::InvalidArgument(format!("string is not UTF-8: {e}")))
     }
 }
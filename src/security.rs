@@ -0,0 +1,131 @@
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::config_utils::{insert_config, map_endpoints};
+use crate::permissions::load_access_control;
+use crate::rmw::rmw_security_enforcement_policy_e_RMW_SECURITY_ENFORCEMENT_ENFORCE as ENFORCEMENT_ENFORCE;
+use crate::rmw::rmw_security_options_t;
+use crate::rsutils::str_from_ptr;
+use crate::Error;
+
+// Files of an SROS2 enclave used to authenticate the participant
+const IDENTITY_CA_FILE: &str = "identity_ca.cert.pem";
+const CERTIFICATE_FILE: &str = "cert.pem";
+const PRIVATE_KEY_FILE: &str = "key.pem";
+const PERMISSIONS_FILE: &str = "permissions.xml";
+
+// SecurityOptions struct: SROS2 settings resolved from rmw_security_options_t and the environment
+pub struct SecurityOptions {
+    pub enforce: bool,
+    pub enclave: String,
+    pub enclave_path: Option<PathBuf>,
+}
+
+impl SecurityOptions {
+    // Resolves the enclave directory, either given by rcl or looked up in ROS_SECURITY_KEYSTORE
+    pub fn new(options: &rmw_security_options_t, enclave: &str) -> Self {
+        let enforce = options.enforce_security == ENFORCEMENT_ENFORCE
+            || std::env::var("ROS_SECURITY_STRATEGY").is_ok_and(|value| value == "Enforce");
+        let enclave_path = match str_from_ptr(options.security_root_path) {
+            Ok(path) if !path.is_empty() => Some(PathBuf::from(path)),
+            _ if std::env::var("ROS_SECURITY_ENABLE").is_ok_and(|value| value == "true") => {
+                std::env::var("ROS_SECURITY_KEYSTORE").ok().map(|keystore| {
+                    Path::new(&keystore)
+                        .join("enclaves")
+                        .join(enclave.trim_start_matches('/'))
+                })
+            }
+            _ => None,
+        };
+        SecurityOptions {
+            enforce,
+            enclave: enclave.to_string(),
+            enclave_path,
+        }
+    }
+
+    // Returns the identity CA, certificate and private key of the enclave if they all exist
+    fn security_files(&self) -> Option<(PathBuf, PathBuf, PathBuf)> {
+        let path = self.enclave_path.as_ref()?;
+        let files = (
+            path.join(IDENTITY_CA_FILE),
+            path.join(CERTIFICATE_FILE),
+            path.join(PRIVATE_KEY_FILE),
+        );
+        (files.0.is_file() && files.1.is_file() && files.2.is_file()).then_some(files)
+    }
+}
+
+// Configures mutual TLS with the enclave material, restricts every link to TLS and
+// installs the access control rules of the enclave.
+// Fails when the material is missing and the security strategy is Enforce.
+pub fn apply_security_options(
+    config: &mut zenoh::Config,
+    options: &SecurityOptions,
+) -> Result<(), Error> {
+    let Some((identity_ca, certificate, private_key)) = options.security_files() else {
+        if options.enforce {
+            return Err(Error::Security(format!(
+                "security is enforced but the enclave material was not found in {:?}",
+                options.enclave_path
+            )));
+        }
+        if let Some(path) = &options.enclave_path {
+            tracing::warn!(
+                "Enclave material not found in {}, running without security",
+                path.display()
+            );
+        }
+        return Ok(());
+    };
+    let settings = [
+        ("root_ca_certificate", identity_ca),
+        ("listen_certificate", certificate.clone()),
+        ("listen_private_key", private_key.clone()),
+        ("connect_certificate", certificate),
+        ("connect_private_key", private_key),
+    ];
+    for (key, path) in settings {
+        let value = Value::String(path.to_string_lossy().into_owned()).to_string();
+        insert_config(config, &format!("transport/link/tls/{key}"), &value)?;
+    }
+    // Both sides present a certificate signed by the identity CA.
+    // SROS2 certificates name the enclave, not the host, so the name is not verified.
+    insert_config(config, "transport/link/tls/enable_mtls", "true")?;
+    insert_config(config, "transport/link/tls/verify_name_on_connect", "false")?;
+    // Replace the TCP endpoints with TLS ones and refuse any other link
+    let to_tls = |endpoint: &str| Some(to_tls_endpoint(endpoint));
+    map_endpoints(config, "listen/endpoints", to_tls)?;
+    map_endpoints(config, "connect/endpoints", to_tls)?;
+    insert_config(config, "transport/link/protocols", "[\"tls\"]")?;
+    apply_permissions(config, options)
+}
+
+// Installs the access control rules generated from the permissions.xml of the enclave.
+// The signature in permissions.p7s is not verified.
+fn apply_permissions(config: &mut zenoh::Config, options: &SecurityOptions) -> Result<(), Error> {
+    let Some(path) = options
+        .enclave_path
+        .as_ref()
+        .map(|path| path.join(PERMISSIONS_FILE))
+        .filter(|path| path.is_file())
+    else {
+        if options.enforce {
+            return Err(Error::Security(format!(
+                "security is enforced but {PERMISSIONS_FILE} was not found"
+            )));
+        }
+        tracing::warn!("{PERMISSIONS_FILE} not found, running without access control");
+        return Ok(());
+    };
+    let access_control = load_access_control(&path, &options.enclave)?;
+    insert_config(config, "access_control", &access_control.to_string())
+}
+
+// Rewrites a TCP endpoint to a TLS endpoint on the same address
+fn to_tls_endpoint(endpoint: &str) -> String {
+    match endpoint.strip_prefix("tcp/") {
+        Some(address) => format!("tls/{address}"),
+        None => endpoint.to_string(),
+    }
+}
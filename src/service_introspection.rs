@@ -0,0 +1,112 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use strum::EnumString;
+
+use crate::rmw::rmw_qos_durability_policy_e_RMW_QOS_POLICY_DURABILITY_VOLATILE as DURABILITY_VOLATILE;
+use crate::Error;
+use crate::Node;
+use crate::Publisher;
+use crate::TypeSupport;
+use crate::DEFAULT_QOS;
+use crate::RMW_GID_STORAGE_SIZE_IRON;
+
+// Suffix of the topic on which service events are published
+const SERVICE_EVENT_SUFFIX: &str = "/_service_event";
+
+// IntrospectionState enum: What is published on the service event topic
+#[derive(PartialEq, Clone, Copy, EnumString)]
+pub enum IntrospectionState {
+    #[strum(serialize = "off")]
+    Off,
+    #[strum(serialize = "metadata")]
+    Metadata,
+    #[strum(serialize = "contents")]
+    Contents,
+}
+
+impl IntrospectionState {
+    // Reads the initial state from the environment, defaulting to Off
+    pub fn from_env() -> Self {
+        std::env::var("RMW_ZENOH_SERVICE_INTROSPECTION")
+            .ok()
+            .and_then(|value| value.to_lowercase().parse().ok())
+            .unwrap_or(IntrospectionState::Off)
+    }
+}
+
+// ServiceEventType enum: Event types defined in service_msgs/msg/ServiceEventInfo
+#[derive(PartialEq, Clone, Copy)]
+#[repr(u8)]
+pub enum ServiceEventType {
+    RequestSent = 0,
+    RequestReceived = 1,
+    ResponseSent = 2,
+    ResponseReceived = 3,
+}
+
+// ServiceIntrospection struct: Publishes the events of a client or a service.
+// The event publisher is only declared when the state is not Off, so that entities
+// without introspection add nothing to the graph.
+pub struct ServiceIntrospection<'a> {
+    state: IntrospectionState,
+    publisher: Option<Publisher<'a>>,
+}
+
+impl<'a> ServiceIntrospection<'a> {
+    // Constructor for creating a new ServiceIntrospection instance in the state set by the
+    // environment
+    pub fn new(
+        node: &mut Node<'a>,
+        service_name: &str,
+        service_type_support: &TypeSupport,
+    ) -> Result<Self, Error> {
+        let state = IntrospectionState::from_env();
+        let publisher = match state {
+            IntrospectionState::Off => None,
+            _ => {
+                let mut qos = DEFAULT_QOS;
+                qos.durability = DURABILITY_VOLATILE;
+                Some(Publisher::new(
+                    node,
+                    &format!("{service_name}{SERVICE_EVENT_SUFFIX}"),
+                    TypeSupport::new_service_event_type_support(service_type_support),
+                    qos,
+                )?)
+            }
+        };
+        Ok(ServiceIntrospection { state, publisher })
+    }
+
+    // Publishes a service event; the message is only included in Contents mode
+    pub fn publish(
+        &self,
+        event_type: ServiceEventType,
+        client_gid: &[i8; RMW_GID_STORAGE_SIZE_IRON],
+        sequence_number: i64,
+        type_support: &TypeSupport,
+        ros_message: *const ::std::os::raw::c_void,
+    ) -> Result<(), Error> {
+        let Some(publisher) = &self.publisher else {
+            return Ok(());
+        };
+        let message =
+            (self.state == IntrospectionState::Contents).then_some((type_support, ros_message));
+        let (request, response) = match event_type {
+            ServiceEventType::RequestSent | ServiceEventType::RequestReceived => (message, None),
+            ServiceEventType::ResponseSent | ServiceEventType::ResponseReceived => (None, message),
+        };
+        // Serialize the event into its payload and publish it
+        publisher.publish_with(|msg| {
+            TypeSupport::serialize_service_event(
+                request,
+                response,
+                event_type as u8,
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |v| v.as_nanos() as i64),
+                client_gid,
+                sequence_number,
+                msg,
+            )
+        })
+    }
+}
@@ -0,0 +1,36 @@
+// Global allocator of the tests, counting the heap allocations of each thread so that tests
+// can check that a path does not allocate. A binary has a single global allocator, so every
+// test module shares this one.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    // Allocations made by the current thread, through Rust or through a counting allocator
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// Counts an allocation of the current thread
+pub fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+}
+
+// Returns the number of allocations made by the current thread so far
+pub fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+// CountingAllocator struct: Forwards to the system allocator, counting every allocation
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
@@ -0,0 +1,389 @@
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+use zenoh::qos::{CongestionControl, Priority, QoSBuilderTrait, Reliability};
+
+use crate::config_utils::{insert_config, metadata_path};
+use crate::Compression;
+use crate::Error;
+
+// Rules file matching topic and service names to Zenoh QoS settings
+pub const TRANSPORT_RULES_ENV: &str = "ZENOH_TRANSPORT_RULES_URI";
+// Field of the session configuration metadata that may reference the rules file instead
+const TRANSPORT_RULES_METADATA_FIELD: &str = "ros_transport_rules";
+
+// TransportSettings struct: Zenoh QoS of the messages of a topic or service.
+// Settings left unset keep the Zenoh defaults of the publisher, querier or reply.
+// Messages of a topic are compressed with the codec once they reach the threshold size, and its
+// subscriptions keep one sample per downsampling interval.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TransportSettings {
+    pub priority: Option<Priority>,
+    pub express: Option<bool>,
+    pub congestion_control: Option<CongestionControl>,
+    pub reliability: Option<Reliability>,
+    pub compression: Option<Compression>,
+    pub compression_threshold: Option<usize>,
+    pub downsampling_interval: Option<Duration>,
+}
+
+impl TransportSettings {
+    // Applies the priority, express flag and congestion control to a Zenoh builder
+    pub fn apply<T: QoSBuilderTrait>(&self, mut builder: T) -> T {
+        if let Some(priority) = self.priority {
+            builder = builder.priority(priority);
+        }
+        if let Some(express) = self.express {
+            builder = builder.express(express);
+        }
+        if let Some(congestion_control) = self.congestion_control {
+            builder = builder.congestion_control(congestion_control);
+        }
+        builder
+    }
+}
+
+// TransportRule struct: Settings applied to the names matching any of the patterns
+struct TransportRule {
+    patterns: Vec<String>,
+    settings: TransportSettings,
+    router_downsampling: Option<RouterDownsampling>,
+}
+
+// RouterDownsampling struct: Egress downsampling of the topics of a rule in rmw_zenohd,
+// limited to the links of the given network interfaces if any
+struct RouterDownsampling {
+    interval: Duration,
+    interfaces: Option<Vec<String>>,
+}
+
+// TransportRules struct: Ordered rules, the first one matching a name applies
+#[derive(Default)]
+pub struct TransportRules {
+    rules: Vec<TransportRule>,
+}
+
+impl TransportRules {
+    // Loads the rules file referenced by ZENOH_TRANSPORT_RULES_URI, or else by
+    // `metadata/ros_transport_rules` in the session configuration. No file means no rules.
+    pub fn load(config: &zenoh::Config) -> Result<Self, Error> {
+        match metadata_path(config, TRANSPORT_RULES_ENV, TRANSPORT_RULES_METADATA_FIELD) {
+            Some(path) => Self::from_file(Path::new(&path)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    // Reads a JSON rules file:
+    // { "rules": [ { "names": ["/cmd_vel", "/camera/**"], "priority": "real_time",
+    //                "express": true, "congestion_control": "block", "reliability": "reliable",
+    //                "compression": "lz4", "compression_threshold": 4096,
+    //                "downsampling_interval_ms": 200, "router_downsampling_interval_ms": 200,
+    //                "router_downsampling_interfaces": ["wlan0"] } ] }
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let invalid = |e: String| Error::ConfigLoad(format!("{}: {e}", path.display()));
+        let text = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let value: Value = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        let rules = value
+            .get("rules")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("missing the \"rules\" array".to_string()))?
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                Self::parse_rule(rule).map_err(|e| invalid(format!("rule {index}: {e}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(TransportRules { rules })
+    }
+
+    // Returns the settings of the first rule matching a fully qualified topic or service name
+    pub fn get(&self, name: &str) -> TransportSettings {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.patterns
+                    .iter()
+                    .any(|pattern| matches_name(pattern, name))
+            })
+            .map(|rule| rule.settings)
+            .unwrap_or_default()
+    }
+
+    // Adds egress downsampling items to a router configuration, limiting the topics of the rules
+    // with a router downsampling interval to one sample per interval before the router forwards
+    // them on the links of their interfaces
+    pub fn apply_downsampling(&self, config: &mut zenoh::Config) -> Result<(), Error> {
+        let downsampling: Vec<Value> = self
+            .rules
+            .iter()
+            .filter_map(|rule| Some((rule, rule.router_downsampling.as_ref()?)))
+            .map(|(rule, router)| {
+                let freq = 1.0 / router.interval.as_secs_f64();
+                let rules: Vec<Value> = rule
+                    .patterns
+                    .iter()
+                    .map(|pattern| json!({ "key_expr": to_data_keyexpr(pattern), "freq": freq }))
+                    .collect();
+                let mut item = json!({ "flow": "egress", "rules": rules });
+                if let Some(interfaces) = &router.interfaces {
+                    item["interfaces"] = json!(interfaces);
+                }
+                item
+            })
+            .collect();
+        if downsampling.is_empty() {
+            return Ok(());
+        }
+        let json = config
+            .get_json("downsampling")
+            .map_err(|e| Error::ConfigLoad(format!("downsampling: {e}")))?;
+        let mut items = match serde_json::from_str(&json) {
+            Ok(Value::Array(items)) => items,
+            _ => Vec::new(),
+        };
+        items.extend(downsampling);
+        insert_config(config, "downsampling", &Value::Array(items).to_string())
+    }
+
+    // Parses a rule object
+    fn parse_rule(rule: &Value) -> Result<TransportRule, String> {
+        let patterns = rule
+            .get("names")
+            .and_then(Value::as_array)
+            .ok_or("missing the \"names\" array")?
+            .iter()
+            .map(|name| {
+                name.as_str()
+                    .map(str::to_string)
+                    .ok_or("names must be strings")
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let field = |key: &str| rule.get(key).filter(|value| !value.is_null());
+        let string_field = |key: &str| match field(key) {
+            Some(value) => value
+                .as_str()
+                .map(Some)
+                .ok_or(format!("{key} must be a string")),
+            None => Ok(None),
+        };
+        let priority = string_field("priority")?
+            .map(|value| parse_priority(value).ok_or(format!("unknown priority {value}")))
+            .transpose()?;
+        let express = field("express")
+            .map(|value| value.as_bool().ok_or("express must be a boolean"))
+            .transpose()?;
+        let congestion_control = string_field("congestion_control")?
+            .map(|value| {
+                parse_congestion_control(value).ok_or(format!("unknown congestion_control {value}"))
+            })
+            .transpose()?;
+        let reliability = string_field("reliability")?
+            .map(|value| parse_reliability(value).ok_or(format!("unknown reliability {value}")))
+            .transpose()?;
+        let compression = string_field("compression")?
+            .filter(|value| *value != "none")
+            .map(|value| {
+                Compression::from_name(value).ok_or(format!("unknown compression {value}"))
+            })
+            .transpose()?;
+        let compression_threshold = field("compression_threshold")
+            .map(|value| {
+                value
+                    .as_u64()
+                    .map(|threshold| threshold as usize)
+                    .ok_or("compression_threshold must be a positive integer")
+            })
+            .transpose()?;
+        let interval_field = |key: &str| {
+            field(key)
+                .map(|value| {
+                    value
+                        .as_u64()
+                        .filter(|interval| *interval > 0)
+                        .map(Duration::from_millis)
+                        .ok_or(format!("{key} must be a positive integer"))
+                })
+                .transpose()
+        };
+        let downsampling_interval = interval_field("downsampling_interval_ms")?;
+        let router_interfaces = field("router_downsampling_interfaces")
+            .map(|value| {
+                value
+                    .as_array()
+                    .filter(|interfaces| !interfaces.is_empty())
+                    .and_then(|interfaces| {
+                        interfaces
+                            .iter()
+                            .map(|interface| interface.as_str().map(str::to_string))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or("router_downsampling_interfaces must be a non-empty array of strings")
+            })
+            .transpose()?;
+        let router_downsampling = match interval_field("router_downsampling_interval_ms")? {
+            Some(interval) => Some(RouterDownsampling {
+                interval,
+                interfaces: router_interfaces,
+            }),
+            None if router_interfaces.is_some() => {
+                return Err(
+                    "router_downsampling_interfaces needs router_downsampling_interval_ms"
+                        .to_string(),
+                )
+            }
+            None => None,
+        };
+        Ok(TransportRule {
+            patterns,
+            settings: TransportSettings {
+                priority,
+                express,
+                congestion_control,
+                reliability,
+                compression,
+                compression_threshold,
+                downsampling_interval,
+            },
+            router_downsampling,
+        })
+    }
+}
+
+// Parses a Zenoh priority name
+fn parse_priority(value: &str) -> Option<Priority> {
+    match value {
+        "real_time" => Some(Priority::RealTime),
+        "interactive_high" => Some(Priority::InteractiveHigh),
+        "interactive_low" => Some(Priority::InteractiveLow),
+        "data_high" => Some(Priority::DataHigh),
+        "data" => Some(Priority::Data),
+        "data_low" => Some(Priority::DataLow),
+        "background" => Some(Priority::Background),
+        _ => None,
+    }
+}
+
+// Parses a Zenoh congestion control name
+fn parse_congestion_control(value: &str) -> Option<CongestionControl> {
+    match value {
+        "drop" => Some(CongestionControl::Drop),
+        "block" => Some(CongestionControl::Block),
+        _ => None,
+    }
+}
+
+// Parses a Zenoh reliability name
+fn parse_reliability(value: &str) -> Option<Reliability> {
+    match value {
+        "reliable" => Some(Reliability::Reliable),
+        "best_effort" => Some(Reliability::BestEffort),
+        _ => None,
+    }
+}
+
+// Matches a name against a pattern, segment by segment.
+// `*` matches any characters within a segment and a `**` segment matches any number of segments.
+pub fn matches_name(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+    let name: Vec<&str> = name.trim_matches('/').split('/').collect();
+    matches_segments(&pattern, &name)
+}
+
+// Converts a name pattern to the key expression of the data of the matching topics in any domain.
+// Topic names are mangled into a single chunk, so wildcards become `$*` within that chunk.
+fn to_data_keyexpr(pattern: &str) -> String {
+    let mut pattern = format!("/{}", pattern.trim_matches('/'));
+    while pattern.contains("**") {
+        pattern = pattern.replace("**", "*");
+    }
+    let chunk = pattern.replace('/', "%").replace('*', "$*");
+    format!("*/{chunk}/**")
+}
+
+// Matches name segments against pattern segments
+fn matches_segments(pattern: &[&str], name: &[&str]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => {
+            matches_segments(&pattern[1..], name)
+                || (!name.is_empty() && matches_segments(pattern, &name[1..]))
+        }
+        (Some(segment), Some(name_segment)) => {
+            matches_chunk(segment, name_segment) && matches_segments(&pattern[1..], &name[1..])
+        }
+        _ => false,
+    }
+}
+
+// Matches a single segment against a pattern where `*` matches any characters
+fn matches_chunk(pattern: &str, chunk: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = chunk.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` in the pattern
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the downsampling items a router gets from rules
+    fn router_downsampling(rules: Value) -> Value {
+        let rules = TransportRules {
+            rules: rules
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|rule| TransportRules::parse_rule(rule).unwrap())
+                .collect(),
+        };
+        let mut config = zenoh::Config::default();
+        rules.apply_downsampling(&mut config).unwrap();
+        serde_json::from_str(&config.get_json("downsampling").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn subscription_downsampling_does_not_downsample_in_the_router() {
+        let items = router_downsampling(json!([
+            { "names": ["/scan"], "downsampling_interval_ms": 100 }
+        ]));
+        assert_eq!(items, json!([]));
+    }
+
+    #[test]
+    fn router_downsampling_is_scoped_to_its_interfaces() {
+        let items = router_downsampling(json!([
+            { "names": ["/scan"], "router_downsampling_interval_ms": 100,
+              "router_downsampling_interfaces": ["wlan0"] },
+            { "names": ["/camera/**"], "router_downsampling_interval_ms": 500 }
+        ]));
+        assert_eq!(
+            items,
+            json!([
+                { "flow": "egress", "interfaces": ["wlan0"],
+                  "rules": [{ "key_expr": "*/%scan/**", "freq": 10.0 }] },
+                { "flow": "egress", "rules": [{ "key_expr": "*/%camera%$*/**", "freq": 2.0 }] }
+            ])
+        );
+    }
+
+    #[test]
+    fn router_interfaces_need_a_router_interval() {
+        let rule = json!({ "names": ["/scan"], "downsampling_interval_ms": 100,
+                           "router_downsampling_interfaces": ["wlan0"] });
+        assert!(TransportRules::parse_rule(&rule).is_err());
+    }
+}
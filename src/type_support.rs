@@ -1,20 +1,45 @@
 use std::ffi::CStr;
 
+use crate::rmw::rcutils_get_zero_initialized_allocator;
 use crate::rmw::rmw_serialized_message_t;
 use crate::rmw::rosidl_message_type_support_t;
+use crate::rmw::rosidl_runtime_c__Sequence__bound;
 use crate::rmw::rosidl_service_type_support_t;
-use crate::rmw::rs_deserialize_message;
-use crate::rmw::rs_get_message_name;
-use crate::rmw::rs_get_message_namespace;
-use crate::rmw::rs_get_message_type_support_callbacks;
-use crate::rmw::rs_get_request_type_support_callbacks;
-use crate::rmw::rs_get_response_type_support_callbacks;
-use crate::rmw::rs_serialize_message;
+use crate::rmw::rs_type_support_kind_e_RS_TYPE_SUPPORT_FASTRTPS as KIND_FASTRTPS;
+use crate::rmw::rs_type_support_kind_t;
+use crate::Error;
+use crate::IMPLEMENTATION_IDENTIFIER_STR;
+use crate::RMW_GID_STORAGE_SIZE_IRON;
+
+// (De)serialization goes through fastcdr, or through the Rust CDR engine with `rust-cdr`
+#[cfg(feature = "rust-cdr")]
+use crate::cdr_type_support::{
+    rs_deserialize_message, rs_get_max_serialized_size, rs_get_message_introspection_callbacks,
+    rs_get_message_name, rs_get_message_namespace, rs_get_message_type_support_callbacks,
+    rs_get_request_type_support_callbacks, rs_get_response_type_support_callbacks,
+    rs_serialize_message, rs_serialize_service_event,
+};
+#[cfg(not(feature = "rust-cdr"))]
+use crate::rmw::{
+    rs_deserialize_message, rs_get_max_serialized_size, rs_get_message_introspection_callbacks,
+    rs_get_message_name, rs_get_message_namespace, rs_get_message_type_support_callbacks,
+    rs_get_request_type_support_callbacks, rs_get_response_type_support_callbacks,
+    rs_serialize_message, rs_serialize_service_event,
+};
+
+// Type support identifier of the message bounds this implementation reads
+const MESSAGE_BOUNDS_IDENTIFIER: &str = IMPLEMENTATION_IDENTIFIER_STR;
 
 // Represents type support for ROS messages or services, including serialization and deserialization.
+// Types are (de)serialized by their FastRTPS type support, or by walking their introspection
+// type support when they were generated without it.
+#[derive(Clone)]
 pub struct TypeSupport {
     pub type_name: String,
+    kind: rs_type_support_kind_t,
     type_support: *const ::std::os::raw::c_void,
+    // Length counted for unbounded strings and sequences by max_serialized_size, 0 if unknown
+    unbounded_length: usize,
 }
 
 // Enable thread-safe usage of `TypeSupport`
@@ -25,80 +50,171 @@ impl TypeSupport {
     // Creates a new `TypeSupport` for a message type.
     pub fn new_message_type_support(
         type_support: *const rosidl_message_type_support_t,
-    ) -> Result<Self, ()> {
-        let type_support = unsafe { rs_get_message_type_support_callbacks(type_support) };
-        if !type_support.is_null() {
-            Ok(TypeSupport {
-                type_name: Self::get_type_name(type_support, "")?,
-                type_support,
-            })
-        } else {
-            Err(())
+    ) -> Result<Self, Error> {
+        let mut kind = KIND_FASTRTPS;
+        let type_support =
+            unsafe { rs_get_message_type_support_callbacks(type_support, &mut kind) };
+        Self::from_callbacks(kind, type_support, "", "message")
+    }
+    // Creates a new `TypeSupport` for a message type whose unbounded strings and sequences are
+    // limited by message bounds. No rosidl generator fills them, so this implementation defines
+    // its own: bounds identified by MESSAGE_BOUNDS_IDENTIFIER, whose data points to the size_t
+    // maximum length of every unbounded string and sequence, including nested ones. Bounds of
+    // other type supports are rejected rather than read with a layout they may not have.
+    // Only introspection type supports can count them.
+    pub fn new_bounded_message_type_support(
+        type_support: *const rosidl_message_type_support_t,
+        message_bounds: *const rosidl_runtime_c__Sequence__bound,
+    ) -> Result<Self, Error> {
+        if message_bounds.is_null() {
+            return Self::new_message_type_support(type_support);
+        }
+        let identifier = unsafe { (*message_bounds).typesupport_identifier };
+        if identifier.is_null()
+            || unsafe { CStr::from_ptr(identifier) }.to_bytes()
+                != MESSAGE_BOUNDS_IDENTIFIER.as_bytes()
+        {
+            return Err(Error::InvalidArgument(format!(
+                "message bounds must be identified by {MESSAGE_BOUNDS_IDENTIFIER}"
+            )));
+        }
+        let length = unsafe { (*message_bounds).data } as *const usize;
+        if length.is_null() {
+            return Err(Error::InvalidArgument(
+                "message bounds without a maximum length".to_string(),
+            ));
         }
+        let mut kind = KIND_FASTRTPS;
+        let type_support =
+            unsafe { rs_get_message_introspection_callbacks(type_support, &mut kind) };
+        if type_support.is_null() {
+            return Err(Error::TypeSupport(
+                "no introspection type support to apply the message bounds".to_string(),
+            ));
+        }
+        let mut type_support = Self::from_callbacks(kind, type_support, "", "message")?;
+        type_support.unbounded_length = unsafe { *length };
+        Ok(type_support)
     }
     // Creates a new `TypeSupport` for a service request type.
     pub fn new_request_type_support(
         type_support: *const rosidl_service_type_support_t,
-    ) -> Result<Self, ()> {
-        let type_support = unsafe { rs_get_request_type_support_callbacks(type_support) };
-        if !type_support.is_null() {
-            Ok(TypeSupport {
-                type_name: Self::get_type_name(type_support, "_Request")?,
-                type_support,
-            })
-        } else {
-            Err(())
-        }
+    ) -> Result<Self, Error> {
+        let mut kind = KIND_FASTRTPS;
+        let type_support =
+            unsafe { rs_get_request_type_support_callbacks(type_support, &mut kind) };
+        Self::from_callbacks(kind, type_support, "_Request", "request")
     }
     // Creates a new `TypeSupport` for a service response type.
     pub fn new_response_type_support(
         type_support: *const rosidl_service_type_support_t,
-    ) -> Result<Self, ()> {
-        let type_support = unsafe { rs_get_response_type_support_callbacks(type_support) };
-        if !type_support.is_null() {
-            Ok(TypeSupport {
-                type_name: Self::get_type_name(type_support, "_Response")?,
-                type_support,
-            })
-        } else {
-            Err(())
+    ) -> Result<Self, Error> {
+        let mut kind = KIND_FASTRTPS;
+        let type_support =
+            unsafe { rs_get_response_type_support_callbacks(type_support, &mut kind) };
+        Self::from_callbacks(kind, type_support, "_Response", "response")
+    }
+    // Creates a `TypeSupport` from the data returned by the C++ lookup functions.
+    fn from_callbacks(
+        kind: rs_type_support_kind_t,
+        type_support: *const ::std::os::raw::c_void,
+        type_name_suffix: &str,
+        description: &str,
+    ) -> Result<Self, Error> {
+        if type_support.is_null() {
+            return Err(Error::TypeSupport(format!(
+                "no fastrtps or introspection type support for the {description}"
+            )));
+        }
+        Ok(TypeSupport {
+            type_name: Self::get_type_name(kind, type_support, type_name_suffix)?,
+            kind,
+            type_support,
+            unbounded_length: 0,
+        })
+    }
+    // Creates a `TypeSupport` describing the introspection event type of a service.
+    // It only carries the type name; events are serialized with `serialize_service_event`.
+    pub fn new_service_event_type_support(service_type_support: &TypeSupport) -> Self {
+        TypeSupport {
+            type_name: format!("{}_Event", service_type_support.type_name),
+            kind: KIND_FASTRTPS,
+            type_support: std::ptr::null(),
+            unbounded_length: 0,
         }
     }
     // Retrieves the fully qualified type name.
     fn get_type_name(
+        kind: rs_type_support_kind_t,
         type_support: *const ::std::os::raw::c_void,
         type_name_suffix: &str,
-    ) -> Result<String, ()> {
+    ) -> Result<String, Error> {
         // Get namespace and name
-        let message_namespace_c = unsafe { rs_get_message_namespace(type_support) };
-        let message_name_c = unsafe { rs_get_message_name(type_support) };
+        let message_namespace_c = unsafe { rs_get_message_namespace(kind, type_support) };
+        let message_name_c = unsafe { rs_get_message_name(kind, type_support) };
         if message_namespace_c.is_null() || message_name_c.is_null() {
-            return Err(());
+            return Err(Error::TypeSupport("missing type name".to_string()));
         }
-        let message_namespace = unsafe {
-            CStr::from_ptr(message_namespace_c)
-                .to_str()
-                .map_err(|_| ())?
-        };
-        let mut message_name = unsafe { CStr::from_ptr(message_name_c).to_str().map_err(|_| ())? };
+        let invalid_name =
+            |e: std::str::Utf8Error| Error::TypeSupport(format!("type name is not UTF-8: {e}"));
+        let message_namespace = unsafe { CStr::from_ptr(message_namespace_c) }
+            .to_str()
+            .map_err(invalid_name)?;
+        let mut message_name = unsafe { CStr::from_ptr(message_name_c) }
+            .to_str()
+            .map_err(invalid_name)?;
 
         // Remove suffix
         if message_name.ends_with(type_name_suffix) {
             message_name = &message_name[..message_name.len() - type_name_suffix.len()];
         } else {
-            return Err(());
+            return Err(Error::TypeSupport(format!(
+                "type {message_name} does not end with {type_name_suffix}"
+            )));
+        }
+        // FastRTPS and C++ introspection namespaces are `pkg::msg`, C introspection ones `pkg__msg`
+        let message_namespace = message_namespace.replace("::", "/").replace("__", "/");
+        Ok(format!("{message_namespace}/{message_name}"))
+    }
+    // Returns the largest serialized size of the type, including encapsulation,
+    // or None if it has unbounded strings or sequences without message bounds.
+    pub fn max_serialized_size(&self) -> Option<usize> {
+        if self.type_support.is_null() {
+            return None;
         }
-        Ok(format!("{message_namespace}/{message_name}").replace("::", "/"))
+        let mut is_bounded = false;
+        let size = unsafe {
+            rs_get_max_serialized_size(
+                self.kind,
+                self.type_support,
+                self.unbounded_length,
+                &mut is_bounded,
+            )
+        };
+        is_bounded.then_some(size)
     }
     // Serializes a ROS message into a serialized message buffer.
     pub fn serialize(
         &self,
         ros_message: *const ::std::os::raw::c_void,
         serialized_message: *mut rmw_serialized_message_t,
-    ) -> Result<(), ()> {
-        match unsafe { rs_serialize_message(self.type_support, ros_message, serialized_message) } {
+    ) -> Result<(), Error> {
+        if self.type_support.is_null() {
+            return Err(Error::TypeSupport(format!(
+                "{} cannot be serialized",
+                self.type_name
+            )));
+        }
+        match unsafe {
+            rs_serialize_message(
+                self.kind,
+                self.type_support,
+                ros_message,
+                serialized_message,
+            )
+        } {
             true => Ok(()),
-            false => Err(()),
+            false => Err(Error::Serialization(self.type_name.clone())),
         }
     }
     // Deserializes a serialized message buffer into a ROS message.
@@ -106,11 +222,72 @@ impl TypeSupport {
         &self,
         serialized_message: *const rmw_serialized_message_t,
         ros_message: *mut ::std::os::raw::c_void,
-    ) -> Result<(), ()> {
-        match unsafe { rs_deserialize_message(self.type_support, serialized_message, ros_message) }
-        {
+    ) -> Result<(), Error> {
+        if self.type_support.is_null() {
+            return Err(Error::TypeSupport(format!(
+                "{} cannot be deserialized",
+                self.type_name
+            )));
+        }
+        match unsafe {
+            rs_deserialize_message(
+                self.kind,
+                self.type_support,
+                serialized_message,
+                ros_message,
+            )
+        } {
+            true => Ok(()),
+            false => Err(Error::Serialization(self.type_name.clone())),
+        }
+    }
+    // Deserializes serialized data borrowed from a received payload into a ROS message.
+    pub fn deserialize_slice(
+        &self,
+        data: &[u8],
+        ros_message: *mut ::std::os::raw::c_void,
+    ) -> Result<(), Error> {
+        // The view only lends the data, so it has no allocator and is never resized
+        let serialized_message = rmw_serialized_message_t {
+            buffer: data.as_ptr() as *mut u8,
+            buffer_length: data.len(),
+            buffer_capacity: data.len(),
+            allocator: unsafe { rcutils_get_zero_initialized_allocator() },
+        };
+        self.deserialize(&serialized_message, ros_message)
+    }
+    // Serializes a service introspection event with the optional request and response.
+    pub fn serialize_service_event(
+        request: Option<(&TypeSupport, *const ::std::os::raw::c_void)>,
+        response: Option<(&TypeSupport, *const ::std::os::raw::c_void)>,
+        event_type: u8,
+        timestamp: i64,
+        client_gid: &[i8; RMW_GID_STORAGE_SIZE_IRON],
+        sequence_number: i64,
+        serialized_message: *mut rmw_serialized_message_t,
+    ) -> Result<(), Error> {
+        let none = (KIND_FASTRTPS, std::ptr::null(), std::ptr::null());
+        let (request_kind, request_callbacks, ros_request) =
+            request.map_or(none, |(ts, msg)| (ts.kind, ts.type_support, msg));
+        let (response_kind, response_callbacks, ros_response) =
+            response.map_or(none, |(ts, msg)| (ts.kind, ts.type_support, msg));
+        match unsafe {
+            rs_serialize_service_event(
+                request_kind,
+                request_callbacks,
+                ros_request,
+                response_kind,
+                response_callbacks,
+                ros_response,
+                event_type,
+                timestamp,
+                client_gid.as_ptr() as *const u8,
+                sequence_number,
+                serialized_message,
+            )
+        } {
             true => Ok(()),
-            false => Err(()),
+            false => Err(Error::Serialization("a service event".to_string())),
         }
     }
 }
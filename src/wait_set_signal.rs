@@ -0,0 +1,120 @@
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::Error;
+
+// WaitSetSignal struct: Wakes the threads blocked in rmw_wait when an entity of the context
+// becomes ready. The lock is only taken to notify while a thread waits, so that receiving
+// samples on the Zenoh RX threads does not contend on it while the executors are busy.
+#[derive(Default)]
+pub struct WaitSetSignal {
+    lock: Mutex<()>,
+    cvar: Condvar,
+    waiters: AtomicUsize,
+}
+
+impl WaitSetSignal {
+    // Creates a signal without waiting threads
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Wakes the waiting threads after an entity became ready through lock-free state.
+    // The fence pairs with the one of `wait_while`: either the waiter sees the new state
+    // when it checks its entities, or this sees the waiter and notifies it under the lock.
+    pub fn notify(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        drop(self.lock.lock());
+        self.cvar.notify_all();
+    }
+    // Makes an entity ready with `update` under the lock and wakes the waiting threads.
+    // Used for state that waiters only read while holding the lock.
+    pub fn notify_with(&self, update: impl FnOnce()) -> Result<(), Error> {
+        let _lock = self.lock.lock()?;
+        update();
+        self.cvar.notify_all();
+        Ok(())
+    }
+    // Blocks while `is_idle` returns true, at most for `timeout` or forever if it is None.
+    // Returns the lock, held while the caller collects the ready entities.
+    pub fn wait_while(
+        &self,
+        timeout: Option<Duration>,
+        mut is_idle: impl FnMut() -> bool,
+    ) -> Result<MutexGuard<'_, ()>, Error> {
+        self.waiters.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        let waited = (|| -> Result<_, Error> {
+            let lock = self.lock.lock()?;
+            Ok(match timeout {
+                None => self.cvar.wait_while(lock, |_| is_idle())?,
+                Some(timeout) if timeout.is_zero() => lock,
+                Some(timeout) => {
+                    self.cvar
+                        .wait_timeout_while(lock, timeout, |_| is_idle())?
+                        .0
+                }
+            })
+        })();
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+        waited
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn notify_without_waiters_does_not_lock() {
+        let signal = WaitSetSignal::new();
+        let _lock = signal.lock.lock().unwrap();
+        // Would deadlock if the lock were taken
+        signal.notify();
+    }
+
+    #[test]
+    fn notify_wakes_a_waiting_thread() {
+        let signal = Arc::new(WaitSetSignal::new());
+        let ready = Arc::new(AtomicBool::new(false));
+        let notifier = {
+            let (signal, ready) = (signal.clone(), ready.clone());
+            std::thread::spawn(move || {
+                while signal.waiters.load(Ordering::Relaxed) == 0 {
+                    std::thread::yield_now();
+                }
+                ready.store(true, Ordering::Relaxed);
+                signal.notify();
+            })
+        };
+        let start = Instant::now();
+        let _lock = signal
+            .wait_while(Some(Duration::from_secs(10)), || {
+                !ready.load(Ordering::Relaxed)
+            })
+            .unwrap();
+        assert!(ready.load(Ordering::Relaxed));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(signal.waiters.load(Ordering::Relaxed), 0);
+        notifier.join().unwrap();
+    }
+
+    #[test]
+    fn wait_times_out_while_idle() {
+        let signal = WaitSetSignal::new();
+        let start = Instant::now();
+        let lock = signal
+            .wait_while(Some(Duration::from_millis(20)), || true)
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        drop(lock);
+        // A zero timeout returns at once
+        let _lock = signal.wait_while(Some(Duration::ZERO), || true).unwrap();
+    }
+}